@@ -0,0 +1,142 @@
+use std::time::Duration;
+
+use crate::units::Units;
+use crate::utils::PointStats;
+
+const BLOCKS: [char; 8] = [' ', '▁', '▂', '▃', '▄', '▅', '▆', '▇'];
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+const HORIZONTAL_BLOCKS: [char; 8] = [' ', '▏', '▎', '▍', '▌', '▋', '▊', '▉'];
+
+// Horizontal bar of `value` out of `max`, `width` characters long when full.
+pub fn bar(value: f64, max: f64, width: usize) -> String {
+    if max <= 0. {
+        return String::new();
+    }
+
+    let eighths = (value / max * (width * 8) as f64).round() as usize;
+    let mut bar = "█".repeat(eighths / 8);
+    if !eighths.is_multiple_of(8) {
+        bar.push(HORIZONTAL_BLOCKS[eighths % 8]);
+    }
+    bar
+}
+
+pub fn elevation_profile(points: &[PointStats], width: usize, height: usize, units: Units) -> Vec<String> {
+    let elevations: Vec<(f64, f64)> = points.iter()
+        .filter_map(|point| Some((point.distance, point.elevation?)))
+        .collect();
+    let Some(&(total_distance, _)) = elevations.last() else {
+        return vec![];
+    };
+    if total_distance <= 0. || width < 2 || height == 0 {
+        return vec![];
+    }
+
+    let min = elevations.iter().map(|(_, elevation)| *elevation).fold(f64::MAX, f64::min);
+    let max = elevations.iter().map(|(_, elevation)| *elevation).fold(f64::MIN, f64::max);
+    let range = (max - min).max(1.);
+
+    // Highest elevation within each column, gaps filled with the previous column.
+    let mut columns: Vec<Option<f64>> = vec![None; width];
+    for (distance, elevation) in &elevations {
+        let column = (distance / total_distance * (width - 1) as f64).round() as usize;
+        columns[column] = Some(columns[column].map_or(*elevation, |highest| highest.max(*elevation)));
+    }
+    let mut previous = elevations[0].1;
+    let columns: Vec<f64> = columns.into_iter()
+        .map(|column| {
+            if let Some(elevation) = column {
+                previous = elevation;
+            }
+            previous
+        })
+        .collect();
+
+    let mut lines = vec![];
+    for row in (0..height).rev() {
+        let bars: String = columns.iter()
+            .map(|elevation| {
+                let level = 1. + (elevation - min) / range * (height - 1) as f64;
+                let fill = level - row as f64;
+                if fill >= 1. {
+                    '█'
+                }
+                else if fill > 0. {
+                    BLOCKS[(fill * 8.) as usize]
+                }
+                else {
+                    ' '
+                }
+            })
+            .collect();
+
+        let axis = if row == height - 1 {
+            format!("{:>9} ┤", units.format_elevation(max))
+        }
+        else if row == 0 {
+            format!("{:>9} ┤", units.format_elevation(min))
+        }
+        else {
+            format!("{:>10}│", "")
+        };
+        lines.push(format!("{axis}{bars}"));
+    }
+
+    lines.push(format!("{:>10}└{}", "", "─".repeat(width)));
+    let start_label = format!("0 {}", units.distance_unit());
+    let end_label = units.format_distance(total_distance, 1);
+    lines.push(format!("{:>11}{start_label}{:>pad$}", "", end_label, pad = width.saturating_sub(start_label.len())));
+
+    lines
+}
+
+// Modelled walking speed along the track as one line of blocks, `width` characters wide, with the
+// slowest and fastest speeds and where they are reached.
+pub fn speed_sparkline(points: &[PointStats], width: usize, units: Units) -> Vec<String> {
+    let Some(last) = points.last() else {
+        return vec![];
+    };
+    if last.distance <= 0. || width == 0 {
+        return vec![];
+    }
+
+    // Distance and walking time falling in each column, legs going by where they start.
+    let mut columns = vec![(0., Duration::ZERO); width];
+    for pair in points.windows(2) {
+        let column = ((pair[0].distance / last.distance * width as f64) as usize).min(width - 1);
+        columns[column].0 += pair[1].distance - pair[0].distance;
+        columns[column].1 += pair[1].duration;
+    }
+    let mut previous = None;
+    let speeds: Vec<Option<f64>> = columns.iter()
+        .map(|(km, duration)| {
+            if !duration.is_zero() {
+                previous = Some(units.distance(*km) / (duration.as_secs_f64() / 3600.));
+            }
+            previous
+        })
+        .collect();
+
+    let known = speeds.iter().enumerate().filter_map(|(column, speed)| Some((column, (*speed)?)));
+    let Some((slowest, min)) = known.clone().min_by(|a, b| a.1.total_cmp(&b.1)) else {
+        return vec![];
+    };
+    let (fastest, max) = known.max_by(|a, b| a.1.total_cmp(&b.1)).unwrap_or((slowest, min));
+    let range = (max - min).max(0.1);
+
+    let line: String = speeds.iter()
+        .map(|speed| match speed {
+            Some(speed) => SPARKS[(((speed - min) / range) * (SPARKS.len() - 1) as f64).round() as usize],
+            None => ' '
+        })
+        .collect();
+    let at = |column: usize| units.distance(last.distance * (column as f64 + 0.5) / width as f64);
+
+    vec![
+        format!("{:>10}│{line}", ""),
+        format!("{:>11}{:.1} {} @ {} {:.1}, {:.1} {} @ {} {:.1}", "",
+            min, units.speed_unit(), units.distance_unit(), at(slowest),
+            max, units.speed_unit(), units.distance_unit(), at(fastest)
+        )
+    ]
+}
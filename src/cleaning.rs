@@ -0,0 +1,457 @@
+use std::time::Duration;
+
+use geo_types::Point;
+use gpx::{Track, TrackSegment, Waypoint};
+use time::OffsetDateTime;
+
+use crate::utils::{approximate_distance, distance, DistanceAlgorithm};
+
+// A jump in the recording between point `index` - 1 and `index` of a segment.
+pub struct Gap {
+    pub segment: usize,
+    pub index: usize,
+    // Distance walked before the gap, in km.
+    pub at_km: f64,
+    pub distance_m: f64,
+    pub duration: Option<Duration>
+}
+
+pub fn find_gaps(track: &Track, max_distance_m: f64, max_duration: Duration) -> Vec<Gap> {
+    let mut gaps = Vec::new();
+    let mut walked_m = 0.;
+
+    for (segment_index, segment) in track.segments.iter().enumerate() {
+        for index in 1..segment.points.len() {
+            let (a, b) = (&segment.points[index - 1], &segment.points[index]);
+            let distance_m = distance_m(a, b);
+            let duration = match (a.time, b.time) {
+                (Some(time_a), Some(time_b)) => Some(OffsetDateTime::from(time_b) - OffsetDateTime::from(time_a))
+                    .filter(|duration| duration.is_positive())
+                    .map(|duration| Duration::from_secs_f64(duration.as_seconds_f64())),
+                _ => None
+            };
+
+            if distance_m > max_distance_m || duration.is_some_and(|duration| duration > max_duration) {
+                gaps.push(Gap { segment: segment_index, index, at_km: walked_m / 1000., distance_m, duration });
+            }
+            walked_m += distance_m;
+        }
+    }
+
+    gaps
+}
+
+// Starts a new segment after each gap so the jump isn't counted as walked.
+pub fn split_at_gaps(track: &mut Track, gaps: &[Gap]) {
+    let mut segments: Vec<TrackSegment> = Vec::new();
+
+    for (segment_index, segment) in track.segments.drain(..).enumerate() {
+        let mut start = 0;
+        for gap in gaps.iter().filter(|gap| gap.segment == segment_index) {
+            let mut part = TrackSegment::new();
+            part.points = segment.points[start..gap.index].to_vec();
+            segments.push(part);
+            start = gap.index;
+        }
+
+        let mut part = TrackSegment::new();
+        part.points = segment.points[start..].to_vec();
+        segments.push(part);
+    }
+
+    track.segments = segments;
+}
+
+// Cumulative distance (km) at each point, segment by segment, jumps between segments not counted.
+fn cumulative_distances(track: &Track, algorithm: DistanceAlgorithm) -> Vec<Vec<f64>> {
+    let mut walked = 0.;
+    track.segments.iter()
+        .map(|segment| {
+            (0..segment.points.len())
+                .map(|i| {
+                    if i > 0 {
+                        walked += distance(&segment.points[i - 1], &segment.points[i], algorithm);
+                    }
+                    walked
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// Distance along the track (km) of the point closest to `waypoint`.
+pub fn waypoint_km(track: &Track, waypoint: &Waypoint, algorithm: DistanceAlgorithm) -> Option<f64> {
+    let distances = cumulative_distances(track, algorithm);
+    track.segments.iter().zip(&distances)
+        .flat_map(|(segment, distances)| segment.points.iter().zip(distances))
+        .map(|(point, km)| (distance_m(point, waypoint), *km))
+        .min_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, km)| km)
+}
+
+// Keeps the part of the track between `from_km` and `to_km`, returns how many points were removed.
+pub fn crop(track: &mut Track, from_km: f64, to_km: f64, algorithm: DistanceAlgorithm) -> usize {
+    let distances = cumulative_distances(track, algorithm);
+    let mut removed = 0;
+
+    for (segment, distances) in track.segments.iter_mut().zip(distances) {
+        let before = segment.points.len();
+        let mut kilometers = distances.into_iter();
+        segment.points.retain(|_| kilometers.next().is_some_and(|km| km >= from_km && km <= to_km));
+        removed += before - segment.points.len();
+    }
+
+    // Emptied segments are kept so that segments still match their tracks.
+    removed
+}
+
+// Drops GPS fixes that can't have been walked: legs faster than `max_speed_kmh` when timestamped,
+// and isolated jumps away and back, horizontal or vertical. Returns how many points were removed.
+pub fn remove_outliers(track: &mut Track, max_speed_kmh: f64) -> usize {
+    let mut removed = 0;
+
+    for segment in &mut track.segments {
+        let mut kept: Vec<Waypoint> = Vec::with_capacity(segment.points.len());
+
+        for i in 0..segment.points.len() {
+            let (point, next) = (&segment.points[i], segment.points.get(i + 1));
+            let Some(previous) = kept.last() else {
+                kept.push(point.clone());
+                continue;
+            };
+
+            if is_too_fast(previous, point, max_speed_kmh) {
+                // A bad first fix makes every later point look too fast, it is the one dropped.
+                if kept.len() == 1 && next.is_some_and(|next| !is_too_fast(point, next, max_speed_kmh)) {
+                    kept[0] = point.clone();
+                }
+                removed += 1;
+            }
+            else if next.is_some_and(|next| is_spike(previous, point, next)) {
+                removed += 1;
+            }
+            else {
+                kept.push(point.clone());
+            }
+        }
+
+        segment.points = kept;
+    }
+
+    removed
+}
+
+fn is_too_fast(a: &Waypoint, b: &Waypoint, max_speed_kmh: f64) -> bool {
+    let (Some(time_a), Some(time_b)) = (a.time, b.time) else {
+        return false;
+    };
+    let seconds = (OffsetDateTime::from(time_b) - OffsetDateTime::from(time_a)).as_seconds_f64();
+    seconds > 0. && distance_m(a, b) / seconds * 3.6 > max_speed_kmh
+}
+
+fn is_spike(previous: &Waypoint, point: &Waypoint, next: &Waypoint) -> bool {
+    // Far from both neighbours, which are themselves close together.
+    let (to_point, from_point, across) = (distance_m(previous, point), distance_m(point, next), distance_m(previous, next));
+    if to_point > 200. && from_point > 200. && across < to_point.min(from_point) / 2. {
+        return true;
+    }
+
+    // Same for the elevation, steeper than anything walkable.
+    if let (Some(before), Some(elevation), Some(after)) = (previous.elevation, point.elevation, next.elevation) {
+        let (up, down) = (elevation - before, elevation - after);
+        return up.signum() == down.signum()
+            && up.abs().min(down.abs()) > 50.
+            && up.abs().min(down.abs()) > 2. * to_point.max(from_point)
+            && (before - after).abs() < up.abs().min(down.abs()) / 2.;
+    }
+
+    false
+}
+
+fn distance_m(a: &Waypoint, b: &Waypoint) -> f64 {
+    approximate_distance(a.point().y(), a.point().x(), b.point().y(), b.point().x())
+}
+
+// Douglas-Peucker on each segment, in meters on a local equirectangular projection with the
+// elevation as third axis so that summits and cols are kept. Returns how many points were removed.
+pub fn simplify(track: &mut Track, tolerance_m: f64) -> usize {
+    let mut removed = 0;
+
+    for segment in &mut track.segments {
+        if segment.points.len() < 3 {
+            continue;
+        }
+
+        let origin = segment.points[0].point();
+        let scale = origin.y().to_radians().cos();
+        let project = |waypoint: &Waypoint| -> [f64; 3] {
+            let point = waypoint.point();
+            [
+                (point.x() - origin.x()).to_radians() * scale * 6_371_000.,
+                (point.y() - origin.y()).to_radians() * 6_371_000.,
+                waypoint.elevation.unwrap_or(0.)
+            ]
+        };
+        let projected: Vec<[f64; 3]> = segment.points.iter().map(project).collect();
+
+        let mut keep = vec![false; projected.len()];
+        keep[0] = true;
+        keep[projected.len() - 1] = true;
+
+        let mut ranges = vec![(0, projected.len() - 1)];
+        while let Some((first, last)) = ranges.pop() {
+            let farthest = (first + 1..last)
+                .map(|i| (i, distance_to_line(projected[i], projected[first], projected[last])))
+                .max_by(|a, b| a.1.total_cmp(&b.1));
+
+            if let Some((i, distance)) = farthest && distance > tolerance_m {
+                keep[i] = true;
+                ranges.push((first, i));
+                ranges.push((i, last));
+            }
+        }
+
+        let before = segment.points.len();
+        let mut flags = keep.into_iter();
+        segment.points.retain(|_| flags.next().unwrap_or(true));
+        removed += before - segment.points.len();
+    }
+
+    removed
+}
+
+fn distance_to_line(point: [f64; 3], start: [f64; 3], end: [f64; 3]) -> f64 {
+    let direction = [end[0] - start[0], end[1] - start[1], end[2] - start[2]];
+    let offset = [point[0] - start[0], point[1] - start[1], point[2] - start[2]];
+    let length_squared = direction.iter().map(|value| value * value).sum::<f64>();
+
+    // Closest point of the segment, clamped to its ends.
+    let t = if length_squared > 0. {
+        (offset.iter().zip(&direction).map(|(a, b)| a * b).sum::<f64>() / length_squared).clamp(0., 1.)
+    }
+    else {
+        0.
+    };
+
+    offset.iter().zip(&direction)
+        .map(|(offset, direction)| (offset - t * direction).powi(2))
+        .sum::<f64>()
+        .sqrt()
+}
+
+// Replaces each segment by points every `spacing_m` along it (plus its last point), interpolating
+// position, elevation and time linearly. Returns the new number of points.
+pub fn resample(track: &mut Track, spacing_m: f64, algorithm: DistanceAlgorithm) -> usize {
+    let mut count = 0;
+
+    for segment in &mut track.segments {
+        let (Some(first), Some(last)) = (segment.points.first(), segment.points.last()) else {
+            continue;
+        };
+        let mut resampled = vec![first.clone()];
+        let mut next_at = spacing_m;
+        let mut walked = 0.;
+
+        for leg in segment.points.windows(2) {
+            let length = distance(&leg[0], &leg[1], algorithm) * 1000.;
+            while length > 0. && walked + length >= next_at {
+                resampled.push(interpolate(&leg[0], &leg[1], (next_at - walked) / length));
+                next_at += spacing_m;
+            }
+            walked += length;
+        }
+
+        // The end point is kept unless the last sample almost falls on it.
+        if walked - (next_at - spacing_m) > spacing_m / 10. {
+            resampled.push(last.clone());
+        }
+
+        count += resampled.len();
+        segment.points = resampled;
+    }
+
+    count
+}
+
+fn interpolate(a: &Waypoint, b: &Waypoint, ratio: f64) -> Waypoint {
+    let (point_a, point_b) = (a.point(), b.point());
+    let mut waypoint = Waypoint::new(Point::new(
+        point_a.x() + (point_b.x() - point_a.x()) * ratio,
+        point_a.y() + (point_b.y() - point_a.y()) * ratio
+    ));
+
+    waypoint.elevation = match (a.elevation, b.elevation) {
+        (Some(elevation_a), Some(elevation_b)) => Some(elevation_a + (elevation_b - elevation_a) * ratio),
+        (elevation_a, elevation_b) => elevation_a.or(elevation_b)
+    };
+    if let (Some(time_a), Some(time_b)) = (a.time, b.time) {
+        let (time_a, time_b) = (OffsetDateTime::from(time_a), OffsetDateTime::from(time_b));
+        waypoint.time = Some((time_a + (time_b - time_a) * ratio).into());
+    }
+
+    waypoint
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(longitude: f64, latitude: f64) -> Waypoint {
+        Waypoint::new(Point::new(longitude, latitude))
+    }
+
+    fn timed(longitude: f64, latitude: f64, seconds: i64) -> Waypoint {
+        let mut waypoint = point(longitude, latitude);
+        waypoint.time = Some(OffsetDateTime::from_unix_timestamp(seconds).unwrap().into());
+        waypoint
+    }
+
+    fn track(segments: Vec<Vec<Waypoint>>) -> Track {
+        let mut track = Track::new();
+        for points in segments {
+            let mut segment = TrackSegment::new();
+            segment.points = points;
+            track.segments.push(segment);
+        }
+        track
+    }
+
+    fn longitudes(track: &Track, segment: usize) -> Vec<f64> {
+        track.segments[segment].points.iter().map(|point| point.point().x()).collect()
+    }
+
+    #[test]
+    fn simplify_drops_points_on_a_straight_line() {
+        let mut track = track(vec![(0..5).map(|i| point(i as f64 * 0.001, 0.)).collect()]);
+        assert_eq!(simplify(&mut track, 1.), 3);
+        assert_eq!(longitudes(&track, 0), vec![0., 0.004]);
+    }
+
+    #[test]
+    fn simplify_keeps_points_off_the_line_by_more_than_the_tolerance() {
+        // The middle point is about 55 m off the line.
+        let points = vec![point(0., 0.), point(0.001, 0.0005), point(0.002, 0.)];
+        let mut kept = track(vec![points.clone()]);
+        assert_eq!(simplify(&mut kept, 10.), 0);
+
+        let mut dropped = track(vec![points]);
+        assert_eq!(simplify(&mut dropped, 100.), 1);
+    }
+
+    #[test]
+    fn simplify_keeps_summits() {
+        let mut summit = point(0.001, 0.);
+        summit.elevation = Some(100.);
+        let mut ends = (point(0., 0.), point(0.002, 0.));
+        (ends.0.elevation, ends.1.elevation) = (Some(0.), Some(0.));
+
+        let mut track = track(vec![vec![ends.0, summit, ends.1]]);
+        assert_eq!(simplify(&mut track, 10.), 0);
+    }
+
+    #[test]
+    fn simplify_leaves_short_and_empty_segments() {
+        let mut track = track(vec![vec![], vec![point(0., 0.)], vec![point(0., 0.), point(0.001, 0.)]]);
+        assert_eq!(simplify(&mut track, 1.), 0);
+        assert_eq!(track.segments.iter().map(|segment| segment.points.len()).collect::<Vec<_>>(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn resample_ends_on_a_sample_at_an_exact_multiple() {
+        let (start, end) = (timed(0., 0., 0), timed(0.01, 0., 1000));
+        let length = distance(&start, &end, DistanceAlgorithm::Haversine) * 1000.;
+        let mut track = track(vec![vec![start, end]]);
+
+        assert_eq!(resample(&mut track, length / 2., DistanceAlgorithm::Haversine), 3);
+        let points = &track.segments[0].points;
+        assert!((points[1].point().x() - 0.005).abs() < 1e-9);
+        assert!((points[2].point().x() - 0.01).abs() < 1e-9);
+        let middle = OffsetDateTime::from(points[1].time.unwrap());
+        assert_eq!(middle.unix_timestamp(), 500);
+    }
+
+    #[test]
+    fn resample_keeps_the_end_point_unless_a_sample_almost_falls_on_it() {
+        let (start, end) = (point(0., 0.), point(0.01, 0.));
+        let length = distance(&start, &end, DistanceAlgorithm::Haversine) * 1000.;
+
+        // Half a spacing left after the last sample: the end point is added.
+        let mut kept = track(vec![vec![start.clone(), end.clone()]]);
+        assert_eq!(resample(&mut kept, length / 3.5, DistanceAlgorithm::Haversine), 5);
+        assert_eq!(kept.segments[0].points.last().unwrap().point().x(), 0.01);
+
+        // 5 % of a spacing left: the last sample stands for the end.
+        let mut dropped = track(vec![vec![start, end]]);
+        assert_eq!(resample(&mut dropped, length / 4.05, DistanceAlgorithm::Haversine), 5);
+        assert!(dropped.segments[0].points.last().unwrap().point().x() < 0.01);
+    }
+
+    #[test]
+    fn resample_skips_empty_segments() {
+        let mut track = track(vec![vec![], vec![point(0., 0.)]]);
+        assert_eq!(resample(&mut track, 25., DistanceAlgorithm::Haversine), 1);
+        assert!(track.segments[0].points.is_empty());
+    }
+
+    #[test]
+    fn remove_outliers_drops_a_spike() {
+        // The third point is about 1.1 km north of its neighbours, which are 22 m apart.
+        let mut track = track(vec![vec![point(0., 0.), point(0.0001, 0.), point(0.0002, 0.01), point(0.0003, 0.), point(0.0004, 0.)]]);
+        assert_eq!(remove_outliers(&mut track, 30.), 1);
+        assert_eq!(longitudes(&track, 0), vec![0., 0.0001, 0.0003, 0.0004]);
+    }
+
+    #[test]
+    fn remove_outliers_drops_a_bad_first_fix() {
+        // 5.5 km away from the second point, 10 s before it.
+        let mut points = vec![timed(0.05, 0., 0)];
+        points.extend((0..5).map(|i| timed(i as f64 * 0.0001, 0., 10 + i * 10)));
+        let mut track = track(vec![points]);
+
+        assert_eq!(remove_outliers(&mut track, 30.), 1);
+        assert_eq!(track.segments[0].points.len(), 5);
+        assert_eq!(track.segments[0].points[0].point().x(), 0.);
+    }
+
+    #[test]
+    fn remove_outliers_drops_a_bad_last_fix() {
+        let mut points: Vec<Waypoint> = (0..5).map(|i| timed(i as f64 * 0.0001, 0., i * 10)).collect();
+        points.push(timed(0.05, 0., 50));
+        let mut track = track(vec![points]);
+
+        assert_eq!(remove_outliers(&mut track, 30.), 1);
+        assert_eq!(track.segments[0].points.len(), 5);
+    }
+
+    #[test]
+    fn remove_outliers_leaves_short_segments() {
+        let mut track = track(vec![vec![], vec![point(0., 0.)]]);
+        assert_eq!(remove_outliers(&mut track, 30.), 0);
+    }
+
+    #[test]
+    fn split_at_gaps_starts_a_segment_after_each_jump() {
+        let mut track = track(vec![vec![point(0., 0.), point(0.0001, 0.), point(0.02, 0.), point(0.0201, 0.)]]);
+        let gaps = find_gaps(&track, 500., Duration::from_secs(3600));
+        assert_eq!(gaps.len(), 1);
+        assert_eq!(gaps[0].index, 2);
+
+        split_at_gaps(&mut track, &gaps);
+        assert_eq!(longitudes(&track, 0), vec![0., 0.0001]);
+        assert_eq!(longitudes(&track, 1), vec![0.02, 0.0201]);
+    }
+
+    #[test]
+    fn crop_keeps_emptied_segments() {
+        // Points about 111 m apart, the second segment carrying on from the first.
+        let mut track = track(vec![
+            vec![point(0., 0.), point(0.001, 0.)],
+            vec![point(0.002, 0.), point(0.003, 0.), point(0.004, 0.)]
+        ]);
+
+        assert_eq!(crop(&mut track, 0.2, 1., DistanceAlgorithm::Haversine), 3);
+        assert_eq!(track.segments.len(), 2);
+        assert!(track.segments[0].points.is_empty());
+        assert_eq!(longitudes(&track, 1), vec![0.003, 0.004]);
+    }
+}
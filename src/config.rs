@@ -0,0 +1,213 @@
+use std::{env::home_dir, fs, path::PathBuf};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::i18n::Language;
+use crate::units::Units;
+use crate::Terrain;
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct Config {
+    pub gpx_dirs: Vec<PathBuf>,
+    pub search_depth: Option<usize>,
+    pub default_terrain: Option<String>,
+    pub split_length: Option<u32>,
+    pub units: Units,
+    // Interface language, from the locale when unset.
+    pub language: Option<Language>,
+    pub format: OutputFormat,
+    pub strava: Option<StravaConfig>
+}
+
+// Credentials of a Strava API application, see https://www.strava.com/settings/api.
+#[derive(Deserialize)]
+pub struct StravaConfig {
+    pub client_id: String,
+    pub client_secret: String,
+    pub refresh_token: String
+}
+
+#[derive(Deserialize, Default, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+    // key=value lines, as with --quiet.
+    Porcelain
+}
+
+impl Config {
+    // Configured directories with "~" expanded, ~/Documents when none are set.
+    pub fn gpx_dirs(&self) -> Vec<PathBuf> {
+        let home = home_dir();
+        if self.gpx_dirs.is_empty() {
+            return home.map(|home| vec![home.join("Documents")]).unwrap_or_default();
+        }
+
+        self.gpx_dirs.iter()
+            .map(|dir| match (dir.strip_prefix("~"), &home) {
+                (Ok(relative), Some(home)) => home.join(relative),
+                _ => dir.clone()
+            })
+            .collect()
+    }
+
+    // How many directory levels below each GPX directory are searched.
+    pub fn search_depth(&self) -> usize {
+        self.search_depth.unwrap_or(4)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SpeedProfile {
+    pub name: String,
+    pub road: f64,
+    pub path: f64,
+    pub track: f64,
+    pub alpine: f64,
+    // Pack carried when the adjustements were measured, a daypack when unset.
+    pub pack_weight: Option<f64>
+}
+
+impl Default for SpeedProfile {
+    fn default() -> Self {
+        Self {
+            name: String::from("default"),
+            road: 0.05,
+            path: 0.08,
+            track: 0.175,
+            alpine: 0.28,
+            pack_weight: None
+        }
+    }
+}
+
+impl SpeedProfile {
+    pub fn set_adjustement(&mut self, terrain: Terrain, adjustement: f64) {
+        match terrain {
+            Terrain::Road => self.road = adjustement,
+            Terrain::Path => self.path = adjustement,
+            Terrain::Track => self.track = adjustement,
+            Terrain::Alpine => self.alpine = adjustement,
+            Terrain::Unknown | Terrain::Formula => {}
+        }
+    }
+
+    pub fn adjustement(&self, terrain: Terrain) -> Option<f64> {
+        match terrain {
+            Terrain::Road => Some(self.road),
+            Terrain::Path => Some(self.path),
+            Terrain::Track => Some(self.track),
+            Terrain::Alpine => Some(self.alpine),
+            Terrain::Unknown | Terrain::Formula => None
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Profiles {
+    #[serde(default)]
+    pub profiles: Vec<SpeedProfile>
+}
+
+// Name of the profile written by --calibrate, preferred over the built-in constants.
+pub const CALIBRATED_PROFILE: &str = "calibrated";
+
+impl Profiles {
+    pub fn calibrated(&self) -> Option<&SpeedProfile> {
+        self.profiles.iter().find(|profile| profile.name == CALIBRATED_PROFILE)
+    }
+
+    // Replaces the profile of the same name, if any.
+    pub fn upsert(&mut self, profile: SpeedProfile) {
+        match self.profiles.iter_mut().find(|existing| existing.name == profile.name) {
+            Some(existing) => *existing = profile,
+            None => self.profiles.push(profile)
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct State {
+    pub last_analysis: Option<LastAnalysis>
+}
+
+// The last analysis, to run it again. Sources other than a local file (downloads, directories,
+// several files) are part of the options.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct LastAnalysis {
+    pub file: PathBuf,
+    pub is_gpx_file: bool,
+    // Selected tracks, from 0.
+    #[serde(default)]
+    pub tracks: Vec<usize>,
+    pub terrain: Option<Terrain>,
+    // Command line arguments, without the program name.
+    #[serde(default)]
+    pub options: Vec<String>
+}
+
+pub fn config_dir() -> Result<PathBuf> {
+    home_dir()
+        .map(|home| home.join(".config").join("mountain_snail"))
+        .ok_or(anyhow!("no home directory for the configuration"))
+}
+
+pub fn load_config() -> Config {
+    load("config.toml")
+}
+
+pub fn load_profiles() -> Profiles {
+    load("profiles.toml")
+}
+
+pub fn load_state() -> State {
+    load("state.toml")
+}
+
+// A missing file means defaults, an invalid one is reported and ignored.
+fn load<T: for<'de> Deserialize<'de> + Default>(file_name: &str) -> T {
+    let Ok(path) = config_dir().map(|dir| dir.join(file_name)) else {
+        return T::default();
+    };
+    let Ok(content) = fs::read_to_string(&path) else {
+        return T::default();
+    };
+
+    match toml::from_str(&content) {
+        Ok(value) => value,
+        Err(e) => {
+            eprintln!("{} {} {e}", console::style("Ignoring").yellow(), path.display());
+            T::default()
+        }
+    }
+}
+
+pub fn save_profiles(profiles: &Profiles) -> Result<PathBuf> {
+    save("profiles.toml", profiles)
+}
+
+pub fn save_state(state: &State) -> Result<PathBuf> {
+    save("state.toml", state)
+}
+
+// Standard input is gone once read, the last document is kept to analyse it again.
+pub fn save_stdin_copy(document: &[u8]) -> Result<PathBuf> {
+    let dir = config_dir()?;
+    fs::create_dir_all(&dir)?;
+    let path = dir.join("last_stdin.gpx");
+    fs::write(&path, document)?;
+    Ok(path)
+}
+
+fn save<T: Serialize>(file_name: &str, value: &T) -> Result<PathBuf> {
+    let dir = config_dir()?;
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(file_name);
+    fs::write(&path, toml::to_string_pretty(value)?)?;
+    Ok(path)
+}
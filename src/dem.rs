@@ -0,0 +1,109 @@
+use std::{collections::HashMap, env::home_dir, fs, io::Read, path::{Path, PathBuf}};
+
+use anyhow::{anyhow, Result};
+use flate2::read::GzDecoder;
+use gpx::Track;
+
+// Public SRTM tiles in .hgt format (AWS terrain tiles, "skadi" layout).
+const TILES_URL: &str = "https://elevation-tiles-prod.s3.amazonaws.com/skadi";
+
+struct Tile {
+    size: usize,
+    samples: Vec<i16>
+}
+
+impl Tile {
+    fn elevation(&self, lat: f64, lon: f64) -> Option<f64> {
+        let row = (1. - lat.rem_euclid(1.)) * (self.size - 1) as f64;
+        let column = lon.rem_euclid(1.) * (self.size - 1) as f64;
+        let (row_0, column_0) = (row.floor() as usize, column.floor() as usize);
+        let (row_1, column_1) = ((row_0 + 1).min(self.size - 1), (column_0 + 1).min(self.size - 1));
+
+        let sample = |row: usize, column: usize| -> Option<f64> {
+            match self.samples[row * self.size + column] {
+                i16::MIN => None,
+                value => Some(value as f64)
+            }
+        };
+
+        // Bilinear interpolation between the four surrounding samples.
+        let (dy, dx) = (row - row_0 as f64, column - column_0 as f64);
+        let top = sample(row_0, column_0)? * (1. - dx) + sample(row_0, column_1)? * dx;
+        let bottom = sample(row_1, column_0)? * (1. - dx) + sample(row_1, column_1)? * dx;
+        Some(top * (1. - dy) + bottom * dy)
+    }
+}
+
+// Fills missing elevations, returns how many points were filled.
+pub fn fill_elevations(track: &mut Track) -> Result<usize> {
+    let cache_dir = cache_dir()?;
+    fs::create_dir_all(&cache_dir)?;
+
+    let mut tiles: HashMap<String, Option<Tile>> = HashMap::new();
+    let mut filled = 0;
+
+    for segment in track.segments.iter_mut() {
+        for point in segment.points.iter_mut() {
+            if point.elevation.is_some() {
+                continue;
+            }
+
+            let (lat, lon) = (point.point().y(), point.point().x());
+            let name = tile_name(lat, lon);
+            if !tiles.contains_key(&name) {
+                let tile = load_tile(&cache_dir, &name)?;
+                tiles.insert(name.clone(), tile);
+            }
+
+            if let Some(Some(tile)) = tiles.get(&name) && let Some(elevation) = tile.elevation(lat, lon) {
+                point.elevation = Some(elevation);
+                filled += 1;
+            }
+        }
+    }
+
+    Ok(filled)
+}
+
+fn cache_dir() -> Result<PathBuf> {
+    home_dir()
+        .map(|home| home.join(".cache").join("mountain_snail").join("dem"))
+        .ok_or(anyhow!("no home directory for the DEM tile cache"))
+}
+
+fn tile_name(lat: f64, lon: f64) -> String {
+    let (lat, lon) = (lat.floor() as i32, lon.floor() as i32);
+    format!("{}{:02}{}{:03}",
+        if lat >= 0 { 'N' } else { 'S' }, lat.abs(),
+        if lon >= 0 { 'E' } else { 'W' }, lon.abs()
+    )
+}
+
+// Tiles are downloaded once into the cache, `None` when no tile exists (e.g. over the sea).
+fn load_tile(cache_dir: &Path, name: &str) -> Result<Option<Tile>> {
+    let path = cache_dir.join(format!("{name}.hgt"));
+
+    if !path.exists() {
+        let url = format!("{TILES_URL}/{}/{name}.hgt.gz", &name[..3]);
+        let response = match ureq::get(&url).call() {
+            Ok(response) => response,
+            Err(ureq::Error::Status(404, _)) | Err(ureq::Error::Status(403, _)) => return Ok(None),
+            Err(e) => return Err(e.into())
+        };
+
+        let mut bytes = vec![];
+        GzDecoder::new(response.into_reader()).read_to_end(&mut bytes)?;
+        fs::write(&path, &bytes)?;
+    }
+
+    let bytes = fs::read(&path)?;
+    let size = ((bytes.len() / 2) as f64).sqrt() as usize;
+    if size < 2 || size * size * 2 != bytes.len() {
+        return Err(anyhow!("{} is not a valid .hgt tile", path.display()));
+    }
+
+    let samples = bytes.chunks_exact(2)
+        .map(|pair| i16::from_be_bytes([pair[0], pair[1]]))
+        .collect();
+    Ok(Some(Tile { size, samples }))
+}
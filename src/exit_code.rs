@@ -0,0 +1,13 @@
+// Process exit codes, listed at the end of --help. 2 is clap's own for invalid arguments.
+pub const FAILURE: i32 = 1;
+pub const INPUT: i32 = 3;
+pub const NETWORK: i32 = 4;
+pub const NO_TERMINAL: i32 = 5;
+
+pub const HELP: &str = "Exit codes:
+  0  success
+  1  unexpected failure
+  2  invalid command line
+  3  unreadable or invalid input (file, directory, standard input)
+  4  download failed (Strava, Komoot)
+  5  no terminal to prompt on, or prompt interrupted (see --stdin and --terrain)";
@@ -0,0 +1,822 @@
+use std::{fs::File, io::{BufWriter, Write}, path::Path, time::Duration};
+
+use anyhow::Result;
+use clap::ValueEnum;
+use gpx::Gpx;
+
+use time::{format_description::well_known::Rfc3339, macros::format_description, OffsetDateTime, UtcOffset};
+use xml::{reader::XmlEvent as ReaderEvent, writer::XmlEvent as WriterEvent, EmitterConfig, EventWriter, ParserConfig};
+
+use crate::import::{point_key, PointExtensions, PointKey};
+use crate::utils::{format_clock, format_duration, PathStats, PointStats, Split, SplitStats, Splits};
+
+#[derive(Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum GpxOutputVersion {
+    #[value(name = "1.0")]
+    Gpx10,
+    #[default]
+    #[value(name = "1.1")]
+    Gpx11
+}
+
+// What the gpx crate can't write by itself.
+#[derive(Default)]
+pub struct GpxExtras<'a> {
+    pub version: GpxOutputVersion,
+    // Points of the analysed tracks by track index in the document, whose estimated elapsed time (s)
+    // and distance (km) go into their extensions.
+    pub etas: &'a [(usize, Vec<&'a PointStats>)],
+    // Extensions of the source points, written back as they were.
+    pub extensions: PointExtensions
+}
+
+const SNAIL_NAMESPACE: &str = "https://github.com/psyKomicron/mountain_snail";
+const GPX10_NAMESPACE: &str = "http://www.topografix.com/GPX/1/0";
+const GPX11_NAMESPACE: &str = "http://www.topografix.com/GPX/1/1";
+
+// Metadata children that GPX 1.0 has directly under <gpx>, the others (author, copyright, ...) are left out.
+const GPX10_METADATA: [&str; 5] = ["name", "desc", "time", "keywords", "bounds"];
+
+// The gpx crate only writes GPX 1.1 without extensions, so its output is rewritten event by event
+// when anything else is asked for. GPX 1.0 has no <metadata> nor <extensions> (their elements go
+// directly under <gpx> and the points) and a url/urlname pair instead of <link>.
+pub fn write_gpx(gpx: &Gpx, extras: &GpxExtras, path: &Path) -> Result<()> {
+    if extras.version == GpxOutputVersion::Gpx11 && extras.etas.is_empty() && extras.extensions.is_empty() {
+        gpx::write(gpx, BufWriter::new(File::create(path)?))?;
+        return Ok(());
+    }
+
+    let mut document = Vec::new();
+    gpx::write(gpx, &mut document)?;
+
+    let reader = ParserConfig::new().trim_whitespace(true).create_reader(document.as_slice());
+    let mut writer = EmitterConfig::new().perform_indent(true).create_writer(BufWriter::new(File::create(path)?));
+    let gpx10 = extras.version == GpxOutputVersion::Gpx10;
+    let (mut tracks, mut points): (usize, usize) = (0, 0);
+    let mut track_etas: Option<&Vec<&PointStats>> = None;
+    let mut point: Option<PointKey> = None;
+    // Names of the open elements, how deep inside an element left out we are, and whether the
+    // current run of <link> already gave the only url of GPX 1.0.
+    let mut parents: Vec<String> = vec![];
+    let mut skipped = 0;
+    let mut linked = false;
+
+    for event in reader {
+        let mut event = event?;
+        if skipped > 0 {
+            match event {
+                ReaderEvent::StartElement { .. } => skipped += 1,
+                ReaderEvent::EndElement { .. } => skipped -= 1,
+                _ => {}
+            }
+            continue;
+        }
+
+        match &mut event {
+            ReaderEvent::StartElement { name, attributes, namespace } => {
+                match name.local_name.as_str() {
+                    "trk" => {
+                        track_etas = extras.etas.iter().find(|(index, _)| *index == tracks).map(|(_, points)| points);
+                        (tracks, points) = (tracks + 1, 0);
+                    },
+                    "wpt" | "trkpt" | "rtept" => point = point_key(&name.local_name, attributes),
+                    _ => {}
+                }
+
+                if gpx10 {
+                    let parent = parents.last().map(String::as_str);
+                    let local_name = name.local_name.clone();
+                    let local_name = local_name.as_str();
+                    if (parent == Some("metadata") && !GPX10_METADATA.contains(&local_name))
+                        || (local_name == "link" && linked)
+                        || (parent == Some("link") && local_name != "text") {
+                        skipped = 1;
+                        continue;
+                    }
+                    linked = local_name == "link" || parent == Some("link");
+                    parents.push(name.local_name.clone());
+
+                    match local_name {
+                        "metadata" => continue,
+                        "link" => {
+                            let href = attributes.iter().find(|attribute| attribute.name.local_name == "href").map(|attribute| attribute.value.clone());
+                            if let Some(href) = href {
+                                writer.write(WriterEvent::start_element("url"))?;
+                                writer.write(WriterEvent::characters(&href))?;
+                                writer.write(WriterEvent::end_element())?;
+                            }
+                            continue;
+                        },
+                        "text" => name.local_name = String::from("urlname"),
+                        _ => {}
+                    }
+
+                    if name.namespace.as_deref() == Some(GPX11_NAMESPACE) {
+                        name.namespace = Some(String::from(GPX10_NAMESPACE));
+                    }
+                    for uri in namespace.0.values_mut().filter(|uri| *uri == GPX11_NAMESPACE) {
+                        *uri = String::from(GPX10_NAMESPACE);
+                    }
+                    for attribute in attributes.iter_mut() {
+                        attribute.value = if attribute.name.local_name == "version" { String::from("1.0") } else { attribute.value.replace("/GPX/1/1", "/GPX/1/0") };
+                    }
+                }
+                else {
+                    parents.push(name.local_name.clone());
+                }
+            },
+            ReaderEvent::EndElement { .. } => {
+                let element = parents.pop().unwrap_or_default();
+                if matches!(element.as_str(), "wpt" | "trkpt" | "rtept") {
+                    let eta = if element == "trkpt" { track_etas.and_then(|etas| etas.get(points)) } else { None };
+                    let source = point.take().and_then(|point| extras.extensions.get(&point));
+                    write_point_extensions(&mut writer, eta.copied(), source, !gpx10)?;
+                    if element == "trkpt" {
+                        points += 1;
+                    }
+                }
+
+                if !(gpx10 && matches!(element.as_str(), "metadata" | "link")) {
+                    writer.write(WriterEvent::end_element())?;
+                }
+                continue;
+            },
+            _ => {}
+        }
+
+        if let Some(event) = event.as_writer_event() {
+            writer.write(event)?;
+        }
+    }
+
+    writer.into_inner().flush()?;
+    Ok(())
+}
+
+// GPX 1.0 allows elements of other namespaces at the end of a point, without <extensions>.
+fn write_point_extensions<W: Write>(writer: &mut EventWriter<W>, eta: Option<&PointStats>, source: Option<&Vec<ReaderEvent>>, wrapped: bool) -> Result<()> {
+    if eta.is_none() && source.is_none() {
+        return Ok(());
+    }
+
+    if wrapped {
+        writer.write(WriterEvent::start_element("extensions"))?;
+    }
+    for event in source.into_iter().flatten().filter_map(|event| event.as_writer_event()) {
+        writer.write(event)?;
+    }
+    if let Some(point) = eta {
+        for (element, value) in [("snail:elapsed", point.elapsed.as_secs().to_string()), ("snail:distance", format!("{:.3}", point.distance))] {
+            writer.write(WriterEvent::start_element(element).ns("snail", SNAIL_NAMESPACE))?;
+            writer.write(WriterEvent::characters(&value))?;
+            writer.write(WriterEvent::end_element())?;
+        }
+    }
+    if wrapped {
+        writer.write(WriterEvent::end_element())?;
+    }
+    Ok(())
+}
+
+pub fn write_csv(stats: &PathStats, path: &Path) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "latitude,longitude,distance_km,elevation_m,grade_percent,split_time_s,cumulative_time_s,elapsed_time_s")?;
+
+    for point in &stats.points {
+        writeln!(writer, "{:.6},{:.6},{:.3},{},{:.1},{:.0},{:.0},{:.0}",
+            point.latitude,
+            point.longitude,
+            point.distance,
+            point.elevation.map(|elevation| format!("{elevation:.1}")).unwrap_or_default(),
+            point.grade * 100.,
+            point.duration.as_secs_f64(),
+            point.total_duration.as_secs_f64(),
+            point.elapsed.as_secs_f64()
+        )?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+// Splits file (JSON, or CSV by extension) from a track's splits, for the splits mode. Only a split
+// shorter than `split_length` (m), the last one, gets its own length.
+pub fn write_splits(splits: &[SplitStats], split_length: f64, path: &Path) -> Result<()> {
+    let splits: Vec<Split> = splits.iter()
+        .map(|split| Split {
+            d_plus: split.d_plus.round() as i32,
+            d_minus: split.d_minus.round() as i32,
+            length: ((split.distance * 1000. - split_length).abs() >= 1.).then(|| (split.distance * 1000.).round() as i32),
+            terrain: None,
+            adjustement: None
+        })
+        .collect();
+
+    save_splits(&Splits { splits }, path)
+}
+
+pub fn save_splits(splits: &Splits, path: &Path) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    if path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("csv")) {
+        write_csv_splits(&mut writer, &splits.splits)?;
+    }
+    else {
+        serde_json::to_writer_pretty(&mut writer, splits)?;
+        writeln!(writer)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn write_csv_splits<W: Write>(writer: &mut W, splits: &[Split]) -> Result<()> {
+    writeln!(writer, "d_plus,d_minus,length,terrain,adjustement")?;
+    for split in splits {
+        writeln!(writer, "{},{},{},{},{}",
+            split.d_plus,
+            split.d_minus,
+            split.length.map(|length| length.to_string()).unwrap_or_default(),
+            split.terrain.and_then(|terrain| terrain.to_possible_value()).map(|value| value.get_name().to_string()).unwrap_or_default(),
+            split.adjustement.map(|adjustement| adjustement.to_string()).unwrap_or_default()
+        )?;
+    }
+    Ok(())
+}
+
+pub fn write_markdown(stats: &PathStats, name: &str, start: Option<OffsetDateTime>, path: &Path) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    writeln!(writer, "# {name}\n")?;
+    writeln!(writer, "| Distance | D+ | D- | Altitude range | Walking time | With breaks |")?;
+    writeln!(writer, "|---:|---:|---:|---:|---:|---:|")?;
+    writeln!(writer, "| {:.2} km | {:.0} m | {:.0} m | {:.0} m - {:.0} m | {} | {} |\n",
+        stats.distance, stats.d_plus, stats.d_minus, stats.min_height, stats.max_height,
+        format_duration(stats.duration), format_duration(stats.duration + stats.break_duration))?;
+
+    writeln!(writer, "## Splits\n")?;
+    writeln!(writer, "| Km | D+ | D- | Time | ETA |")?;
+    writeln!(writer, "|---:|---:|---:|---:|---:|")?;
+    let mut km = 0.;
+    for split in &stats.splits {
+        km += split.distance;
+        writeln!(writer, "| {:.1} | {:.0} m | {:.0} m | {} | {} |",
+            km, split.d_plus, split.d_minus, format_duration(split.duration), format_duration(split.total_duration))?;
+    }
+
+    if !stats.checkpoints.is_empty() {
+        writeln!(writer, "\n## Checkpoints\n")?;
+        writeln!(writer, "| Checkpoint | Km | D+ | ETA |")?;
+        writeln!(writer, "|---|---:|---:|---:|")?;
+        for checkpoint in &stats.checkpoints {
+            let eta = match start {
+                Some(start) => format!("{} ({})", format_duration(checkpoint.eta), format_clock(start, checkpoint.eta)),
+                None => format_duration(checkpoint.eta)
+            };
+            writeln!(writer, "| {} | {:.2} | {:.0} m | {eta} |", checkpoint.name.replace('|', "\\|"), checkpoint.distance, checkpoint.d_plus)?;
+        }
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+// Per-point values follow the "coordinateProperties" convention of togeojson, one array entry per coordinate.
+pub fn write_geojson(stats: &PathStats, name: &str, path: &Path) -> Result<()> {
+    let coordinates: Vec<Vec<f64>> = stats.points.iter()
+        .map(|point| match point.elevation {
+            Some(elevation) => vec![point.longitude, point.latitude, elevation],
+            None => vec![point.longitude, point.latitude]
+        })
+        .collect();
+
+    let geojson = serde_json::json!({
+        "type": "FeatureCollection",
+        "features": [{
+            "type": "Feature",
+            "geometry": { "type": "LineString", "coordinates": coordinates },
+            "properties": {
+                "name": name,
+                "distance_km": stats.distance,
+                "d_plus_m": stats.d_plus,
+                "d_minus_m": stats.d_minus,
+                "duration_s": stats.duration.as_secs(),
+                "break_duration_s": stats.break_duration.as_secs(),
+                "coordinateProperties": {
+                    "distance_km": stats.points.iter().map(|point| point.distance).collect::<Vec<f64>>(),
+                    "elevation_m": stats.points.iter().map(|point| point.elevation).collect::<Vec<Option<f64>>>(),
+                    "grade_percent": stats.points.iter().map(|point| point.grade * 100.).collect::<Vec<f64>>(),
+                    "cumulative_time_s": stats.points.iter().map(|point| point.total_duration.as_secs()).collect::<Vec<u64>>(),
+                    "elapsed_time_s": stats.points.iter().map(|point| point.elapsed.as_secs()).collect::<Vec<u64>>()
+                }
+            }
+        }]
+    });
+
+    let writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer(writer, &geojson)?;
+    Ok(())
+}
+
+// The track plus a placemark every hour of walking and at each checkpoint, timestamped when the start is known.
+pub fn write_kml(stats: &PathStats, name: &str, start: Option<OffsetDateTime>, path: &Path) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(writer, r#"<kml xmlns="http://www.opengis.net/kml/2.2">"#)?;
+    writeln!(writer, "<Document>\n<name>{}</name>", escape_html(name))?;
+    writeln!(writer, r#"<Style id="track"><LineStyle><color>ff2b7d3b</color><width>3</width></LineStyle></Style>"#)?;
+
+    writeln!(writer, "<Placemark>\n<name>{}</name>\n<styleUrl>#track</styleUrl>", escape_html(name))?;
+    writeln!(writer, "<LineString>\n<tessellate>1</tessellate>\n<coordinates>")?;
+    for point in &stats.points {
+        writeln!(writer, "{:.6},{:.6},{:.1}", point.longitude, point.latitude, point.elevation.unwrap_or(0.))?;
+    }
+    writeln!(writer, "</coordinates>\n</LineString>\n</Placemark>")?;
+
+    let label = |elapsed: Duration| match start {
+        Some(start) => format_clock(start, elapsed),
+        None => format_duration(elapsed)
+    };
+
+    let mut hour = 1;
+    for point in &stats.points {
+        if point.elapsed.as_secs() >= hour * 3600 {
+            write_kml_placemark(&mut writer, &format!("{}h · {}", hour, label(point.elapsed)), point, start)?;
+            hour += 1;
+        }
+    }
+
+    for checkpoint in &stats.checkpoints {
+        // Checkpoints are snapped to the nearest track point, the first one at that distance.
+        if let Some(point) = stats.points.iter().find(|point| point.distance >= checkpoint.distance) {
+            write_kml_placemark(&mut writer, &format!("{} · {}", checkpoint.name, label(checkpoint.eta)), point, start)?;
+        }
+    }
+
+    writeln!(writer, "</Document>\n</kml>")?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_kml_placemark<W: Write>(writer: &mut W, name: &str, point: &PointStats, start: Option<OffsetDateTime>) -> Result<()> {
+    writeln!(writer, "<Placemark>\n<name>{}</name>", escape_html(name))?;
+    if let Some(start) = start {
+        writeln!(writer, "<TimeStamp><when>{}</when></TimeStamp>", (start + point.elapsed).format(&Rfc3339)?)?;
+    }
+    writeln!(writer, "<Point><coordinates>{:.6},{:.6},{:.1}</coordinates></Point>\n</Placemark>",
+        point.longitude, point.latitude, point.elevation.unwrap_or(0.))?;
+    Ok(())
+}
+
+const HTML_TEMPLATE: &str = r##"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>__NAME__</title>
+<style>
+    body { font-family: sans-serif; max-width: 960px; margin: 2em auto; color: #222; }
+    table { border-collapse: collapse; margin-bottom: 1.5em; }
+    th, td { padding: 4px 12px; border-bottom: 1px solid #ddd; text-align: right; }
+    #profile { width: 100%; height: auto; background: #fafafa; }
+    #readout { height: 1.5em; font-weight: bold; }
+</style>
+</head>
+<body>
+<h1>__NAME__</h1>
+<table>
+    <tr><th>Distance</th><th>D+</th><th>D-</th><th>Altitude range</th><th>Average altitude</th><th>Estimated time</th></tr>
+    __SUMMARY__
+</table>
+<div id="readout"></div>
+<svg id="profile" viewBox="0 0 900 300">
+    <path id="elevation" fill="#cde3c5" stroke="#3b7d2b" stroke-width="1.5"/>
+    <path id="time" fill="none" stroke="#2b5d9b" stroke-width="1" stroke-dasharray="4 3"/>
+    <line id="cursor" y1="20" y2="280" stroke="#c33" visibility="hidden"/>
+</svg>
+<h2>Splits</h2>
+<table>
+    <tr><th>Km</th><th>D+</th><th>D-</th><th>Time</th><th>ETA</th></tr>
+    __SPLITS__
+</table>
+<script>
+    // [km, elevation (m), cumulative time (s)]
+    const data = __DATA__;
+    const W = 900, H = 300, P = 40;
+    const svg = document.getElementById("profile");
+    const last = data.length ? data[data.length - 1] : [1, 0, 1];
+    const elevations = data.map(d => d[1]);
+    const minE = Math.min(...elevations), maxE = Math.max(...elevations);
+    const x = km => P + km / last[0] * (W - 2 * P);
+    const y = e => H - P - (e - minE) / Math.max(maxE - minE, 1) * (H - 2 * P);
+    const yt = t => H - P - t / Math.max(last[2], 1) * (H - 2 * P);
+    const hours = t => Math.floor(t / 3600) + "h" + String(Math.floor(t % 3600 / 60)).padStart(2, "0");
+
+    document.getElementById("elevation").setAttribute("d",
+        "M" + x(0) + "," + (H - P) + " L" + data.map(d => x(d[0]) + "," + y(d[1])).join(" L") + " L" + x(last[0]) + "," + (H - P) + " Z");
+    document.getElementById("time").setAttribute("d", "M" + data.map(d => x(d[0]) + "," + yt(d[2])).join(" L"));
+
+    const label = (text, lx, ly) => {
+        const element = document.createElementNS("http://www.w3.org/2000/svg", "text");
+        element.setAttribute("x", lx);
+        element.setAttribute("y", ly);
+        element.setAttribute("font-size", "11");
+        element.textContent = text;
+        svg.appendChild(element);
+    };
+    label(Math.round(maxE) + " m", 2, P);
+    label(Math.round(minE) + " m", 2, H - P);
+    label(last[0].toFixed(1) + " km", W - P - 20, H - P + 15);
+    label(hours(last[2]), W - P + 2, P);
+
+    const cursor = document.getElementById("cursor");
+    const readout = document.getElementById("readout");
+    svg.addEventListener("mousemove", event => {
+        const rect = svg.getBoundingClientRect();
+        const km = ((event.clientX - rect.left) * W / rect.width - P) / (W - 2 * P) * last[0];
+        let nearest = data[0];
+        for (const d of data) {
+            if (Math.abs(d[0] - km) < Math.abs(nearest[0] - km)) nearest = d;
+        }
+        if (!nearest) return;
+        cursor.setAttribute("x1", x(nearest[0]));
+        cursor.setAttribute("x2", x(nearest[0]));
+        cursor.setAttribute("visibility", "visible");
+        readout.textContent = nearest[0].toFixed(2) + " km · " + Math.round(nearest[1]) + " m · ETA " + hours(nearest[2]);
+    });
+</script>
+</body>
+</html>
+"##;
+
+// Part of the hike walked in one go, `from` and `to` being the elapsed times at its ends.
+struct IcsEvent<'a> {
+    summary: String,
+    first: Option<&'a PointStats>,
+    from_km: f64,
+    to_km: f64,
+    d_plus: f64,
+    from: Duration,
+    to: Duration
+}
+
+// One event for the hike, or one per stage on consecutive days, with the checkpoint ETAs in the description.
+pub fn write_ics(stats: &PathStats, name: &str, start: OffsetDateTime, path: &Path) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    let elapsed_at = |index: usize| stats.points.get(index).map(|point| point.elapsed).unwrap_or_default();
+
+    let events: Vec<IcsEvent> = if stats.stages.len() > 1 {
+        stats.stages.iter()
+            .map(|stage| IcsEvent {
+                summary: format!("{name} - {}", stage.name),
+                first: stats.points.get(stage.points.0),
+                from_km: stage.from_km,
+                to_km: stage.from_km + stage.stats.distance,
+                d_plus: stage.stats.d_plus,
+                from: elapsed_at(stage.points.0),
+                to: elapsed_at(stage.points.1)
+            })
+            .collect()
+    }
+    else {
+        vec![IcsEvent {
+            summary: name.to_string(),
+            first: stats.points.first(),
+            from_km: 0.,
+            to_km: stats.distance,
+            d_plus: stats.d_plus,
+            from: Duration::ZERO,
+            to: stats.duration + stats.break_duration
+        }]
+    };
+
+    write_ics_line(&mut writer, "BEGIN:VCALENDAR")?;
+    write_ics_line(&mut writer, "VERSION:2.0")?;
+    write_ics_line(&mut writer, "PRODID:-//mountain_snail//EN")?;
+    let stamp = ics_time(OffsetDateTime::now_utc())?;
+
+    for (day, IcsEvent { summary, first, from_km, to_km, d_plus, from, to }) in events.into_iter().enumerate() {
+        let day_start = start + time::Duration::days(day as i64);
+
+        let mut description = format!("{:.1} km, {:.0} m D+, {} walking with breaks", to_km - from_km, d_plus, format_duration(to - from));
+        for checkpoint in stats.checkpoints.iter().filter(|checkpoint| checkpoint.distance >= from_km && checkpoint.distance <= to_km) {
+            description.push_str(&format!("\n{} {} (km {:.1})", format_clock(day_start, checkpoint.eta.saturating_sub(from)), checkpoint.name, checkpoint.distance));
+        }
+
+        write_ics_line(&mut writer, "BEGIN:VEVENT")?;
+        write_ics_line(&mut writer, &format!("UID:{}-{}@mountain_snail", start.unix_timestamp(), day + 1))?;
+        write_ics_line(&mut writer, &format!("DTSTAMP:{stamp}"))?;
+        write_ics_line(&mut writer, &format!("DTSTART:{}", ics_time(day_start)?))?;
+        write_ics_line(&mut writer, &format!("DTEND:{}", ics_time(day_start + (to - from))?))?;
+        write_ics_line(&mut writer, &format!("SUMMARY:{}", escape_ics(&summary)))?;
+        write_ics_line(&mut writer, &format!("DESCRIPTION:{}", escape_ics(&description)))?;
+        if let Some(first) = first {
+            write_ics_line(&mut writer, &format!("GEO:{:.6};{:.6}", first.latitude, first.longitude))?;
+        }
+        write_ics_line(&mut writer, "END:VEVENT")?;
+    }
+
+    write_ics_line(&mut writer, "END:VCALENDAR")?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn ics_time(time: OffsetDateTime) -> Result<String> {
+    Ok(time.to_offset(UtcOffset::UTC).format(format_description!("[year][month][day]T[hour][minute][second]Z"))?)
+}
+
+fn escape_ics(text: &str) -> String {
+    text.replace('\\', "\\\\").replace(';', "\\;").replace(',', "\\,").replace('\n', "\\n")
+}
+
+// Lines end with CRLF and are folded at 75 bytes, continuations starting with a space.
+fn write_ics_line<W: Write>(writer: &mut W, line: &str) -> Result<()> {
+    let mut length = 0;
+    for character in line.chars() {
+        if length + character.len_utf8() > 75 {
+            write!(writer, "\r\n ")?;
+            length = 1;
+        }
+        write!(writer, "{character}")?;
+        length += character.len_utf8();
+    }
+    write!(writer, "\r\n")?;
+    Ok(())
+}
+
+pub fn write_html_report(stats: &PathStats, name: &str, path: &Path) -> Result<()> {
+    let step = (stats.points.len() / 1000).max(1);
+    let profile: Vec<(f64, f64, u64)> = stats.points.iter()
+        .step_by(step)
+        .filter_map(|point: &PointStats| Some(((point.distance * 1000.).round() / 1000., point.elevation?.round(), point.elapsed.as_secs())))
+        .collect();
+
+    let summary = format!("<tr><td>{:.2} km</td><td>{:.0} m</td><td>{:.0} m</td><td>{:.0} m - {:.0} m</td><td>{:.0} m</td><td>{}</td></tr>",
+        stats.distance, stats.d_plus, stats.d_minus, stats.min_height, stats.max_height, stats.average_altitude, format_duration(stats.duration + stats.break_duration));
+
+    let mut km = 0.;
+    let splits = stats.splits.iter()
+        .map(|split| {
+            km += split.distance;
+            format!("<tr><td>{:.1}</td><td>{:.0} m</td><td>{:.0} m</td><td>{}</td><td>{}</td></tr>",
+                km, split.d_plus, split.d_minus, format_duration(split.duration), format_duration(split.total_duration))
+        })
+        .collect::<Vec<String>>()
+        .join("\n    ");
+
+    let html = HTML_TEMPLATE
+        .replace("__NAME__", &escape_html(name))
+        .replace("__SUMMARY__", &summary)
+        .replace("__SPLITS__", &splits)
+        .replace("__DATA__", &serde_json::to_string(&profile)?);
+
+    std::fs::write(path, html)?;
+    Ok(())
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+// One key=value per line in fixed metric units (km, m, seconds) whatever --units says, for scripts.
+// Lists are numbered from 1 in the key, e.g. split_3_s.
+pub fn write_porcelain<W: Write>(writer: &mut W, stats: &PathStats, start: Option<OffsetDateTime>) -> Result<()> {
+    let single_line = |text: &str| text.replace(['\n', '\r'], " ");
+
+    writeln!(writer, "distance_km={:.3}", stats.distance)?;
+    writeln!(writer, "d_plus_m={:.0}", stats.d_plus)?;
+    writeln!(writer, "d_minus_m={:.0}", stats.d_minus)?;
+    if let Some(shape) = stats.shape {
+        writeln!(writer, "shape={}", shape.name())?;
+    }
+    writeln!(writer, "effort={:.1}", stats.effort)?;
+    writeln!(writer, "missing_elevations={}", stats.missing_elevations)?;
+    if let Some(sac) = stats.sac_estimate {
+        writeln!(writer, "sac_estimate=T{sac}")?;
+    }
+    writeln!(writer, "min_elevation_m={:.0}", stats.min_height)?;
+    writeln!(writer, "max_elevation_m={:.0}", stats.max_height)?;
+    writeln!(writer, "average_altitude_m={:.0}", stats.average_altitude)?;
+    writeln!(writer, "walking_s={}", stats.duration.as_secs())?;
+    writeln!(writer, "breaks_s={}", stats.break_duration.as_secs())?;
+    writeln!(writer, "uphill_s={}", stats.uphill_duration.as_secs())?;
+    writeln!(writer, "downhill_s={}", stats.downhill_duration.as_secs())?;
+    writeln!(writer, "grade_adjusted_pace_s_per_km={}", stats.grade_adjusted_pace.as_secs())?;
+    writeln!(writer, "climbing_s={}", stats.grade_zones.climbing.as_secs())?;
+    writeln!(writer, "flat_s={}", stats.grade_zones.flat.as_secs())?;
+    writeln!(writer, "descending_s={}", stats.grade_zones.descending.as_secs())?;
+    if let Some(ascent_rate) = stats.ascent_rate {
+        writeln!(writer, "ascent_rate_m_per_h={ascent_rate:.0}")?;
+    }
+    if let Some(vam) = stats.best_vam {
+        writeln!(writer, "best_vam_m_per_h={:.0}", vam.rate)?;
+        writeln!(writer, "best_vam_from_km={:.3}", vam.from_km)?;
+        writeln!(writer, "best_vam_to_km={:.3}", vam.to_km)?;
+    }
+    writeln!(writer, "total_s={}", (stats.duration + stats.break_duration).as_secs())?;
+    if let Some(start) = start {
+        writeln!(writer, "departure={}", start.format(&Rfc3339)?)?;
+        writeln!(writer, "arrival={}", (start + stats.duration + stats.break_duration).format(&Rfc3339)?)?;
+    }
+    if let Some(energy) = stats.energy {
+        writeln!(writer, "energy_kcal={energy:.0}")?;
+    }
+    if let Some(recorded) = stats.recorded {
+        writeln!(writer, "recorded_moving_s={}", recorded.moving.as_secs())?;
+        writeln!(writer, "recorded_elapsed_s={}", recorded.elapsed.as_secs())?;
+    }
+
+    for (i, checkpoint) in stats.checkpoints.iter().enumerate() {
+        writeln!(writer, "checkpoint_{}_name={}", i + 1, single_line(&checkpoint.name))?;
+        writeln!(writer, "checkpoint_{}_km={:.3}", i + 1, checkpoint.distance)?;
+        writeln!(writer, "checkpoint_{}_eta_s={}", i + 1, checkpoint.eta.as_secs())?;
+    }
+    for (i, bail_out) in stats.bail_outs.iter().enumerate() {
+        writeln!(writer, "bail_out_{}_name={}", i + 1, single_line(&bail_out.name))?;
+        writeln!(writer, "bail_out_{}_km={:.3}", i + 1, bail_out.distance)?;
+        writeln!(writer, "bail_out_{}_descent_m={:.0}", i + 1, bail_out.descent)?;
+        writeln!(writer, "bail_out_{}_saved_s={}", i + 1, bail_out.time_saved.as_secs())?;
+    }
+    for (i, stage) in stats.stages.iter().enumerate() {
+        writeln!(writer, "stage_{}_name={}", i + 1, single_line(&stage.name))?;
+        writeln!(writer, "stage_{}_km={:.3}", i + 1, stage.stats.distance)?;
+        writeln!(writer, "stage_{}_s={}", i + 1, stage.stats.duration.as_secs())?;
+    }
+    for position in &stats.hourly {
+        writeln!(writer, "hour_{}_km={:.3}", position.hour, position.distance)?;
+        writeln!(writer, "hour_{}_lat={:.6}", position.hour, position.latitude)?;
+        writeln!(writer, "hour_{}_lon={:.6}", position.hour, position.longitude)?;
+    }
+    for (i, split) in stats.splits.iter().enumerate() {
+        writeln!(writer, "split_{}_km={:.3}", i + 1, split.distance)?;
+        writeln!(writer, "split_{}_d_plus_m={:.0}", i + 1, split.d_plus)?;
+        writeln!(writer, "split_{}_s={}", i + 1, split.duration.as_secs())?;
+        writeln!(writer, "split_{}_grade_adjusted_pace_s_per_km={}", i + 1, split.grade_adjusted_pace.as_secs())?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use super::*;
+    use crate::utils::{distance_splits, Checkpoint};
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("mountain_snail_{}_{name}", std::process::id()))
+    }
+
+    fn point(distance: f64, elevation: f64, grade: f64, seconds: u64) -> PointStats {
+        PointStats {
+            latitude: 45.5,
+            longitude: 6.5 + distance / 100.,
+            elevation: Some(elevation),
+            distance,
+            d_plus: elevation - 1000.,
+            d_minus: 0.,
+            grade,
+            duration: Duration::ZERO,
+            total_duration: Duration::from_secs(seconds),
+            elapsed: Duration::from_secs(seconds)
+        }
+    }
+
+    fn stats() -> PathStats {
+        PathStats {
+            distance: 2.,
+            d_plus: 100.,
+            duration: Duration::from_secs(5400),
+            points: vec![point(0., 1000., 0., 0), point(1., 1100., 0.1, 3600), point(2., 1100., 0., 5400)],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn csv_has_one_line_per_point() {
+        let path = temp_path("points.csv");
+        write_csv(&stats(), &path).unwrap();
+        let csv = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("latitude,longitude,distance_km,elevation_m,grade_percent"));
+        assert_eq!(lines[2], "45.500000,6.510000,1.000,1100.0,10.0,0,3600,3600");
+    }
+
+    #[test]
+    fn markdown_lists_splits_and_escapes_checkpoint_names() {
+        let mut stats = stats();
+        stats.splits = distance_splits(&stats.points, 1.);
+        stats.checkpoints.push(Checkpoint { name: String::from("Col | Hut"), distance: 1., d_plus: 100., offset: 0., eta: Duration::from_secs(3600) });
+
+        let path = temp_path("report.md");
+        write_markdown(&stats, "Tour", OffsetDateTime::from_unix_timestamp(8 * 3600).ok(), &path).unwrap();
+        let markdown = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(markdown.starts_with("# Tour\n"));
+        assert!(markdown.contains("| 2.00 km | 100 m | 0 m |"));
+        assert!(markdown.contains("| 1.0 | 100 m | 0 m | 1h00 | 1h00 |\n| 2.0 | 0 m | 0 m | 0h30 | 1h30 |"));
+        assert!(markdown.contains("| Col \\| Hut | 1.00 | 100 m | 1h00 (09:00) |"));
+    }
+
+    #[test]
+    fn html_report_fills_the_template_and_escapes_the_name() {
+        let path = temp_path("report.html");
+        write_html_report(&stats(), "Dents <du> Midi", &path).unwrap();
+        let html = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(html.contains("Dents &lt;du&gt; Midi"));
+        assert!(html.contains("[[0.0,1000.0,0],[1.0,1100.0,3600],[2.0,1100.0,5400]]"));
+        assert!(!html.contains("__NAME__") && !html.contains("__DATA__"));
+    }
+
+    #[test]
+    fn kml_has_hourly_and_checkpoint_placemarks() {
+        let mut stats = stats();
+        stats.checkpoints.push(Checkpoint { name: String::from("Hut"), distance: 2., d_plus: 100., offset: 0., eta: Duration::from_secs(5400) });
+
+        let path = temp_path("route.kml");
+        write_kml(&stats, "Tour", OffsetDateTime::from_unix_timestamp(8 * 3600).ok(), &path).unwrap();
+        let kml = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(kml.matches("<Placemark>").count(), 3);
+        assert!(kml.contains("6.500000,45.500000,1000.0\n6.510000,45.500000,1100.0\n6.520000,45.500000,1100.0\n"));
+        assert!(kml.contains("<name>1h · 09:00</name>\n<TimeStamp><when>1970-01-01T09:00:00Z</when></TimeStamp>"));
+        assert!(kml.contains("<name>Hut · 09:30</name>"));
+    }
+
+    #[test]
+    fn geojson_carries_per_coordinate_properties() {
+        let path = temp_path("route.geojson");
+        write_geojson(&stats(), "Tour", &path).unwrap();
+        let geojson: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let feature = &geojson["features"][0];
+        assert_eq!(feature["geometry"]["coordinates"][1], serde_json::json!([6.51, 45.5, 1100.]));
+        assert_eq!(feature["properties"]["name"], "Tour");
+        assert_eq!(feature["properties"]["coordinateProperties"]["elapsed_time_s"], serde_json::json!([0, 3600, 5400]));
+    }
+
+    #[test]
+    fn porcelain_writes_one_key_per_line_on_single_lines() {
+        let mut stats = stats();
+        stats.checkpoints.push(Checkpoint { name: String::from("Col\nHut"), distance: 1., d_plus: 100., offset: 0., eta: Duration::from_secs(3600) });
+
+        let mut output = Vec::new();
+        write_porcelain(&mut output, &stats, OffsetDateTime::from_unix_timestamp(8 * 3600).ok()).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.lines().all(|line| line.contains('=')));
+        assert!(output.contains("distance_km=2.000\nd_plus_m=100\n"));
+        assert!(output.contains("arrival=1970-01-01T09:30:00Z\n"));
+        assert!(output.contains("checkpoint_1_name=Col Hut\ncheckpoint_1_km=1.000\ncheckpoint_1_eta_s=3600\n"));
+    }
+
+    #[test]
+    fn ics_lines_are_folded_at_75_bytes() {
+        let mut output = Vec::new();
+        write_ics_line(&mut output, &format!("SUMMARY:{}", "é".repeat(40))).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.ends_with("\r\n"));
+        let lines: Vec<&str> = output.trim_end().split("\r\n").collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines.iter().all(|line| line.len() <= 75));
+        assert!(lines[1].starts_with(' '));
+    }
+
+    #[test]
+    fn ics_has_one_event_with_escaped_text() {
+        let path = temp_path("hike.ics");
+        write_ics(&stats(), "Tour, day one", OffsetDateTime::from_unix_timestamp(8 * 3600).unwrap(), &path).unwrap();
+        let ics = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(ics.matches("BEGIN:VEVENT").count(), 1);
+        assert!(ics.contains("DTSTART:19700101T080000Z\r\nDTEND:19700101T093000Z\r\n"));
+        assert!(ics.contains("SUMMARY:Tour\\, day one\r\n"));
+        assert!(ics.contains("GEO:45.500000;6.500000\r\n"));
+    }
+
+    #[test]
+    fn written_splits_only_give_the_short_last_one_a_length() {
+        let mut stats = stats();
+        stats.points.push(point(2.4, 1100., 0., 6000));
+        let path = temp_path("splits.json");
+        write_splits(&distance_splits(&stats.points, 1.), 1000., &path).unwrap();
+        let splits: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(splits["splits"], serde_json::json!([
+            { "d_plus": 100, "d_minus": 0 },
+            { "d_plus": 0, "d_minus": 0 },
+            { "d_plus": 0, "d_minus": 0, "length": 400 }
+        ]));
+    }
+}
@@ -0,0 +1,53 @@
+use std::{fs::{self, OpenOptions}, io::Write, path::PathBuf};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::config::config_dir;
+use crate::Terrain;
+
+// One analysis per line of history.jsonl, next to the configuration.
+#[derive(Serialize, Deserialize)]
+pub struct HistoryEntry {
+    // RFC 3339, local time.
+    pub date: String,
+    pub file: PathBuf,
+    pub track: String,
+    // Analysed tracks, from 0.
+    #[serde(default)]
+    pub tracks: Vec<usize>,
+    pub terrain: Option<Terrain>,
+    // Command line arguments, without the program name.
+    #[serde(default)]
+    pub options: Vec<String>,
+    pub distance: f64,
+    pub d_plus: f64,
+    pub d_minus: f64,
+    pub walking_s: u64,
+    pub breaks_s: u64
+}
+
+pub fn record(entry: &HistoryEntry) -> Result<PathBuf> {
+    let dir = config_dir()?;
+    fs::create_dir_all(&dir)?;
+    let path = dir.join("history.jsonl");
+    let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+    writeln!(file, "{}", serde_json::to_string(entry)?)?;
+    Ok(path)
+}
+
+// Oldest first, no history meaning none. Lines that can't be read are skipped.
+pub fn load() -> Result<Vec<HistoryEntry>> {
+    let path = config_dir()?.join("history.jsonl");
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+
+    let content = fs::read_to_string(&path)?;
+    Ok(content.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .filter_map(|(i, line)| serde_json::from_str(line).inspect_err(|e| warn!("{} line {}: {e}", path.display(), i + 1)).ok())
+        .collect())
+}
@@ -0,0 +1,308 @@
+use std::{env, sync::OnceLock};
+
+use serde::Deserialize;
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    #[default]
+    English,
+    French
+}
+
+static LANGUAGE: OnceLock<Language> = OnceLock::new();
+
+// The configured language wins, then the locale environment variables.
+pub fn set_language(language: Option<Language>) {
+    let language = language.unwrap_or_else(|| {
+        let locale = ["LC_ALL", "LC_MESSAGES", "LANG"].iter()
+            .find_map(|name| env::var(name).ok().filter(|value| !value.is_empty()))
+            .unwrap_or_default();
+
+        if locale.starts_with("fr") { Language::French } else { Language::English }
+    });
+    let _ = LANGUAGE.set(language);
+}
+
+// Interface strings are looked up by their English text, untranslated ones are shown as is.
+pub fn tr(text: &str) -> &str {
+    translate(LANGUAGE.get().copied().unwrap_or_default(), text)
+}
+
+fn translate(language: Language, text: &str) -> &str {
+    let translations = match language {
+        Language::English => return text,
+        Language::French => FRENCH
+    };
+
+    translations.iter()
+        .find(|(english, _)| *english == text)
+        .map(|(_, translation)| *translation)
+        .unwrap_or(text)
+}
+
+const FRENCH: &[(&str, &str)] = &[
+    // Terrains
+    ("road", "route"),
+    ("path", "sentier"),
+    ("track", "piste"),
+    ("alpine", "alpin"),
+    ("manual", "manuel"),
+    ("custom formula", "formule personnalisée"),
+    ("new profile...", "nouveau profil..."),
+    // Conditions
+    ("dry", "sec"),
+    ("wet", "mouillé"),
+    ("snow, trail broken", "neige, trace faite"),
+    ("deep snow", "neige profonde"),
+    ("same as the trip", "comme le reste de la sortie"),
+    // Splits editor
+    ("add a split", "ajouter un intervalle"),
+    ("save and quit", "enregistrer et quitter"),
+    ("quit without saving", "quitter sans enregistrer"),
+    ("modify", "modifier"),
+    ("insert before", "insérer avant"),
+    ("remove", "supprimer"),
+    ("back", "retour"),
+    ("Split", "Intervalle"),
+    ("Length", "Longueur"),
+    ("empty for the split length", "vide pour la longueur des intervalles"),
+    ("Split length", "Longueur des intervalles"),
+    ("Conditions", "Conditions"),
+    ("profile", "profil"),
+    // Prompts
+    ("Type", "Type"),
+    ("Choose file (type to filter)", "Choisir un fichier (taper pour filtrer)"),
+    ("Terrain", "Terrain"),
+    ("Select GPX track(s)", "Choisir le(s) tracé(s) GPX"),
+    ("Add time to GPX points ?", "Ajouter l'heure aux points GPX ?"),
+    ("Detect terrain from OpenStreetMap ?", "Détecter le terrain depuis OpenStreetMap ?"),
+    ("Assign a different terrain to sections of the route ?", "Attribuer un autre terrain à des sections du parcours ?"),
+    ("Add another section ?", "Ajouter une autre section ?"),
+    ("Section start", "Début de la section"),
+    ("Section end", "Fin de la section"),
+    ("Walking speed adjustement (bigger == slower):", "Ajustement de la vitesse de marche (plus grand == plus lent) :"),
+    ("Speed formula in km/h (variables: grade, distance, altitude):", "Formule de vitesse en km/h (variables : grade, distance, altitude) :"),
+    ("Profile name:", "Nom du profil :"),
+    ("Body weight (kg):", "Poids du corps (kg) :"),
+    ("Pack weight (kg):", "Poids du sac (kg) :"),
+    ("Pack weight carried on the recordings (kg):", "Poids du sac porté sur les enregistrements (kg) :"),
+    ("Stage ends", "Fins d'étape"),
+    ("comma separated", "séparées par des virgules"),
+    ("Write one GPX file per stage ?", "Écrire un fichier GPX par étape ?"),
+    ("Timed GPX output path:", "Chemin du GPX horodaté :"),
+    ("Track file path (GPX, TCX, FIT, KML/KMZ):", "Chemin du fichier de tracé (GPX, TCX, FIT, KML/KMZ) :"),
+    ("Splits file path (JSON, CSV):", "Chemin du fichier d'intervalles (JSON, CSV) :"),
+    ("Path doesn't exist", "Ce chemin n'existe pas"),
+    ("GPX name:", "Nom du GPX :"),
+    ("GPX description:", "Description du GPX :"),
+    ("GPX creator:", "Créateur du GPX :"),
+    ("Planned with mountain_snail, ETA", "Prévu avec mountain_snail, durée estimée"),
+    ("speed adjustement (bigger == slower):", "ajustement de la vitesse (plus grand == plus lent) :"),
+    ("Section end must be after its start", "La fin de la section doit être après son début"),
+    ("Save as the profile", "Enregistrer comme profil"),
+    ("used by default", "utilisé par défaut"),
+    ("Stage", "Étape"),
+    // Progress and diagnostics
+    ("Mountain snail - Hiking time calculator.", "Mountain snail - Calculateur de temps de randonnée."),
+    ("Chosen track:", "Tracé choisi :"),
+    ("track n°", "tracé n°"),
+    ("Default", "Sans nom"),
+    ("Downloading from Strava...", "Téléchargement depuis Strava..."),
+    ("Downloading from Komoot...", "Téléchargement depuis Komoot..."),
+    ("Looking up elevations...", "Recherche des altitudes..."),
+    ("point elevation(s) filled", "altitude(s) de point complétée(s)"),
+    ("Querying OpenStreetMap...", "Interrogation d'OpenStreetMap..."),
+    ("Track has no elevation data, slopes will be ignored (use --dem to fill them in).", "Le tracé n'a pas d'altitudes, les pentes seront ignorées (--dem pour les compléter)."),
+    ("Outliers:", "Points aberrants :"),
+    ("point(s) removed", "point(s) supprimé(s)"),
+    ("Cropped to km", "Réduit aux km"),
+    ("end", "fin"),
+    ("Nothing left of the track after cropping.", "Il ne reste rien du tracé après la réduction."),
+    ("Recording gap at km", "Interruption de l'enregistrement au km"),
+    ("in", "en"),
+    ("Track split at", "Tracé coupé à"),
+    ("gap(s), the jumps are not counted", "interruption(s), les sauts ne sont pas comptés"),
+    ("Simplified:", "Simplifié :"),
+    ("Resampled every", "Rééchantillonné tous les"),
+    ("point(s) instead of", "point(s) au lieu de"),
+    ("Tracks ignored:", "Tracés ignorés :"),
+    ("tracks in the file:", "tracés dans le fichier :"),
+    ("Crop ignored:", "Réduction ignorée :"),
+    ("Bail-out ignored:", "Échappatoire ignorée :"),
+    ("no waypoint named", "aucun point nommé"),
+    ("Timezone ignored:", "Fuseau horaire ignoré :"),
+    ("no timezone found at the start, clock times stay local", "aucun fuseau trouvé au départ, les heures restent locales"),
+    ("Invalid GPX, reading what can be:", "GPX invalide, lecture de ce qui peut l'être :"),
+    ("Left out:", "Laissés de côté :"),
+    ("points without valid coordinates", "points sans coordonnées valides"),
+    ("empty segments", "segments vides"),
+    ("unreadable times or elevations", "heures ou altitudes illisibles"),
+    ("Stopped at:", "Arrêté à :"),
+    ("the rest of the file is ignored", "la suite du fichier est ignorée"),
+    ("Skipping", "Ignoré :"),
+    ("no track", "aucun tracé"),
+    ("no timestamps", "aucun horodatage"),
+    ("only GPX files can be streamed", "seuls les fichiers GPX peuvent être lus au fil de l'eau"),
+    ("Implausible:", "Invraisemblable :"),
+    ("over", "sur"),
+    ("Watching", "Surveillance de"),
+    ("for changes (Ctrl+C to stop)...", "(Ctrl+C pour arrêter)..."),
+    ("Calibrated speed adjustements:", "Ajustements de vitesse calibrés :"),
+    ("recording(s)", "enregistrement(s)"),
+    ("No timestamped recording to calibrate from.", "Aucun enregistrement horodaté pour calibrer."),
+    ("Profile saved to", "Profil enregistré dans"),
+    // Errors
+    ("Error reading", "Erreur de lecture de"),
+    ("Error reading GPX file:", "Erreur de lecture du fichier GPX :"),
+    ("Error reading GPX from stdin:", "Erreur de lecture du GPX sur l'entrée standard :"),
+    ("Error reading splits file:", "Erreur de lecture du fichier d'intervalles :"),
+    ("Cannot read", "Lecture impossible de"),
+    ("Cannot read the history:", "Lecture de l'historique impossible :"),
+    ("Cannot prompt:", "Impossible de poser la question :"),
+    ("Strava download failed:", "Échec du téléchargement Strava :"),
+    ("Komoot download failed:", "Échec du téléchargement Komoot :"),
+    ("Elevation lookup failed:", "Échec de la recherche des altitudes :"),
+    ("Terrain detection failed:", "Échec de la détection du terrain :"),
+    ("Weather forecast unavailable:", "Prévisions météo indisponibles :"),
+    ("Extensions not kept:", "Extensions non conservées :"),
+    ("TUI failed:", "Échec de l'interface plein écran :"),
+    ("Server failed:", "Échec du serveur :"),
+    ("No completion support for", "Pas de complétion pour"),
+    ("Failed to write the completion script:", "Échec de l'écriture du script de complétion :"),
+    ("Failed to print the results:", "Échec de l'affichage des résultats :"),
+    ("Last analysis not saved:", "Dernière analyse non enregistrée :"),
+    ("Analysis not added to the history:", "Analyse non ajoutée à l'historique :"),
+    ("Profile not saved:", "Profil non enregistré :"),
+    // Written files
+    ("CSV written to", "CSV écrit dans"),
+    ("Splits written to", "Intervalles écrits dans"),
+    ("Markdown report written to", "Rapport Markdown écrit dans"),
+    ("KML written to", "KML écrit dans"),
+    ("Calendar written to", "Calendrier écrit dans"),
+    ("GeoJSON written to", "GeoJSON écrit dans"),
+    ("Profile written to", "Profil écrit dans"),
+    ("HTML report written to", "Rapport HTML écrit dans"),
+    ("Merged GPX written to", "GPX fusionné écrit dans"),
+    ("Timed GPX written to", "GPX horodaté écrit dans"),
+    ("Stage GPX written to", "GPX d'étape écrit dans"),
+    ("Failed to write CSV:", "Échec de l'écriture du CSV :"),
+    ("Failed to write splits:", "Échec de l'écriture des intervalles :"),
+    ("Failed to write Markdown report:", "Échec de l'écriture du rapport Markdown :"),
+    ("Failed to write KML:", "Échec de l'écriture du KML :"),
+    ("Failed to write calendar:", "Échec de l'écriture du calendrier :"),
+    ("Failed to write GeoJSON:", "Échec de l'écriture du GeoJSON :"),
+    ("Failed to draw profile:", "Échec du tracé du profil :"),
+    ("Failed to write HTML report:", "Échec de l'écriture du rapport HTML :"),
+    ("Failed to write merged GPX:", "Échec de l'écriture du GPX fusionné :"),
+    ("Failed to write timed GPX:", "Échec de l'écriture du GPX horodaté :"),
+    ("Failed to write stage GPX:", "Échec de l'écriture du GPX d'étape :"),
+    // Report
+    ("Repeat last analysis", "Refaire la dernière analyse"),
+    ("No analysis recorded yet.", "Aucune analyse enregistrée pour l'instant."),
+    ("Run an analysis again (Esc to quit)", "Refaire une analyse (Échap pour quitter)"),
+    ("Track", "Tracé"),
+    ("Track info:", "Informations du tracé :"),
+    ("points", "points"),
+    ("to", "à"),
+    // Batch and compare tables, translations no wider than the columns
+    ("Name", "Nom"),
+    ("Distance", "Distance"),
+    ("w/ breaks", "+ pauses"),
+    ("Effort", "Effort"),
+    ("Moving", "Marche"),
+    ("Elapsed", "Total"),
+    ("Combined total:", "Total cumulé :"),
+    ("Range", "Altitudes"),
+    ("Time", "Durée"),
+    ("walking", "de marche"),
+    ("with breaks", "avec les pauses"),
+    ("Night penalty", "Pénalité de nuit"),
+    ("Heat penalty", "Pénalité de chaleur"),
+    ("Missing elevation", "Altitude manquante"),
+    ("interpolated", "interpolée"),
+    ("of the points have no elevation, grades and times are unreliable", "des points n'ont pas d'altitude, pentes et durées sont peu fiables"),
+    ("Uphill", "Montée"),
+    ("Grade-adjusted pace", "Allure corrigée de la pente"),
+    ("Climbing", "Montée"),
+    ("flat", "plat"),
+    ("descending", "descente"),
+    ("Ascent rate", "Vitesse ascensionnelle"),
+    ("Best VAM", "Meilleure VAM"),
+    ("Bail-out points:", "Échappatoires :"),
+    ("loop", "boucle"),
+    ("out-and-back", "aller-retour"),
+    ("point-to-point", "traversée"),
+    ("The finish is away from the start, plan the return transport.", "L'arrivée est loin du départ, prévoyez le retour."),
+    ("down", "de descente"),
+    ("saved", "gagnées"),
+    ("downhill", "descente"),
+    ("Departure", "Départ"),
+    ("Arrival", "Arrivée"),
+    ("Group:", "Groupe :"),
+    ("(slowest, used for the plan)", "(le plus lent, utilisé pour le plan)"),
+    ("Recorded", "Enregistré"),
+    ("moving", "en mouvement"),
+    ("elapsed", "au total"),
+    ("predicted", "prévu"),
+    ("Energy", "Énergie"),
+    ("Weather at the highest point", "Météo au point culminant"),
+    ("Heat/rain penalty", "Pénalité chaleur/pluie"),
+    ("Provisions", "Provisions"),
+    ("Sunrise", "Lever du soleil"),
+    ("Sunset", "Coucher du soleil"),
+    ("of walking in the dark, bring a headlamp.", "de marche de nuit, prenez une frontale."),
+    ("No sunrise or sunset on that day (polar day or night).", "Pas de lever ni de coucher du soleil ce jour-là (jour ou nuit polaire)."),
+    ("Average altitude", "Altitude moyenne"),
+    ("median", "médiane"),
+    ("quartiles", "quartiles"),
+    ("Grades:", "Pentes :"),
+    ("Steepest climb", "Montée la plus raide"),
+    ("Difficulty", "Difficulté"),
+    ("Effort score", "Indice d'effort"),
+    ("Speed profile:", "Profil de vitesse :"),
+    ("(estimate only, from slopes and altitude)", "(estimation seulement, d'après les pentes et l'altitude)"),
+    ("Steepest descent", "Descente la plus raide"),
+    ("Stages:", "Étapes :"),
+    ("Climbs:", "Montées :"),
+    ("Segments:", "Segments :"),
+    ("Splits", "Intervalles"),
+    ("Checkpoints:", "Points de passage :"),
+    ("off track", "hors tracé"),
+    ("Hourly positions:", "Positions heure par heure :"),
+    ("at", "à"),
+    ("before", "avant"),
+    ("after", "après"),
+    ("Round trip:", "Aller-retour :"),
+    ("Outbound", "Aller"),
+    ("Return", "Retour"),
+    ("Total", "Total"),
+    ("Reverse direction:", "Sens inverse :"),
+    ("Elevation profile:", "Profil altimétrique :"),
+    // Splits mode
+    ("split(s) found.", "intervalle(s) trouvé(s)."),
+    ("Path info:", "Parcours :"),
+    ("Splits:", "Intervalles :"),
+    ("Total time:", "Durée totale :")
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn translates_known_strings_only_in_french() {
+        let (english, french) = FRENCH[0];
+        assert_eq!(translate(Language::French, english), french);
+        assert_eq!(translate(Language::English, english), english);
+        assert_eq!(translate(Language::French, "not a known string"), "not a known string");
+    }
+
+    #[test]
+    fn french_strings_are_translated_once() {
+        for (i, (english, french)) in FRENCH.iter().enumerate() {
+            assert!(!french.is_empty(), "{english:?} has an empty translation");
+            assert!(FRENCH[i + 1..].iter().all(|(other, _)| other != english), "{english:?} is translated twice");
+        }
+    }
+}
@@ -0,0 +1,777 @@
+use std::{collections::HashMap, fs::File, io::{BufRead, BufReader, Read, Seek}, path::Path};
+
+use anyhow::{anyhow, bail, Result};
+use clap::ValueEnum;
+use fitparser::{profile::MesgNum, FitDataRecord, Value};
+use serde_json::Value as JsonValue;
+use geo_types::Point;
+use gpx::{Gpx, GpxVersion, Track, TrackSegment, Waypoint};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use xml::{attribute::OwnedAttribute, reader::{EventReader, XmlEvent}, ParserConfig};
+
+use crate::utils::{Split, Splits};
+use crate::Terrain;
+
+pub const EXTENSIONS: [&str; 5] = ["gpx", "tcx", "fit", "kml", "kmz"];
+
+pub fn is_supported(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| EXTENSIONS.contains(&extension.to_lowercase().as_str()))
+}
+
+// Reads any supported file into a GPX document, picked by extension.
+pub fn read_file(path: &Path) -> Result<Gpx> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or("").to_lowercase();
+
+    match extension.as_str() {
+        "tcx" => read_tcx(reader),
+        "fit" => read_fit(&mut reader),
+        "kml" => read_kml(reader),
+        "kmz" => read_kmz(reader),
+        _ => gpx::read(reader).map_err(|e| anyhow!("{e}"))
+    }
+}
+
+// What the lenient GPX reader left out.
+#[derive(Default)]
+pub struct Recovery {
+    // Points without valid lat/lon.
+    pub skipped_points: usize,
+    pub empty_segments: usize,
+    // Times and elevations that couldn't be read, dropped from their point.
+    pub invalid_values: usize,
+    // Why the rest of the document was ignored.
+    pub error: Option<String>
+}
+
+// Tracks, their segments and points and the waypoints of a GPX document, read element by element
+// whatever the namespaces. Broken points and values and empty segments are left out and counted,
+// and an XML error ends the document where it occurs, keeping what came before.
+pub fn read_gpx_lenient<R: Read>(reader: R) -> Result<(Gpx, Recovery)> {
+    let mut gpx = Gpx {
+        version: GpxVersion::Gpx11,
+        creator: Some(String::from("mountain_snail")),
+        ..Default::default()
+    };
+    let mut recovery = Recovery::default();
+    let mut track: Option<Track> = None;
+    let mut segment: Option<TrackSegment> = None;
+    let mut point: Option<Waypoint> = None;
+    // Names of the open elements.
+    let mut parents: Vec<String> = vec![];
+    let mut text = String::new();
+
+    for event in EventReader::new(reader) {
+        let event = match event {
+            Ok(event) => event,
+            Err(e) => {
+                recovery.error = Some(e.to_string());
+                break;
+            }
+        };
+
+        match event {
+            XmlEvent::StartElement { name, attributes, .. } => {
+                match name.local_name.as_str() {
+                    "trk" => track = Some(Track::new()),
+                    "trkseg" => segment = Some(TrackSegment::new()),
+                    "trkpt" | "wpt" => {
+                        let coordinate = |key: &str| attributes.iter()
+                            .find(|attribute| attribute.name.local_name == key)
+                            .and_then(|attribute| attribute.value.trim().parse::<f64>().ok())
+                            .filter(|degrees| degrees.is_finite());
+                        match (coordinate("lat"), coordinate("lon")) {
+                            (Some(latitude), Some(longitude)) if latitude.abs() <= 90. && longitude.abs() <= 180. => {
+                                point = Some(Waypoint::new(Point::new(longitude, latitude)));
+                            },
+                            // Its children find no point to fill.
+                            _ => recovery.skipped_points += 1
+                        }
+                    },
+                    _ => {}
+                }
+                parents.push(name.local_name);
+                text.clear();
+            },
+            XmlEvent::Characters(characters) | XmlEvent::CData(characters) => text.push_str(&characters),
+            XmlEvent::EndElement { name } => {
+                parents.pop();
+                let in_point = matches!(parents.last().map(String::as_str), Some("trkpt" | "wpt"));
+                match name.local_name.as_str() {
+                    "ele" if in_point => if let Some(point) = &mut point {
+                        point.elevation = text.trim().parse::<f64>().ok().filter(|elevation| elevation.is_finite());
+                        if point.elevation.is_none() {
+                            recovery.invalid_values += 1;
+                        }
+                    },
+                    "time" if in_point => if let Some(point) = &mut point {
+                        point.time = OffsetDateTime::parse(text.trim(), &Rfc3339).ok().map(|time| time.into());
+                        if point.time.is_none() {
+                            recovery.invalid_values += 1;
+                        }
+                    },
+                    "name" if in_point => if let Some(point) = &mut point {
+                        point.name = Some(text.trim().to_string());
+                    },
+                    "name" if parents.last().is_some_and(|parent| parent == "trk") => if let Some(track) = &mut track {
+                        track.name = Some(text.trim().to_string());
+                    },
+                    "trkpt" => if let Some(point) = point.take() {
+                        segment.get_or_insert_with(TrackSegment::new).points.push(point);
+                    },
+                    "wpt" => if let Some(point) = point.take() {
+                        gpx.waypoints.push(point);
+                    },
+                    "trkseg" => match segment.take() {
+                        Some(segment) if !segment.points.is_empty() => track.get_or_insert_with(Track::new).segments.push(segment),
+                        _ => recovery.empty_segments += 1
+                    },
+                    "trk" => if let Some(track) = track.take() && !track.segments.is_empty() {
+                        gpx.tracks.push(track);
+                    },
+                    _ => {}
+                }
+                text.clear();
+            },
+            _ => {}
+        }
+    }
+
+    // A document cut short keeps its last complete points.
+    if let Some(segment) = segment.take() && !segment.points.is_empty() {
+        track.get_or_insert_with(Track::new).segments.push(segment);
+    }
+    if let Some(track) = track.take() && !track.segments.is_empty() {
+        gpx.tracks.push(track);
+    }
+
+    if gpx.tracks.is_empty() {
+        bail!("no readable track point{}", recovery.error.as_ref().map(|e| format!(" ({e})")).unwrap_or_default());
+    }
+    Ok((gpx, recovery))
+}
+
+// Point element name and coordinates in 1e-7 degrees, which the source and the gpx crate's output agree on.
+pub type PointKey = (String, i64, i64);
+
+// Content of the <extensions> of GPX points, which the gpx crate drops when reading.
+pub type PointExtensions = HashMap<PointKey, Vec<XmlEvent>>;
+
+pub fn point_key(name: &str, attributes: &[OwnedAttribute]) -> Option<PointKey> {
+    let coordinate = |key: &str| attributes.iter()
+        .find(|attribute| attribute.name.local_name == key)
+        .and_then(|attribute| attribute.value.trim().parse::<f64>().ok())
+        .map(|degrees| (degrees * 1e7).round() as i64);
+    Some((name.to_string(), coordinate("lat")?, coordinate("lon")?))
+}
+
+// Events inside the <extensions> of every wpt, trkpt and rtept of a GPX document.
+pub fn read_point_extensions<R: Read>(reader: R) -> Result<PointExtensions> {
+    let mut extensions = PointExtensions::new();
+    let mut point: Option<PointKey> = None;
+    // Depth inside the current point's <extensions>, 0 outside.
+    let mut depth = 0;
+
+    for event in ParserConfig::new().trim_whitespace(true).create_reader(reader) {
+        let event = event?;
+        match &event {
+            XmlEvent::StartElement { name, attributes, .. } if depth == 0 => {
+                match name.local_name.as_str() {
+                    "wpt" | "trkpt" | "rtept" => point = point_key(&name.local_name, attributes),
+                    "extensions" if point.is_some() => depth = 1,
+                    _ => {}
+                }
+                continue;
+            },
+            XmlEvent::EndElement { name } if depth == 0 => {
+                if matches!(name.local_name.as_str(), "wpt" | "trkpt" | "rtept") {
+                    point = None;
+                }
+                continue;
+            },
+            XmlEvent::StartElement { .. } => depth += 1,
+            XmlEvent::EndElement { .. } => depth -= 1,
+            _ => {}
+        }
+
+        if depth > 0 && let Some(point) = &point {
+            extensions.entry(point.clone()).or_default().push(event);
+        }
+    }
+
+    Ok(extensions)
+}
+
+// Name of the first track, found without parsing the whole file: only the first 64 KiB are
+// looked at so that listing a directory of long recordings doesn't read them whole.
+pub fn peek_track_name(path: &Path) -> Option<String> {
+    let reader = BufReader::new(File::open(path).ok()?.take(64 * 1024));
+    // Depth inside the first <trk>, 0 before it.
+    let mut depth = 0;
+    let mut in_name = false;
+
+    for event in ParserConfig::new().trim_whitespace(true).create_reader(reader) {
+        match event.ok()? {
+            XmlEvent::StartElement { name, .. } if depth == 0 && name.local_name == "trk" => depth = 1,
+            XmlEvent::StartElement { .. } if depth == 0 => {},
+            XmlEvent::StartElement { name, .. } => {
+                depth += 1;
+                in_name = depth == 2 && name.local_name == "name";
+            },
+            XmlEvent::Characters(text) | XmlEvent::CData(text) if in_name => {
+                let name = text.trim();
+                return (!name.is_empty()).then(|| name.to_string());
+            },
+            XmlEvent::EndElement { .. } if depth > 0 => {
+                depth -= 1;
+                in_name = false;
+                if depth == 0 {
+                    return None;
+                }
+            },
+            _ => {}
+        }
+    }
+
+    None
+}
+
+// First track point, looked for in the first 64 KiB only.
+pub fn peek_first_point(path: &Path) -> Option<Waypoint> {
+    let reader = BufReader::new(File::open(path).ok()?.take(64 * 1024));
+    for event in ParserConfig::new().create_reader(reader) {
+        if let XmlEvent::StartElement { name, attributes, .. } = event.ok()?
+            && name.local_name == "trkpt" {
+            let coordinate = |key: &str| attributes.iter()
+                .find(|attribute| attribute.name.local_name == key)
+                .and_then(|attribute| attribute.value.trim().parse::<f64>().ok());
+            return Some(Waypoint::new(Point::new(coordinate("lon")?, coordinate("lat")?)));
+        }
+    }
+
+    None
+}
+
+const SPLITS_SCHEMA: &str = "expected {\"splits\": [[d_plus, d_minus], ...]}, entries being either \
+    [d_plus, d_minus] pairs or objects like {\"d_plus\": 120, \"d_minus\": 30, \"length\": 800, \"terrain\": \"alpine\"}";
+
+// JSON splits, checked entry by entry so that errors name the malformed entry and field.
+pub fn read_json_splits<R: Read>(reader: R) -> Result<Splits> {
+    let document: JsonValue = serde_json::from_reader(reader)?;
+    let Some(entries) = document.get("splits").and_then(|splits| splits.as_array()) else {
+        bail!("no \"splits\" list, {SPLITS_SCHEMA}");
+    };
+
+    for (i, entry) in entries.iter().enumerate() {
+        check_split(entry).map_err(|e| anyhow!("entry {}: {e}, {SPLITS_SCHEMA}", i + 1))?;
+    }
+    Ok(serde_json::from_value(document)?)
+}
+
+fn check_split(entry: &JsonValue) -> Result<()> {
+    let whole = |field: &str, value: Option<&JsonValue>, positive: bool| -> Result<()> {
+        let Some(value) = value else {
+            bail!("{field} is missing");
+        };
+        match value.as_i64() {
+            Some(number) if i32::try_from(number).is_err() => bail!("{field} {number} is out of range"),
+            Some(number) if number < 0 => bail!("{field} must not be negative, got {number}"),
+            Some(0) if positive => bail!("{field} must be positive, got 0"),
+            Some(_) => Ok(()),
+            None => bail!("{field} must be a whole number of meters, got {value}")
+        }
+    };
+
+    match entry {
+        JsonValue::Array(pair) => {
+            if pair.len() != 2 {
+                bail!("[d_plus, d_minus] pair has {} value(s)", pair.len());
+            }
+            whole("d_plus", pair.first(), false)?;
+            whole("d_minus", pair.get(1), false)
+        },
+        JsonValue::Object(fields) => {
+            whole("d_plus", fields.get("d_plus"), false)?;
+            whole("d_minus", fields.get("d_minus"), false)?;
+            if let Some(length) = fields.get("length").filter(|length| !length.is_null()) {
+                whole("length", Some(length), true)?;
+            }
+            if let Some(terrain) = fields.get("terrain").filter(|terrain| !terrain.is_null()) {
+                let name = terrain.as_str().unwrap_or_default();
+                if Terrain::from_str(name, true).is_err() {
+                    bail!("unknown terrain {terrain} (road, path, track or alpine)");
+                }
+            }
+            let adjustement = fields.get("adjustement").or(fields.get("adjustment")).filter(|adjustement| !adjustement.is_null());
+            if let Some(adjustement) = adjustement && adjustement.as_f64().is_none() {
+                bail!("adjustement must be a number, got {adjustement}");
+            }
+            if let Some(field) = fields.keys().find(|key| !["d_plus", "d_minus", "length", "terrain", "adjustement", "adjustment"].contains(&key.as_str())) {
+                bail!("unknown field \"{field}\"");
+            }
+            Ok(())
+        },
+        _ => bail!("{entry} is neither a [d_plus, d_minus] pair nor an object")
+    }
+}
+
+// Splits as spreadsheet rows: d_plus, d_minus and optionally length (m), terrain and adjustement,
+// in that order or in the order of a header line. Cells are separated by commas or semicolons.
+pub fn read_csv_splits<R: Read>(reader: R) -> Result<Splits> {
+    let mut columns: Vec<String> = ["d_plus", "d_minus", "length", "terrain", "adjustement"].map(String::from).into();
+    let mut splits = vec![];
+
+    for (i, line) in BufReader::new(reader).lines().enumerate() {
+        let line = line?;
+        let separator = if line.contains(';') { ';' } else { ',' };
+        let cells: Vec<&str> = line.split(separator).map(|cell| cell.trim().trim_matches('"')).collect();
+        if cells.iter().all(|cell| cell.is_empty()) {
+            continue;
+        }
+
+        let header = cells.first().is_some_and(|cell| cell.parse::<f64>().is_err());
+        if i == 0 && header {
+            columns = cells.iter().map(|cell| String::from(if *cell == "adjustment" { "adjustement" } else { cell })).collect();
+            continue;
+        }
+
+        let cell = |column: &str| columns.iter().position(|name| name == column)
+            .and_then(|index| cells.get(index))
+            .filter(|cell| !cell.is_empty());
+        let number = |column: &str| -> Result<Option<f64>> {
+            cell(column)
+                .map(|cell| cell.replace(',', ".").parse::<f64>().map_err(|_| anyhow!("line {}: {column} \"{cell}\" is not a number", i + 1)))
+                .transpose()
+        };
+
+        let (Some(d_plus), Some(d_minus)) = (number("d_plus")?, number("d_minus")?) else {
+            bail!("line {}: d_plus and d_minus are required", i + 1);
+        };
+        let length = number("length")?;
+        if let Some((column, value)) = [("d_plus", Some(d_plus)), ("d_minus", Some(d_minus)), ("length", length)].into_iter()
+            .find_map(|(column, value)| value.filter(|value| *value < 0.).map(|value| (column, value))) {
+            bail!("line {}: {column} must not be negative, got {value}", i + 1);
+        }
+        let terrain = cell("terrain")
+            .map(|cell| Terrain::from_str(cell, true).map_err(|_| anyhow!("line {}: unknown terrain \"{cell}\"", i + 1)))
+            .transpose()?;
+
+        splits.push(Split {
+            d_plus: d_plus.round() as i32,
+            d_minus: d_minus.round() as i32,
+            length: length.map(|length| length.round() as i32),
+            terrain,
+            adjustement: number("adjustement")?
+        });
+    }
+
+    Ok(Splits { splits })
+}
+
+// Courses and activities become tracks, their <Track> elements segments and course points waypoints.
+pub fn read_tcx<R: Read>(reader: R) -> Result<Gpx> {
+    let mut gpx = Gpx {
+        version: GpxVersion::Gpx11,
+        creator: Some(String::from("mountain_snail")),
+        ..Default::default()
+    };
+
+    let mut elements: Vec<String> = Vec::new();
+    let mut text = String::new();
+    let mut track = Track::new();
+    let mut segment = TrackSegment::new();
+    let (mut latitude, mut longitude, mut elevation) = (None, None, None);
+    let (mut point_time, mut point_name): (Option<OffsetDateTime>, Option<String>) = (None, None);
+
+    for event in EventReader::new(reader) {
+        match event? {
+            XmlEvent::StartElement { name, .. } => {
+                match name.local_name.as_str() {
+                    "Course" | "Activity" => track = Track::new(),
+                    "Track" => segment = TrackSegment::new(),
+                    "Trackpoint" | "CoursePoint" => {
+                        (latitude, longitude, elevation) = (None, None, None);
+                        (point_time, point_name) = (None, None);
+                    },
+                    _ => {}
+                }
+                elements.push(name.local_name);
+                text.clear();
+            },
+            XmlEvent::Characters(characters) | XmlEvent::CData(characters) => text.push_str(&characters),
+            XmlEvent::EndElement { name } => {
+                elements.pop();
+                let parent = elements.last().map(|parent| parent.as_str()).unwrap_or("");
+
+                match (name.local_name.as_str(), parent) {
+                    ("LatitudeDegrees", _) => latitude = text.trim().parse::<f64>().ok(),
+                    ("LongitudeDegrees", _) => longitude = text.trim().parse::<f64>().ok(),
+                    ("AltitudeMeters", _) => elevation = text.trim().parse::<f64>().ok(),
+                    ("Time", _) => point_time = OffsetDateTime::parse(text.trim(), &Rfc3339).ok(),
+                    ("Name", "Course") | ("Id", "Activity") => track.name = Some(text.trim().to_string()),
+                    ("Name", "CoursePoint") => point_name = Some(text.trim().to_string()),
+                    ("Trackpoint", _) | ("CoursePoint", _) => {
+                        // Trackpoints without a position only carry sensor data.
+                        if let (Some(latitude), Some(longitude)) = (latitude, longitude) {
+                            let mut waypoint = Waypoint::new(Point::new(longitude, latitude));
+                            waypoint.elevation = elevation;
+                            waypoint.time = point_time.map(|time| time.into());
+                            waypoint.name = point_name.take();
+
+                            if name.local_name == "Trackpoint" {
+                                segment.points.push(waypoint);
+                            }
+                            else {
+                                gpx.waypoints.push(waypoint);
+                            }
+                        }
+                    },
+                    ("Track", _) if !segment.points.is_empty() => track.segments.push(std::mem::take(&mut segment)),
+                    ("Course", _) | ("Activity", _) if !track.segments.is_empty() => gpx.tracks.push(std::mem::take(&mut track)),
+                    _ => {}
+                }
+                text.clear();
+            },
+            _ => {}
+        }
+    }
+
+    if gpx.tracks.is_empty() {
+        return Err(anyhow!("no track with positions in TCX file"));
+    }
+    Ok(gpx)
+}
+
+// Record messages become the points of a single track, course points waypoints.
+pub fn read_fit<R: Read>(reader: &mut R) -> Result<Gpx> {
+    let mut gpx = Gpx {
+        version: GpxVersion::Gpx11,
+        creator: Some(String::from("mountain_snail")),
+        ..Default::default()
+    };
+    let mut segment = TrackSegment::new();
+
+    for record in fitparser::from_reader(reader)? {
+        let Some(mut waypoint) = fit_waypoint(&record) else {
+            continue;
+        };
+
+        match record.kind() {
+            MesgNum::Record => segment.points.push(waypoint),
+            MesgNum::CoursePoint => {
+                waypoint.elevation = None;
+                gpx.waypoints.push(waypoint);
+            },
+            _ => {}
+        }
+    }
+
+    if segment.points.is_empty() {
+        return Err(anyhow!("no record with a position in FIT file"));
+    }
+    let mut track = Track::new();
+    track.segments.push(segment);
+    gpx.tracks.push(track);
+    Ok(gpx)
+}
+
+fn fit_waypoint(record: &FitDataRecord) -> Option<Waypoint> {
+    // Positions are stored in semicircles: 2^31 of them for 180°.
+    let semicircles = |value: &Value| match value {
+        Value::SInt32(value) => Some(*value as f64 * 180. / 2f64.powi(31)),
+        _ => None
+    };
+    let number = |value: &Value| match value {
+        Value::Float64(value) => Some(*value),
+        Value::Float32(value) => Some(*value as f64),
+        Value::UInt16(value) => Some(*value as f64),
+        Value::UInt32(value) => Some(*value as f64),
+        _ => None
+    };
+
+    let (mut latitude, mut longitude) = (None, None);
+    let (mut altitude, mut enhanced_altitude, mut timestamp, mut name) = (None, None, None, None);
+    for field in record.fields() {
+        match field.name() {
+            "position_lat" => latitude = semicircles(field.value()),
+            "position_long" => longitude = semicircles(field.value()),
+            "altitude" => altitude = number(field.value()),
+            "enhanced_altitude" => enhanced_altitude = number(field.value()),
+            "timestamp" => if let Value::Timestamp(time) = field.value() {
+                timestamp = OffsetDateTime::from_unix_timestamp(time.timestamp()).ok();
+            },
+            "name" => if let Value::String(value) = field.value() {
+                name = Some(value.clone());
+            },
+            _ => {}
+        }
+    }
+
+    let mut waypoint = Waypoint::new(Point::new(longitude?, latitude?));
+    waypoint.elevation = enhanced_altitude.or(altitude);
+    waypoint.time = timestamp.map(|time| time.into());
+    waypoint.name = name;
+    Some(waypoint)
+}
+
+// Placemarks with a LineString become tracks (one segment per LineString), Point placemarks waypoints.
+pub fn read_kml<R: Read>(reader: R) -> Result<Gpx> {
+    let mut gpx = Gpx {
+        version: GpxVersion::Gpx11,
+        creator: Some(String::from("mountain_snail")),
+        ..Default::default()
+    };
+
+    let mut elements: Vec<String> = Vec::new();
+    let mut text = String::new();
+    let mut track = Track::new();
+    let mut placemark_name: Option<String> = None;
+
+    for event in EventReader::new(reader) {
+        match event? {
+            XmlEvent::StartElement { name, .. } => {
+                if name.local_name == "Placemark" {
+                    track = Track::new();
+                    placemark_name = None;
+                }
+                elements.push(name.local_name);
+                text.clear();
+            },
+            XmlEvent::Characters(characters) | XmlEvent::CData(characters) => text.push_str(&characters),
+            XmlEvent::EndElement { name } => {
+                elements.pop();
+                let parent = elements.last().map(|parent| parent.as_str()).unwrap_or("");
+
+                match (name.local_name.as_str(), parent) {
+                    ("name", "Placemark") => placemark_name = Some(text.trim().to_string()),
+                    ("coordinates", "LineString") => {
+                        let mut segment = TrackSegment::new();
+                        segment.points = text.split_whitespace().filter_map(kml_waypoint).collect();
+                        if !segment.points.is_empty() {
+                            track.segments.push(segment);
+                        }
+                    },
+                    ("coordinates", "Point") => {
+                        if let Some(mut waypoint) = text.split_whitespace().next().and_then(kml_waypoint) {
+                            waypoint.name = placemark_name.clone();
+                            gpx.waypoints.push(waypoint);
+                        }
+                    },
+                    ("Placemark", _) if !track.segments.is_empty() => {
+                        track.name = placemark_name.take();
+                        gpx.tracks.push(std::mem::take(&mut track));
+                    },
+                    _ => {}
+                }
+                text.clear();
+            },
+            _ => {}
+        }
+    }
+
+    if gpx.tracks.is_empty() {
+        return Err(anyhow!("no LineString in KML file"));
+    }
+    Ok(gpx)
+}
+
+// A KMZ is a zip archive holding the KML document (usually doc.kml) and its resources.
+pub fn read_kmz<R: Read + Seek>(reader: R) -> Result<Gpx> {
+    let mut archive = zip::ZipArchive::new(reader)?;
+    for index in 0..archive.len() {
+        let mut file = archive.by_index(index)?;
+        if file.name().to_lowercase().ends_with(".kml") {
+            let mut content = Vec::new();
+            file.read_to_end(&mut content)?;
+            return read_kml(content.as_slice());
+        }
+    }
+
+    Err(anyhow!("no KML document in KMZ archive"))
+}
+
+// KML tuples are "longitude,latitude[,altitude]".
+fn kml_waypoint(tuple: &str) -> Option<Waypoint> {
+    let mut values = tuple.split(',').map(|value| value.parse::<f64>());
+    let longitude = values.next()?.ok()?;
+    let latitude = values.next()?.ok()?;
+
+    let mut waypoint = Waypoint::new(Point::new(longitude, latitude));
+    // Drawn routes are often clamped to the ground with a 0 altitude.
+    waypoint.elevation = values.next().and_then(|altitude| altitude.ok()).filter(|altitude| *altitude != 0.);
+    Some(waypoint)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf, time::Duration};
+
+    use time::macros::datetime;
+
+    use super::*;
+    use crate::export;
+    use crate::utils::{PathStats, PointStats};
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("mountain_snail_{}_{name}", std::process::id()))
+    }
+
+    fn splits() -> Vec<Split> {
+        vec![
+            Split { d_plus: 120, d_minus: 30, length: None, terrain: None, adjustement: None },
+            Split { d_plus: 0, d_minus: 250, length: Some(800), terrain: Some(Terrain::Alpine), adjustement: Some(0.3) }
+        ]
+    }
+
+    fn assert_same_splits(read: &[Split]) {
+        assert_eq!(read.len(), 2);
+        assert_eq!((read[0].d_plus, read[0].d_minus, read[0].length, read[0].adjustement), (120, 30, None, None));
+        assert!(read[0].terrain.is_none());
+        assert_eq!((read[1].d_plus, read[1].d_minus, read[1].length, read[1].adjustement), (0, 250, Some(800), Some(0.3)));
+        assert!(read[1].terrain == Some(Terrain::Alpine));
+    }
+
+    #[test]
+    fn csv_splits_round_trip() {
+        let mut csv = Vec::new();
+        export::write_csv_splits(&mut csv, &splits()).unwrap();
+        assert_same_splits(&read_csv_splits(csv.as_slice()).unwrap().splits);
+    }
+
+    #[test]
+    fn json_splits_round_trip() {
+        let json = serde_json::to_vec(&Splits { splits: splits() }).unwrap();
+        assert_same_splits(&read_json_splits(json.as_slice()).unwrap().splits);
+    }
+
+    #[test]
+    fn csv_splits_follow_the_header() {
+        let read = read_csv_splits("length;d_minus;d_plus;adjustment\n1000;5;10;0,25\n\n".as_bytes()).unwrap().splits;
+        assert_eq!(read.len(), 1);
+        assert_eq!((read[0].d_plus, read[0].d_minus, read[0].length, read[0].adjustement), (10, 5, Some(1000), Some(0.25)));
+    }
+
+    #[test]
+    fn invalid_splits_name_the_culprit() {
+        let error = |result: Result<Splits>| result.err().map(|e| e.to_string()).unwrap_or_default();
+
+        assert!(error(read_json_splits(r#"{"splits": [[10, 5], [10]]}"#.as_bytes())).contains("entry 2"));
+        assert!(error(read_json_splits(r#"{"splits": [{"d_plus": 10, "d_minus": -5}]}"#.as_bytes())).contains("d_minus must not be negative"));
+        assert!(error(read_json_splits(r#"{"splits": [{"d_plus": 10, "d_minus": 5, "lenght": 800}]}"#.as_bytes())).contains("unknown field \"lenght\""));
+        assert!(error(read_json_splits(r#"{"splits": [{"d_plus": 10, "d_minus": 5, "length": 0}]}"#.as_bytes())).contains("length must be positive"));
+        assert!(error(read_csv_splits("d_plus,d_minus\n10,5\n-10,5".as_bytes())).contains("line 3"));
+        assert!(error(read_csv_splits("d_plus,d_minus,terrain\n10,5,glacier".as_bytes())).contains("unknown terrain"));
+    }
+
+    #[test]
+    fn tcx_courses_become_tracks() {
+        let tcx = r#"<?xml version="1.0" encoding="UTF-8"?>
+<TrainingCenterDatabase xmlns="http://www.garmin.com/xmlschemas/TrainingCenterDatabase/v2">
+  <Courses>
+    <Course>
+      <Name>Col</Name>
+      <Track>
+        <Trackpoint>
+          <Time>2024-07-01T08:00:00Z</Time>
+          <Position><LatitudeDegrees>45.1</LatitudeDegrees><LongitudeDegrees>6.1</LongitudeDegrees></Position>
+          <AltitudeMeters>1200</AltitudeMeters>
+        </Trackpoint>
+        <Trackpoint>
+          <Time>2024-07-01T08:10:00Z</Time>
+          <Position><LatitudeDegrees>45.2</LatitudeDegrees><LongitudeDegrees>6.2</LongitudeDegrees></Position>
+          <AltitudeMeters>1500</AltitudeMeters>
+        </Trackpoint>
+        <Trackpoint>
+          <Time>2024-07-01T08:11:00Z</Time>
+          <HeartRateBpm><Value>120</Value></HeartRateBpm>
+        </Trackpoint>
+      </Track>
+      <CoursePoint>
+        <Name>Refuge</Name>
+        <Position><LatitudeDegrees>45.2</LatitudeDegrees><LongitudeDegrees>6.2</LongitudeDegrees></Position>
+      </CoursePoint>
+    </Course>
+  </Courses>
+</TrainingCenterDatabase>"#;
+
+        let gpx = read_tcx(tcx.as_bytes()).unwrap();
+        assert_eq!(gpx.tracks.len(), 1);
+        assert_eq!(gpx.tracks[0].name.as_deref(), Some("Col"));
+        let points = &gpx.tracks[0].segments[0].points;
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[1].elevation, Some(1500.));
+        assert_eq!(OffsetDateTime::from(points[0].time.unwrap()), datetime!(2024-07-01 08:00 UTC));
+        assert_eq!(gpx.waypoints.len(), 1);
+        assert_eq!(gpx.waypoints[0].name.as_deref(), Some("Refuge"));
+    }
+
+    #[test]
+    fn tcx_without_positions_is_rejected() {
+        let tcx = "<TrainingCenterDatabase><Activities><Activity><Id>Run</Id><Lap><Track><Trackpoint><Time>2024-07-01T08:00:00Z</Time></Trackpoint></Track></Lap></Activity></Activities></TrainingCenterDatabase>";
+        assert!(read_tcx(tcx.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn kml_export_round_trip() {
+        let point = |longitude: f64, latitude: f64, elevation: Option<f64>, elapsed: u64| PointStats {
+            latitude,
+            longitude,
+            elevation,
+            distance: 0.,
+            d_plus: 0.,
+            d_minus: 0.,
+            grade: 0.,
+            duration: Duration::ZERO,
+            total_duration: Duration::from_secs(elapsed),
+            elapsed: Duration::from_secs(elapsed)
+        };
+        let stats = PathStats {
+            points: vec![point(6.1, 45.1, Some(1200.), 0), point(6.2, 45.2, None, 4000)],
+            ..Default::default()
+        };
+        let path = temp_path("round_trip.kml");
+        export::write_kml(&stats, "Col", None, &path).unwrap();
+        let gpx = read_kml(File::open(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+
+        let gpx = gpx.unwrap();
+        assert_eq!(gpx.tracks[0].name.as_deref(), Some("Col"));
+        let points = &gpx.tracks[0].segments[0].points;
+        assert_eq!(points.len(), 2);
+        assert_eq!((points[0].point().x(), points[0].point().y(), points[0].elevation), (6.1, 45.1, Some(1200.)));
+        // Written as a 0 altitude, read back as none.
+        assert_eq!(points[1].elevation, None);
+        // The placemark of the first hour of walking.
+        assert_eq!(gpx.waypoints.len(), 1);
+        assert!(gpx.waypoints[0].name.as_deref().is_some_and(|name| name.starts_with("1h")));
+    }
+
+    #[test]
+    fn track_name_is_peeked_from_namespaced_tracks() {
+        let path = temp_path("peek.gpx");
+        fs::write(&path, r#"<?xml version="1.0" encoding="UTF-8"?>
+<g:gpx xmlns:g="http://www.topografix.com/GPX/1/1" version="1.1" creator="test">
+  <g:metadata><g:name>Document</g:name></g:metadata>
+  <g:trk ><g:name> Tour </g:name><g:trkseg><g:trkpt lat="45.1" lon="6.1"/></g:trkseg></g:trk>
+</g:gpx>"#).unwrap();
+        let name = peek_track_name(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(name.as_deref(), Some("Tour"));
+    }
+
+    #[test]
+    fn first_point_is_peeked_past_the_waypoints() {
+        let path = temp_path("first.gpx");
+        fs::write(&path, r#"<gpx version="1.1" creator="test">
+  <wpt lat="44.0" lon="5.0"><name>Car park</name></wpt>
+  <trk><trkseg><trkpt lat="45.1" lon="6.1"/><trkpt lat="45.2" lon="6.2"/></trkseg></trk>
+</gpx>"#).unwrap();
+        let first = peek_first_point(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(first.map(|point| (point.point().x(), point.point().y())), Some((6.1, 45.1)));
+    }
+}
@@ -0,0 +1,68 @@
+use anyhow::{anyhow, Result};
+use geo_types::Point;
+use gpx::{Gpx, GpxVersion, Track, TrackSegment, Waypoint};
+use serde_json::Value;
+
+const API_URL: &str = "https://api.komoot.de/v007";
+
+#[derive(Clone)]
+pub struct KomootTour {
+    pub id: u64,
+    pub share_token: Option<String>
+}
+
+impl KomootTour {
+    // Accepts komoot.com/tour/ID URLs (with the share_token of private links) or a bare tour ID.
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let input = input.trim();
+        let (path, query) = input.split_once('?').unwrap_or((input, ""));
+        let id_text = path.trim_end_matches('/').rsplit('/').next().unwrap_or("");
+        let id = id_text.parse::<u64>().map_err(|_| format!("invalid Komoot tour ID \"{id_text}\""))?;
+
+        let share_token = query.split('&')
+            .find_map(|parameter| parameter.strip_prefix("share_token="))
+            .map(|token| token.to_string());
+
+        Ok(Self { id, share_token })
+    }
+
+    pub fn file_name(&self) -> String {
+        format!("komoot_tour_{}.gpx", self.id)
+    }
+
+    fn url(&self, endpoint: &str) -> String {
+        match &self.share_token {
+            Some(token) => format!("{API_URL}/tours/{}{endpoint}?share_token={token}", self.id),
+            None => format!("{API_URL}/tours/{}{endpoint}", self.id)
+        }
+    }
+}
+
+// Only public tours, or private ones shared with a share_token link, can be downloaded.
+pub fn download(tour: &KomootTour) -> Result<Gpx> {
+    let details: Value = ureq::get(&tour.url("")).call()?.into_json()?;
+    let coordinates: Value = ureq::get(&tour.url("/coordinates")).call()?.into_json()?;
+    let items = coordinates["items"].as_array().ok_or(anyhow!("tour has no coordinates"))?;
+
+    let mut segment = TrackSegment::new();
+    for item in items {
+        let (Some(latitude), Some(longitude)) = (item["lat"].as_f64(), item["lng"].as_f64()) else {
+            continue;
+        };
+
+        let mut waypoint = Waypoint::new(Point::new(longitude, latitude));
+        waypoint.elevation = item["alt"].as_f64();
+        segment.points.push(waypoint);
+    }
+
+    let mut track = Track::new();
+    track.name = details["name"].as_str().map(|name| name.to_string());
+    track.segments.push(segment);
+
+    Ok(Gpx {
+        version: GpxVersion::Gpx11,
+        creator: Some(String::from("mountain_snail")),
+        tracks: vec![track],
+        ..Default::default()
+    })
+}
@@ -0,0 +1,73 @@
+// Analysis core: pace models, track statistics and units, without file system nor prompts. Builds
+// for wasm32 with `--no-default-features`, the cli feature adding what the command line needs
+// (argument parsing, parallel distances, progress bar).
+use std::io::Read;
+
+use anyhow::{anyhow, Result};
+use gpx::Track;
+
+use crate::i18n::tr;
+use crate::pace::PacePlan;
+use crate::utils::{read_gpx, AnalysisOptions, PathStats};
+
+pub mod i18n;
+pub mod pace;
+pub mod units;
+pub mod utils;
+
+#[derive(PartialEq, Clone, Copy, serde::Deserialize, serde::Serialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum Terrain {
+    #[cfg_attr(feature = "cli", value(skip))]
+    #[serde(skip)]
+    Unknown,
+    Road,
+    Path,
+    Track,
+    Alpine,
+    #[cfg_attr(feature = "cli", value(skip))]
+    #[serde(skip)]
+    Formula
+}
+
+impl From<usize> for Terrain {
+    fn from(value: usize) -> Self {
+        match value {
+            0 => Self::Road,
+            1 => Self::Path,
+            2 => Self::Track,
+            3 => Self::Alpine,
+            5 => Self::Formula,
+            _ => Self::Unknown
+        }
+    }
+}
+
+impl Terrain {
+    pub fn name(&self) -> &'static str {
+        tr(match self {
+            Self::Unknown => "manual",
+            Self::Road => "road",
+            Self::Path => "path",
+            Self::Track => "track",
+            Self::Alpine => "alpine",
+            Self::Formula => "custom formula"
+        })
+    }
+}
+
+// Every track of a GPX document analysed as one itinerary, for callers holding the document in
+// memory (a browser page).
+pub fn analyse<R: Read>(reader: R, plan: &PacePlan, options: &AnalysisOptions) -> Result<PathStats> {
+    let gpx = gpx::read(reader).map_err(|e| anyhow!("{e}"))?;
+    let mut track = Track::new();
+    for source in gpx.tracks {
+        track.segments.extend(source.segments);
+    }
+    if track.segments.iter().all(|segment| segment.points.is_empty()) {
+        return Err(anyhow!("no track point in GPX file"));
+    }
+
+    Ok(read_gpx(&mut track, plan, options))
+}
@@ -1,250 +1,2749 @@
-use std::{env::home_dir, fs::{read_dir, File}, io::BufReader, path::PathBuf, process::exit, time::Duration};
+use std::{fs::read_dir, path::{Path, PathBuf}, process::exit, rc::Rc, time::Duration};
 use std::fs;
 
+use anyhow::Context;
+use clap::{parser::ValueSource, ArgMatches, CommandFactory, FromArgMatches, Parser, ValueHint};
+use clap_complete::{engine::{ArgValueCompleter, PathCompleter}, env::Shells, CompleteEnv, Shell};
 use console::style;
-use dialoguer::Select;
-use dialoguer;
-use gpx::{read, Gpx};
+use dialoguer::MultiSelect;
+use gpx::{Gpx, GpxVersion, Track, TrackSegment, Waypoint};
 use humanize_duration::prelude::DurationExt;
 use readable::up::UptimeFull;
+use time::{macros::format_description, OffsetDateTime, PrimitiveDateTime, Time, UtcOffset};
+use time_tz::{timezones, Offset, TimeZone, Tz};
+use tracing::{info, Level};
 
-use crate::utils::{calculate_travel_time, read_gpx};
+use crate::config::{Config, LastAnalysis, OutputFormat, SpeedProfile};
+use crate::i18n::tr;
+use crate::komoot::KomootTour;
+use crate::pace::{Conditions, ExponentialPace, ExpressionPace, HikerProfile, PaceModel, PacePlan, PaceSection, ScaledPace};
+use crate::strava::StravaSource;
+use crate::units::Units;
+use crate::utils::{calculate_travel_time, read_gpx, AnalysisOptions, BreakPolicy, DistanceAlgorithm, HeatPenalty, NightPenalty};
 
-mod utils;
+mod charts;
+mod cleaning;
+mod config;
+mod dem;
+mod exit_code;
+mod export;
+mod history;
+mod import;
+mod komoot;
+mod osm;
+mod plot;
+#[cfg(feature = "server")]
+mod server;
+mod strava;
+mod stream;
+mod sun;
+#[cfg(feature = "tui")]
+mod tui;
 
-#[derive(PartialEq)]
-enum Terrain {
-    Unknown,
-    Road,
-    Path,
-    Track,
-    Alpine
+// The analysis core is shared with the library, which builds for wasm32.
+use mountain_snail::{i18n, pace, units, utils, Terrain};
+mod weather;
+
+#[derive(Parser, Clone)]
+#[command(version, about = "Mountain snail - Hiking time calculator.", after_help = exit_code::HELP)]
+struct Args {
+    /// Track files analysed as one itinerary, in order (skips the file picker)
+    #[arg(value_name = "FILE", value_hint = ValueHint::FilePath, add = track_file_completer())]
+    files: Vec<PathBuf>,
+
+    /// Print the completion script of SHELL, e.g. `mountain_snail --completions bash > /etc/bash_completion.d/mountain_snail`
+    #[arg(long, value_name = "SHELL")]
+    completions: Option<Shell>,
+
+    /// Write the files given on the command line merged into a single GPX
+    #[arg(long, value_name = "PATH")]
+    merged_output: Option<PathBuf>,
+
+    /// Browse files and tracks in a full-screen interface, switching terrain and direction live
+    #[cfg(feature = "tui")]
+    #[arg(long)]
+    tui: bool,
+
+    /// Answer analyses over HTTP on ADDRESS (e.g. 127.0.0.1:8080): POST a track file to /analyse
+    #[cfg(feature = "server")]
+    #[arg(long, value_name = "ADDRESS")]
+    serve: Option<String>,
+
+    /// Print the analysis as JSON on stdout instead of the styled report
+    #[arg(long)]
+    json: bool,
+
+    /// Show diagnostics: -v for counts and timings, -vv for every failed distance calculation
+    #[arg(long, short, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Print only key=value lines in metric units, without colors or decorations, for scripts
+    #[arg(long, short, visible_alias = "porcelain")]
+    quiet: bool,
+
+    /// Export one CSV row per track point (distance, elevation, grade, times)
+    #[arg(long, value_name = "PATH")]
+    csv: Option<PathBuf>,
+
+    /// Slice the track into --split-length splits and write them as a splits file (JSON, or CSV by extension)
+    #[arg(long, value_name = "PATH")]
+    write_splits: Option<PathBuf>,
+
+    /// Also write each point's estimated elapsed time and distance into the timed GPX's extensions
+    #[arg(long)]
+    gpx_eta: bool,
+
+    /// GPX version of the written GPX files, 1.1 by default (some devices only accept 1.0)
+    #[arg(long, value_enum)]
+    gpx_version: Option<export::GpxOutputVersion>,
+
+    /// Name of the timed GPX (asked for otherwise)
+    #[arg(long, value_name = "NAME")]
+    gpx_name: Option<String>,
+
+    /// Description of the timed GPX (asked for otherwise, the planned time appended by default)
+    #[arg(long, value_name = "TEXT")]
+    gpx_description: Option<String>,
+
+    /// Creator of the timed GPX (asked for otherwise)
+    #[arg(long, value_name = "NAME")]
+    gpx_creator: Option<String>,
+
+    /// Write a Markdown trip report (summary, per-km and checkpoint ETA tables)
+    #[arg(long, value_name = "PATH")]
+    markdown: Option<PathBuf>,
+
+    /// Write a KML file with the track and timed placemarks every hour and at checkpoints
+    #[arg(long, value_name = "PATH")]
+    kml: Option<PathBuf>,
+
+    /// Write an iCalendar event for the hike (one per stage with --stages) with checkpoint ETAs
+    #[arg(long, value_name = "PATH", requires = "start")]
+    ics: Option<PathBuf>,
+
+    /// Write the analysed track as GeoJSON with per-point elevation, grade and times
+    #[arg(long, value_name = "PATH")]
+    geojson: Option<PathBuf>,
+
+    /// Write a standalone HTML report with an interactive elevation profile
+    #[arg(long, value_name = "PATH")]
+    html_report: Option<PathBuf>,
+
+    /// Grades (%) below which the track descends and above which it climbs, flat in between
+    #[arg(long, value_name = "DOWN,UP", value_delimiter = ',', num_args = 2, default_values_t = [-3., 3.], allow_negative_numbers = true)]
+    grade_zones: Vec<f64>,
+
+    /// Walking time over which the best sustained ascent rate (VAM) is measured
+    #[arg(long, value_name = "MINUTES", default_value_t = 30)]
+    vam_window: u64,
+
+    /// Plot the modelled speed along the track below the elevation profile
+    #[arg(long)]
+    speed_profile: bool,
+
+    /// Draw the elevation profile, with a mark at each hour of walking, to a PNG image
+    #[arg(long, value_name = "PATH")]
+    profile_png: Option<PathBuf>,
+
+    /// Same as --profile-png as an SVG image
+    #[arg(long, value_name = "PATH")]
+    profile_svg: Option<PathBuf>,
+
+    /// Distance over which the steepest climb and descent are averaged
+    #[arg(long, value_name = "METERS", default_value_t = 200.)]
+    steep_window: f64,
+
+    /// Waypoint to consider leaving the track at, repeatable; waypoints of type "escape" or "bail-out" always are
+    #[arg(long, value_name = "NAME")]
+    bail_out: Vec<String>,
+
+    /// Average grade (%) of the escape routes, down to the finish's altitude
+    #[arg(long, value_name = "PERCENT", default_value_t = 15.)]
+    escape_grade: f64,
+
+    /// List where on the track you should be at each full hour, to leave with a safety contact
+    #[arg(long)]
+    hourly: bool,
+
+    /// Split the track into stages ending at its named waypoints (or at asked distances)
+    #[arg(long)]
+    stages: bool,
+
+    /// Also report distance, D+/D- and duration for each track segment
+    #[arg(long)]
+    segments: bool,
+
+    /// Read GPX files point by point without loading them in memory, for very large recordings
+    /// (summary only: no track choice, cleaning, smoothing or per-point analysis)
+    #[arg(long)]
+    stream: bool,
+
+    /// Re-run the analysis whenever the chosen file changes, prompts taking their default answer
+    #[arg(long, conflicts_with_all = ["stdin", "stream", "batch", "calibrate"])]
+    watch: bool,
+
+    /// Read a GPX document from standard input, without any prompt (requires --terrain)
+    #[arg(long, requires = "terrain", conflicts_with_all = ["files", "strava", "komoot", "batch"])]
+    stdin: bool,
+
+    /// Walk the whole track on this terrain instead of choosing it in the menu
+    #[arg(long, value_enum)]
+    terrain: Option<Terrain>,
+
+    /// Analyse a Strava route or activity (URL or activity ID) instead of a local file
+    #[arg(long, value_name = "URL", value_parser = StravaSource::parse)]
+    strava: Option<StravaSource>,
+
+    /// Analyse a Komoot tour (URL, including the share_token of private links)
+    #[arg(long, value_name = "URL", value_parser = KomootTour::parse, conflicts_with = "strava")]
+    komoot: Option<KomootTour>,
+
+    /// Rank route options by estimated time (or --sort), with distance, D+ and effort score
+    #[arg(long, value_name = "FILE", num_args = 2..)]
+    compare: Vec<PathBuf>,
+
+    /// Order of the --batch and --compare tables, by name and by time by default
+    #[arg(long, value_enum)]
+    sort: Option<SortKey>,
+
+    /// Add, change or remove the entries of a splits file (created when missing) and save it
+    #[arg(long, value_name = "PATH")]
+    edit_splits: Option<PathBuf>,
+
+    /// Fit the speed adjustement of each terrain to timestamped recordings
+    #[arg(long, value_name = "FILE", num_args = 1..)]
+    calibrate: Vec<PathBuf>,
+
+    /// Analyse every GPX, TCX, FIT or KML/KMZ file in DIR and print a summary table
+    #[arg(long, value_name = "DIR", value_hint = ValueHint::DirPath)]
+    batch: Option<PathBuf>,
+
+    /// Split length in meters for the GPX split table
+    #[arg(long, value_name = "METERS", default_value_t = 1000)]
+    split_length: u32,
+
+    /// Also estimate the track walked in the opposite direction
+    #[arg(long)]
+    reverse: bool,
+
+    /// Estimate an out-and-back hike returning along the same track
+    #[arg(long)]
+    round_trip: bool,
+
+    /// List past analyses and pick one to run again
+    #[arg(long)]
+    history: bool,
+
+    // Command line arguments without the program name, saved with the analysis.
+    #[arg(skip)]
+    options: Vec<String>,
+
+    // Profile written by --calibrate, read from profiles.toml once.
+    #[arg(skip)]
+    calibrated: Option<SpeedProfile>,
+
+    /// Read GPX files that can't be parsed anyway, leaving out broken points, values and segments
+    #[arg(long)]
+    lenient: bool,
+
+    /// Analyse these tracks of the file (from 1, repeatable) instead of asking
+    #[arg(long = "track", value_name = "N")]
+    tracks: Vec<usize>,
+
+    /// Smooth GPS elevation noise with a moving average over this many points
+    #[arg(long, value_name = "POINTS", default_value_t = 0)]
+    smoothing: usize,
+
+    /// Fill the elevation of points without one from their neighbours (not when streaming)
+    #[arg(long)]
+    interpolate_elevation: bool,
+
+    /// Warn when more than this share of the points have no elevation
+    #[arg(long, value_name = "PERCENT", default_value_t = 5.)]
+    missing_elevation_threshold: f64,
+
+    /// Ignore elevation changes smaller than this when summing D+/D-
+    #[arg(long, value_name = "METERS", default_value_t = 0.)]
+    elevation_threshold: f64,
+
+    /// Only analyse the track from this distance on
+    #[arg(long, value_name = "KM")]
+    from_km: Option<f64>,
+
+    /// Only analyse the track up to this distance
+    #[arg(long, value_name = "KM")]
+    to_km: Option<f64>,
+
+    /// Only analyse the track from the point closest to this waypoint on
+    #[arg(long, value_name = "NAME", conflicts_with = "from_km")]
+    from_waypoint: Option<String>,
+
+    /// Only analyse the track up to the point closest to this waypoint
+    #[arg(long, value_name = "NAME", conflicts_with = "to_km")]
+    to_waypoint: Option<String>,
+
+    /// Drop GPS jumps: isolated spikes, and legs faster than --max-speed on recorded tracks
+    #[arg(long)]
+    remove_outliers: bool,
+
+    /// Fastest plausible speed between two recorded points, in km/h
+    #[arg(long, value_name = "KMH", default_value_t = 30.)]
+    max_speed: f64,
+
+    /// Report jumps longer than this between consecutive points as recording gaps
+    #[arg(long, value_name = "METERS", default_value_t = 500.)]
+    gap_distance: f64,
+
+    /// Recorded legs slower than this count as stopped, not in the moving time
+    #[arg(long, value_name = "KM/H", default_value_t = 1.)]
+    stop_speed: f64,
+
+    /// Report pauses longer than this between consecutive recorded points as gaps
+    #[arg(long, value_name = "MINUTES", default_value_t = 10)]
+    gap_time: u64,
+
+    /// Split the analysis at recording gaps instead of counting the jump as walked
+    #[arg(long)]
+    split_gaps: bool,
+
+    /// Simplify the track (Douglas-Peucker), dropping points closer than this to the simplified line
+    #[arg(long, value_name = "METERS")]
+    simplify: Option<f64>,
+
+    /// Resample the track to one point every METERS before analysis
+    #[arg(long, value_name = "METERS")]
+    resample: Option<f64>,
+
+    /// Fill in missing elevations from SRTM tiles (downloaded once, then cached)
+    #[arg(long)]
+    dem: bool,
+
+    /// Units of the report and prompts
+    #[arg(long, value_enum, default_value_t = Units::Metric)]
+    units: Units,
+
+    /// Algorithm used for point-to-point distances
+    #[arg(long, value_enum, default_value_t = DistanceAlgorithm::Vincenty)]
+    distance: DistanceAlgorithm,
+
+    /// Take a short break after every this many minutes of walking
+    #[arg(long, value_name = "MINUTES")]
+    break_every: Option<u64>,
+
+    /// Length of the short breaks
+    #[arg(long, value_name = "MINUTES", default_value_t = 10)]
+    break_length: u64,
+
+    /// Take a lunch break after this many minutes of walking
+    #[arg(long, value_name = "MINUTES")]
+    lunch_after: Option<u64>,
+
+    /// Length of the lunch break
+    #[arg(long, value_name = "MINUTES", default_value_t = 30)]
+    lunch_length: u64,
+
+    /// Planned departure, "HH:MM" for today or "YYYY-MM-DD HH:MM" (local time, or --timezone's)
+    #[arg(long, value_name = "TIME", value_parser = parse_start_time)]
+    start: Option<OffsetDateTime>,
+
+    /// Timezone of the route for clock times and GPX timestamps: an offset (+02:00, UTC), a name
+    /// (Europe/Paris) or "auto" for the zone of the first track point
+    #[arg(long, value_name = "ZONE", value_parser = parse_timezone)]
+    timezone: Option<Timezone>,
+
+    /// Walk descents steeper than --descent-grade braking, slower instead of ever faster
+    #[arg(long)]
+    steep_descent: bool,
+
+    /// Grade (%) from which descents are walked braking
+    #[arg(long, value_name = "PERCENT", default_value_t = 20., requires = "steep_descent")]
+    descent_grade: f64,
+
+    /// How fast the pace drops past --descent-grade, 3.5 mirroring the climbing formula
+    #[arg(long, default_value_t = 3.5, requires = "steep_descent")]
+    descent_braking: f64,
+
+    /// Grade (%) from which legs are scrambled rather than walked, e.g. 45
+    #[arg(long, value_name = "PERCENT")]
+    scramble_grade: Option<f64>,
+
+    /// Vertical meters per hour when scrambling
+    #[arg(long, value_name = "M/H", default_value_t = 300., requires = "scramble_grade", value_parser = parse_positive)]
+    scramble_rate: f64,
+
+    /// Ground conditions for the whole trip, sections can be given their own
+    #[arg(long, value_enum, default_value_t = Conditions::Dry)]
+    conditions: Conditions,
+
+    /// Slow the pace down above this altitude, progressively with the height (thinner air)
+    #[arg(long, value_name = "METERS")]
+    altitude_threshold: Option<f64>,
+
+    /// Slowdown per 1000 m above --altitude-threshold
+    #[arg(long, value_name = "PERCENT", default_value_t = 10.)]
+    altitude_penalty: f64,
+
+    /// Walk this many percent slower in the dark, from sunset to sunrise or during --night-hours
+    #[arg(long, value_name = "PERCENT", requires = "start")]
+    night_penalty: Option<f64>,
+
+    /// Darkness as a local clock range, e.g. "21:00-06:00", instead of the sun times
+    #[arg(long, value_name = "HH:MM-HH:MM", value_parser = parse_clock_range, requires = "night_penalty")]
+    night_hours: Option<(Time, Time)>,
+
+    /// Fetch the forecast for the highest point at the time it is reached
+    #[arg(long)]
+    weather: bool,
+
+    /// Slow the pace down for heat and rain from the forecast
+    #[arg(long, requires = "weather")]
+    weather_penalty: bool,
+
+    /// Estimate calories burnt (prompts for the weights not given below)
+    #[arg(long)]
+    energy: bool,
+
+    /// Hiker body weight
+    #[arg(long, value_name = "KG")]
+    body_weight: Option<f64>,
+
+    /// Pack weight, slows the pace down compared to a daypack (or the calibration's pack)
+    #[arg(long, value_name = "KG")]
+    pack_weight: Option<f64>,
+
+    /// List the water and snacks to carry
+    #[arg(long)]
+    provisions: bool,
+
+    /// Water drunk per hour in mild weather
+    #[arg(long, value_name = "LITERS", default_value_t = 0.5)]
+    water_rate: f64,
+
+    /// Snacks eaten per hour
+    #[arg(long, value_name = "GRAMS", default_value_t = 50.)]
+    snack_rate: f64,
+
+    /// Expected temperature, defaults to the forecast when --weather is used
+    #[arg(long, value_name = "CELSIUS")]
+    temperature: Option<f64>,
+
+    /// Slow the pace down by PERCENT per degree above --heat-threshold, twice as much on climbs
+    #[arg(long, value_name = "PERCENT", conflicts_with = "weather_penalty")]
+    heat_penalty: Option<f64>,
+
+    /// Temperature above which --heat-penalty applies
+    #[arg(long, value_name = "CELSIUS", default_value_t = 25.)]
+    heat_threshold: f64,
+
+    /// Group member as NAME=SPEED, SPEED relative to the chosen terrain (0.8 = 20 % slower), repeatable
+    #[arg(long, value_name = "NAME=SPEED", value_parser = parse_hiker)]
+    hiker: Vec<HikerProfile>
+}
+
+#[derive(Clone, Copy)]
+enum Timezone {
+    Fixed(UtcOffset),
+    Named(&'static Tz),
+    // Looked up from the route's first point.
+    Auto
+}
+
+#[derive(PartialEq, Clone, Copy, clap::ValueEnum)]
+enum SortKey {
+    Name,
+    Distance,
+    Time,
+    Effort
+}
+
+fn main() {
+    // Completion scripts call back into the binary with COMPLETE set, it answers and exits.
+    CompleteEnv::with_factory(Args::command).complete();
+
+    let config = config::load_config();
+    i18n::set_language(config.language);
+    let matches = Args::command().get_matches();
+    let mut args = configured_args(&matches, &config);
+    // Files are saved absolute, for the analysis to be run again from any directory.
+    let files: Vec<usize> = matches.indices_of("files").map(Iterator::collect).unwrap_or_default();
+    args.options = std::env::args().enumerate().skip(1)
+        .map(|(i, arg)| if files.contains(&i) { fs::canonicalize(&arg).map_or(arg, |path| path.display().to_string()) } else { arg })
+        .collect();
+    if let Some(shell) = args.completions {
+        print_completions(shell);
+        return;
+    }
+
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_target(false)
+        .without_time()
+        .with_max_level(match (args.quiet, args.verbose) {
+            (true, 0) => Level::ERROR,
+            (false, 0) => Level::WARN,
+            (_, 1) => Level::INFO,
+            _ => Level::DEBUG
+        })
+        .init();
+    if args.quiet {
+        console::set_colors_enabled(false);
+        console::set_colors_enabled_stderr(false);
+    }
+    else {
+        eprintln!("{}", tr("Mountain snail - Hiking time calculator."));
+    }
+
+    if args.history {
+        let Some(entry) = pick_history_entry(&args) else {
+            return;
+        };
+        args = saved_args(&entry.options, &config);
+        if args.files.is_empty() && !args.stdin && args.strava.is_none() && args.komoot.is_none() {
+            args.files.push(entry.file);
+        }
+        if args.tracks.is_empty() {
+            args.tracks = entry.tracks.iter().map(|index| index + 1).collect();
+        }
+        args.terrain = args.terrain.or(entry.terrain);
+    }
+
+    run(&config, args);
+}
+
+// Sources given on the command line, the file picker being shown otherwise.
+fn has_source(args: &Args) -> bool {
+    !args.files.is_empty() || args.stdin || args.strava.is_some() || args.komoot.is_some() || args.batch.is_some() || !args.compare.is_empty()
+}
+
+// Every analysis becomes the last one, offered to be run again in the file picker.
+fn save_last_analysis(file: PathBuf, is_gpx_file: bool, tracks: Vec<usize>, args: &Args) {
+    let last_analysis = LastAnalysis { file, is_gpx_file, tracks, terrain: args.terrain, options: args.options.clone() };
+    if let Err(e) = config::save_state(&config::State { last_analysis: Some(last_analysis) }) {
+        eprintln!("{} {e}", style(tr("Last analysis not saved:")).yellow());
+    }
+}
+
+fn run(config: &Config, mut args: Args) {
+    #[cfg(feature = "tui")]
+    if args.tui {
+        if let Err(e) = tui::run(config, &args) {
+            eprintln!("{} {e}", style(tr("TUI failed:")).red());
+            exit(exit_code::FAILURE);
+        }
+        return;
+    }
+
+    #[cfg(feature = "server")]
+    if let Some(address) = &args.serve {
+        if let Err(e) = server::serve(address, &args) {
+            eprintln!("{} {e}", style(tr("Server failed:")).red());
+            exit(exit_code::FAILURE);
+        }
+        return;
+    }
+
+    if !args.calibrate.is_empty() {
+        calibrate(&args.calibrate, &args);
+        return;
+    }
+
+    if let Some(path) = &args.edit_splits {
+        edit_splits(path, args.units);
+        return;
+    }
+
+    if let Some(dir) = &args.batch {
+        let plan = get_pace_plan(config, &args);
+        analyse_directory(dir, &plan, &args);
+        save_last_analysis(fs::canonicalize(dir).unwrap_or_else(|_| dir.clone()), true, vec![], &args);
+        return;
+    }
+
+    if !args.compare.is_empty() {
+        let plan = get_pace_plan(config, &args);
+        summarise_files(&args.compare, &plan, &args, true);
+        save_last_analysis(fs::canonicalize(&args.compare[0]).unwrap_or_else(|_| args.compare[0].clone()), true, vec![], &args);
+        return;
+    }
+
+    if let Some(source) = &args.strava {
+        eprintln!("{}", tr("Downloading from Strava..."));
+        let gpx = match strava::access_token(config.strava.as_ref()).and_then(|token| strava::download(source, &token)) {
+            Ok(gpx) => gpx,
+            Err(e) => {
+                eprintln!("{} {e}", style(tr("Strava download failed:")).red());
+                exit(exit_code::NETWORK);
+            }
+        };
+
+        let plan = get_pace_plan(config, &args);
+        let tracks = analyse_gpx(gpx, source.file_name(), plan, &args);
+        save_last_analysis(PathBuf::from(source.file_name()), true, tracks, &args);
+        return;
+    }
+
+    if let Some(tour) = &args.komoot {
+        eprintln!("{}", tr("Downloading from Komoot..."));
+        let gpx = match komoot::download(tour) {
+            Ok(gpx) => gpx,
+            Err(e) => {
+                eprintln!("{} {e}", style(tr("Komoot download failed:")).red());
+                exit(exit_code::NETWORK);
+            }
+        };
+
+        let plan = get_pace_plan(config, &args);
+        let tracks = analyse_gpx(gpx, tour.file_name(), plan, &args);
+        save_last_analysis(PathBuf::from(tour.file_name()), true, tracks, &args);
+        return;
+    }
+
+    if args.stdin {
+        let plan = get_pace_plan(config, &args);
+        // Nothing is kept of a streamed document to run the analysis again.
+        if args.stream {
+            analyse_stream("stdin", std::io::stdin().lock(), None, &plan, &args);
+            return;
+        }
+
+        // Kept to be read a second time when lenient.
+        let mut document = Vec::new();
+        if let Err(e) = std::io::Read::read_to_end(&mut std::io::stdin().lock(), &mut document) {
+            eprintln!("{} {e}", style(tr("Error reading GPX from stdin:")).red());
+            exit(exit_code::INPUT);
+        }
+        let gpx = match gpx::read(document.as_slice()).map_err(|e| anyhow::anyhow!("{e}")) {
+            Ok(gpx) => gpx,
+            Err(e) if args.lenient => match read_leniently(&e, document.as_slice()) {
+                Ok(gpx) => gpx,
+                Err(e) => {
+                    eprintln!("{} {e}", style(tr("Error reading GPX from stdin:")).red());
+                    exit(exit_code::INPUT);
+                }
+            },
+            Err(e) => {
+                eprintln!("{} {e}", style(tr("Error reading GPX from stdin:")).red());
+                exit(exit_code::INPUT);
+            }
+        };
+        let tracks = analyse_gpx(gpx, String::from("stdin.gpx"), plan, &args);
+        // The document is kept and run again as a file.
+        match config::save_stdin_copy(&document) {
+            Ok(path) => {
+                args.options.retain(|option| option != "--stdin");
+                save_last_analysis(path, true, tracks, &args);
+            },
+            Err(e) => eprintln!("{} {e}", style(tr("Last analysis not saved:")).yellow())
+        }
+        return;
+    }
+
+    if args.stream && !args.files.is_empty() {
+        let plan = get_pace_plan(config, &args);
+        for file in &args.files {
+            stream_file(file, &plan, &args);
+        }
+        save_last_analysis(fs::canonicalize(&args.files[0]).unwrap_or_else(|_| args.files[0].clone()), true, vec![], &args);
+        return;
+    }
+
+    if args.watch && args.files.len() > 1 {
+        Args::command().error(clap::error::ErrorKind::TooManyValues, "--watch follows a single file").exit();
+    }
+    if args.watch && let [file] = args.files.as_slice() {
+        let plan = get_pace_plan(config, &args);
+        watch(file, true, &plan, &args);
+    }
+
+    if !args.files.is_empty() {
+        let gpx = match merge_files(&args.files, &args) {
+            Ok(gpx) => gpx,
+            Err(e) => {
+                eprintln!("{} {e:#}", style(tr("Error reading GPX file:")).red());
+                exit(exit_code::INPUT);
+            }
+        };
+        if let Some(merged_path) = &args.merged_output {
+            let extras = export::GpxExtras { version: args.gpx_version.unwrap_or_default(), extensions: source_extensions(&args, &merged_path.to_string_lossy()), ..Default::default() };
+            match export::write_gpx(&gpx, &extras, merged_path) {
+                Ok(()) => eprintln!("{} {}", style(tr("Merged GPX written to")).green(), merged_path.display()),
+                Err(e) => eprintln!("{} {e}", style(tr("Failed to write merged GPX:")).red())
+            }
+        }
+
+        let source_path = match args.files.as_slice() {
+            [file] => file.clone(),
+            files => files[0].with_file_name("merged.gpx")
+        };
+        let plan = get_pace_plan(config, &args);
+        let tracks = analyse_gpx(gpx, source_path.to_string_lossy().to_string(), plan, &args);
+        save_last_analysis(fs::canonicalize(&source_path).unwrap_or(source_path), true, tracks, &args);
+        return;
+    }
+
+    let state = config::load_state();
+    // Analyses of other sources (downloads, directories) carry them in their options.
+    let last_analysis = state.last_analysis.filter(|last| last.file.exists() || has_source(&saved_args(&last.options, config)));
+    let (is_gpx_file, file_path, repeat) = get_path(config, last_analysis.as_ref());
+    if repeat && let Some(last) = &last_analysis {
+        // The options given now come after the saved ones and override them.
+        let options: Vec<String> = last.options.iter().cloned().chain(args.options.iter().cloned()).collect();
+        args = saved_args(&options, config);
+        if args.tracks.is_empty() {
+            args.tracks = last.tracks.iter().map(|index| index + 1).collect();
+        }
+        args.terrain = args.terrain.or(last.terrain);
+        if has_source(&args) {
+            return run(config, args);
+        }
+    }
+    let (plan, terrain) = choose_pace_plan(config, &args);
+    // Recorded in the history with the analysis.
+    args.terrain = args.terrain.or(terrain);
+
+    if args.watch {
+        watch(Path::new(&file_path), is_gpx_file, &plan, &args);
+    }
+
+    let mut tracks = vec![];
+    if is_gpx_file && args.stream {
+        stream_file(Path::new(&file_path), &plan, &args);
+    }
+    else if is_gpx_file {
+        let gpx = match read_track_file(Path::new(&file_path), &args) {
+            Ok(gpx) => gpx,
+            Err(e) => {
+                eprintln!("{} {e:#}", style(tr("Error reading GPX file:")).red());
+                exit(exit_code::INPUT);
+            },
+        };
+        tracks = analyse_gpx(gpx, file_path.clone(), plan, &args);
+    }
+    else {
+        analyse_by_splits(file_path.clone(), &plan, &args);
+    }
+    save_last_analysis(PathBuf::from(file_path), is_gpx_file, tracks, &args);
+}
+
+// Supported track files for the FILE arguments, and directories to get to them.
+fn track_file_completer() -> ArgValueCompleter {
+    ArgValueCompleter::new(PathCompleter::any().filter(|path| path.is_dir() || import::is_supported(path)))
+}
+
+// The script registers the binary itself as the completer, so that every shell gets the same
+// candidates, track files included.
+fn print_completions(shell: Shell) {
+    let shells = Shells::builtins();
+    let Some(completer) = shells.completer(&shell.to_string()) else {
+        eprintln!("{} {shell}", style(tr("No completion support for")).red());
+        exit(exit_code::FAILURE);
+    };
+    if let Err(e) = completer.write_registration("COMPLETE", "mountain_snail", "mountain_snail", "mountain_snail", &mut std::io::stdout()) {
+        eprintln!("{} {e}", style(tr("Failed to write the completion script:")).red());
+        exit(exit_code::FAILURE);
+    }
+}
+
+// Arguments from saved options, later ones overriding earlier ones.
+fn saved_args(options: &[String], config: &Config) -> Args {
+    let matches = Args::command()
+        .args_override_self(true)
+        .try_get_matches_from(std::iter::once(String::from("mountain_snail")).chain(options.iter().cloned()))
+        .unwrap_or_else(|e| e.exit());
+    let mut args = configured_args(&matches, config);
+    args.options = options.to_vec();
+    args
+}
+
+// Past analyses, newest first, one of which can be picked to run it again.
+fn pick_history_entry(args: &Args) -> Option<history::HistoryEntry> {
+    let mut entries = match history::load() {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("{} {e}", style(tr("Cannot read the history:")).red());
+            exit(exit_code::INPUT);
+        }
+    };
+    if entries.is_empty() {
+        eprintln!("{}", tr("No analysis recorded yet."));
+        return None;
+    }
+    entries.reverse();
+
+    let units = args.units;
+    let lines: Vec<String> = entries.iter()
+        .map(|entry| format!("{}  {} · {}  {} +{}  {}",
+            entry.date.get(..16).unwrap_or(&entry.date).replace('T', " "),
+            entry.file.file_name().unwrap_or_default().to_string_lossy(),
+            entry.track,
+            units.format_distance(entry.distance, 1),
+            units.format_elevation(entry.d_plus),
+            utils::format_duration(Duration::from_secs(entry.walking_s + entry.breaks_s))
+        ))
+        .collect();
+    if args.quiet {
+        for line in &lines {
+            println!("{line}");
+        }
+        return None;
+    }
+
+    let index = dialoguer::FuzzySelect::with_theme(&dialoguer::theme::ColorfulTheme::default())
+        .with_prompt(tr("Run an analysis again (Esc to quit)"))
+        .items(&lines)
+        .default(0)
+        .interact_opt()
+        .or_exit()?;
+    Some(entries.swap_remove(index))
+}
+
+// Arguments completed by config.toml, the command line winning.
+fn configured_args(matches: &ArgMatches, config: &Config) -> Args {
+    let mut args = Args::from_arg_matches(matches).unwrap_or_else(|e| e.exit());
+    if config.format == OutputFormat::Porcelain {
+        args.quiet = true;
+    }
+    if matches.value_source("split_length") == Some(ValueSource::DefaultValue)
+        && let Some(split_length) = config.split_length {
+        args.split_length = split_length;
+    }
+    if config.format == OutputFormat::Json {
+        args.json = true;
+    }
+    if matches.value_source("units") == Some(ValueSource::DefaultValue) {
+        args.units = config.units;
+    }
+    // Imperial splits default to a mile.
+    if args.units == Units::Imperial && config.split_length.is_none()
+        && matches.value_source("split_length") == Some(ValueSource::DefaultValue) {
+        args.split_length = 1609;
+    }
+    args.calibrated = config::load_profiles().calibrated().cloned();
+    args
+}
+
+// Returns the indices of the analysed tracks.
+fn analyse_gpx(mut gpx: Gpx, gpx_file_path: String, mut plan: PacePlan, args: &Args) -> Vec<usize> {
+    // Standard input holds the document, or the file is being watched: prompts take their default answer.
+    let interactive = !args.stdin && !args.watch;
+    info!("GPX file has {} track(s), {} route(s)", gpx.tracks.len(), gpx.routes.len());
+
+    let mut track_indices: Vec<usize> = vec![0];
+    if !args.tracks.is_empty() {
+        track_indices = args.tracks.iter()
+            .filter(|number| (1..=gpx.tracks.len()).contains(*number))
+            .map(|number| number - 1)
+            .collect();
+        if track_indices.len() < args.tracks.len() {
+            eprintln!("  {} {} {}", style(tr("Tracks ignored:")).yellow(), tr("tracks in the file:"), gpx.tracks.len());
+        }
+        if track_indices.is_empty() {
+            track_indices.push(0);
+        }
+    }
+    else if gpx.tracks.len() > 1 {
+        let names: Vec<String> = gpx.tracks.iter().map(|track| track.name.clone().unwrap_or_default()).collect();
+        // Files given on the command line form one itinerary, every track is selected.
+        let mut defaults = vec![args.files.len() > 1; names.len()];
+        defaults[0] = true;
+
+        track_indices = if interactive {
+            MultiSelect::with_theme(&dialoguer::theme::ColorfulTheme::default())
+                .with_prompt(tr("Select GPX track(s)"))
+                .items(&names)
+                .defaults(&defaults)
+                .interact()
+                .or_exit()
+        }
+        else {
+            (0..names.len()).filter(|index| defaults[*index]).collect()
+        };
+        if track_indices.is_empty() {
+            track_indices.push(0);
+        }
+    }
+
+    let track_names: Vec<String> = track_indices.iter()
+        .map(|index| match &gpx.tracks[*index].name { Some(name) => name.clone(), None => String::from(tr("Default")) })
+        .collect();
+    let track_name = track_names.join(" + ");
+    for (index, name) in track_indices.iter().zip(&track_names) {
+        eprintln!("{} {} {}",
+            style(tr("Chosen track:")).bold(),
+            style("·").black().bright(),
+            style(format!("\"{}\" ({}{})", name, tr("track n°"), index + 1)).green()
+        );
+    }
+
+    let edit_track_times = interactive && dialoguer::Confirm::with_theme(&dialoguer::theme::ColorfulTheme::default())
+        .with_prompt(tr("Add time to GPX points ?"))
+        .interact()
+        .or_exit();
+
+    // Several tracks are analysed as one itinerary, each keeping its own segments. The segments are
+    // moved out of the document rather than copied, and put back before writing the timed GPX.
+    let mut segment_counts: Vec<usize> = track_indices.iter().map(|index| gpx.tracks[*index].segments.len()).collect();
+    let mut track = Track::new();
+    track.name = gpx.tracks[track_indices[0]].name.clone();
+    for index in &track_indices {
+        track.segments.append(&mut gpx.tracks[*index].segments);
+    }
+
+    if args.dem {
+        eprintln!("  {}", tr("Looking up elevations..."));
+        match dem::fill_elevations(&mut track) {
+            Ok(filled) => eprintln!("    {} {} {}", style(">").blue(), filled, tr("point elevation(s) filled")),
+            Err(e) => eprintln!("  {} {e}", style(tr("Elevation lookup failed:")).red())
+        }
+    }
+    else if track.segments.iter().all(|segment| segment.points.iter().all(|point| point.elevation.is_none())) {
+        eprintln!("  {}", style(tr("Track has no elevation data, slopes will be ignored (use --dem to fill them in).")).yellow());
+    }
+
+    if args.remove_outliers {
+        let removed = cleaning::remove_outliers(&mut track, args.max_speed);
+        eprintln!("  {} {} {} {}", style(">").blue(), tr("Outliers:"), removed, tr("point(s) removed"));
+    }
+
+    if args.from_km.is_some() || args.to_km.is_some() || args.from_waypoint.is_some() || args.to_waypoint.is_some() {
+        let waypoint_km = |name: &String| -> Option<f64> {
+            let waypoint = gpx.waypoints.iter().find(|waypoint| waypoint.name.as_ref() == Some(name));
+            if waypoint.is_none() {
+                eprintln!("  {} {} \"{name}\"", style(tr("Crop ignored:")).yellow(), tr("no waypoint named"));
+            }
+            cleaning::waypoint_km(&track, waypoint?, args.distance)
+        };
+        let from_km = args.from_waypoint.as_ref().and_then(waypoint_km).or(args.from_km).unwrap_or(0.);
+        let to_km = args.to_waypoint.as_ref().and_then(waypoint_km).or(args.to_km).unwrap_or(f64::MAX);
+
+        let removed = cleaning::crop(&mut track, from_km, to_km, args.distance);
+        eprintln!("  {} {} {:.1} - {}: {} {}", style(">").blue(),
+            tr("Cropped to km"),
+            from_km,
+            if to_km == f64::MAX { String::from(tr("end")) } else { format!("{to_km:.1}") },
+            removed,
+            tr("point(s) removed")
+        );
+        if track.segments.iter().all(|segment| segment.points.is_empty()) {
+            eprintln!("{}", style(tr("Nothing left of the track after cropping.")).red());
+            exit(exit_code::INPUT);
+        }
+    }
+
+    let gaps = cleaning::find_gaps(&track, args.gap_distance, Duration::from_secs(args.gap_time * 60));
+    for gap in &gaps {
+        eprintln!("  {} {} {:.1}: {:.0} m{}", style(">").yellow(),
+            tr("Recording gap at km"),
+            gap.at_km,
+            gap.distance_m,
+            gap.duration.map(|duration| format!(" {} {}", tr("in"), utils::format_duration(duration))).unwrap_or_default()
+        );
+    }
+    if args.split_gaps && !gaps.is_empty() {
+        // Each track gains one segment per gap it contains.
+        let mut first_segment = 0;
+        for count in segment_counts.iter_mut() {
+            let segments = first_segment..first_segment + *count;
+            first_segment = segments.end;
+            *count += gaps.iter().filter(|gap| segments.contains(&gap.segment)).count();
+        }
+        cleaning::split_at_gaps(&mut track, &gaps);
+        eprintln!("  {} {} {} {}", style(">").blue(), tr("Track split at"), gaps.len(), tr("gap(s), the jumps are not counted"));
+    }
+
+    if let Some(tolerance) = args.simplify {
+        let before: usize = track.segments.iter().map(|segment| segment.points.len()).sum();
+        let removed = cleaning::simplify(&mut track, tolerance);
+        eprintln!("  {} {} {} / {} {}", style(">").blue(), tr("Simplified:"), removed, before, tr("point(s) removed"));
+    }
+
+    if let Some(spacing) = args.resample && spacing > 0. {
+        let before: usize = track.segments.iter().map(|segment| segment.points.len()).sum();
+        let after = cleaning::resample(&mut track, spacing, args.distance);
+        eprintln!("  {} {} {} m: {} {} {}", style(">").blue(), tr("Resampled every"), spacing, after, tr("point(s) instead of"), before);
+    }
+
+    let detect_terrain = interactive && dialoguer::Confirm::with_theme(&dialoguer::theme::ColorfulTheme::default())
+        .with_prompt(tr("Detect terrain from OpenStreetMap ?"))
+        .default(false)
+        .interact()
+        .or_exit();
+    if detect_terrain {
+        eprintln!("  {}", tr("Querying OpenStreetMap..."));
+        match osm::detect_terrain(&track, 1.) {
+            Ok(sections) => {
+                for section in &sections {
+                    eprintln!("    {} {} km - {} km: {}", style(">").blue(),
+                        (section.from_km * 10.).round() / 10.,
+                        (section.to_km * 10.).round() / 10.,
+                        style(section.terrain.name()).bold()
+                    );
+                }
+
+                // Sections entered by hand keep precedence over detected ones.
+                let detected = sections.into_iter().map(|section| PaceSection {
+                    from_km: section.from_km,
+                    to_km: section.to_km,
+                    model: Rc::new(ExponentialPace::new(get_speed_adjustement(section.terrain, args.calibrated.as_ref()) as f64))
+                });
+                plan.sections.splice(0..0, detected);
+            },
+            Err(e) => eprintln!("  {} {e}", style(tr("Terrain detection failed:")).red())
+        }
+    }
+    
+    plan = adjusted_plan(plan, args);
+
+    // Read before timestamps get rewritten by the analysis.
+    let recorded = utils::recorded_times(&track, args.distance, args.stop_speed);
+
+    let first = track.segments.iter().find_map(|segment| segment.points.first()).cloned();
+    let mut options = analysis_options(args, first.as_ref(), edit_track_times);
+    // Clock times of the report are given from the departure resolved for the analysis.
+    let args = &Args { start: options.start, ..args.clone() };
+    let mut stats = read_gpx(&mut track, &plan, &options);
+
+    let mut forecast = None;
+    let mut weather_delay = Duration::ZERO;
+    if args.weather && let Some(summit) = stats.points.iter().filter(|point| point.elevation.is_some()).max_by(|a, b| a.elevation.unwrap_or(0.).total_cmp(&b.elevation.unwrap_or(0.))) {
+        let summit_time = args.start.unwrap_or_else(OffsetDateTime::now_utc) + summit.elapsed;
+        match weather::forecast(summit.latitude, summit.longitude, summit.elevation.unwrap_or(0.), summit_time) {
+            Ok(summit_forecast) => {
+                let penalty = summit_forecast.speed_penalty();
+                if args.weather_penalty && penalty > 1. {
+                    let base_duration = stats.duration;
+                    plan = plan.scaled(penalty);
+                    stats = read_gpx(&mut track, &plan, &options);
+                    weather_delay = stats.duration - base_duration;
+                }
+                // The forecast stands in for --temperature.
+                if args.temperature.is_none() && let Some(heat) = heat_penalty(args, Some(summit_forecast.temperature)) {
+                    options.heat = Some(heat);
+                    stats = read_gpx(&mut track, &plan, &options);
+                }
+                forecast = Some(summit_forecast);
+            },
+            Err(e) => eprintln!("  {} {e}", style(tr("Weather forecast unavailable:")).red())
+        }
+    }
+    // Plan for the group's slowest member.
+    let mut group_times: Vec<(&HikerProfile, Duration)> = vec![];
+    if !args.hiker.is_empty() {
+        let hiker_options = AnalysisOptions { edit_track_times: false, ..options.clone() };
+        group_times = args.hiker.iter()
+            .map(|hiker| (hiker, read_gpx(&mut track, &plan.scaled(1. / hiker.speed_factor), &hiker_options).duration))
+            .collect();
+
+        if let Some((slowest, _)) = group_times.iter().max_by_key(|(_, duration)| *duration) {
+            plan = plan.scaled(1. / slowest.speed_factor);
+            stats = read_gpx(&mut track, &plan, &options);
+        }
+    }
+
+    if args.energy {
+        let weight = |given: Option<f64>, prompt: &str, default: f64| given.unwrap_or_else(|| if interactive { prompt_weight(prompt, default) } else { default });
+        let body_weight = weight(args.body_weight, "Body weight (kg):", 75.);
+        let pack_weight = weight(args.pack_weight, "Pack weight (kg):", 8.);
+        stats.energy = Some(utils::energy_expenditure(&stats, body_weight, pack_weight));
+    }
+    stats.checkpoints = utils::checkpoints(&gpx.waypoints, &stats.points);
+    stats.recorded = recorded;
+    stats.grade_distribution = utils::grade_distribution(&stats.points);
+    stats.grade_zones = utils::grade_zones(&stats.points, args.grade_zones[0], args.grade_zones[1]);
+    stats.ascent_rate = utils::ascent_rate(&stats.points, args.grade_zones[1]);
+    stats.best_vam = utils::best_vam(&stats.points, Duration::from_secs(args.vam_window * 60));
+    (stats.steepest_climb, stats.steepest_descent) = utils::steepest_sections(&stats.points, args.steep_window / 1000.);
+    stats.climbs = utils::climbs(&stats.points);
+    stats.shape = utils::route_shape(&stats.points);
+    stats.sac_estimate = utils::sac_estimate(&stats);
+    stats.effort = utils::effort_score(&stats);
+    if args.hourly {
+        stats.hourly = utils::hourly_positions(&stats.points, &stats.checkpoints);
+    }
+    let escapes: Vec<&str> = gpx.waypoints.iter()
+        .filter(|waypoint| waypoint.type_.as_deref().is_some_and(|kind| ["escape", "bail-out", "bailout"].contains(&kind.to_lowercase().as_str())))
+        .filter_map(|waypoint| waypoint.name.as_deref())
+        .chain(args.bail_out.iter().map(String::as_str))
+        .collect();
+    stats.bail_outs = stats.checkpoints.iter()
+        .filter(|checkpoint| escapes.contains(&checkpoint.name.as_str()))
+        .filter_map(|checkpoint| utils::bail_out(checkpoint, &stats.points, &plan, args.escape_grade / 100.))
+        .collect();
+    for name in &args.bail_out {
+        if !stats.checkpoints.iter().any(|checkpoint| checkpoint.name == *name) {
+            eprintln!("  {} {} \"{name}\"", style(tr("Bail-out ignored:")).yellow(), tr("no waypoint named"));
+        }
+    }
+    if args.stages {
+        // Named waypoints mark the end of each stage, otherwise they are asked for.
+        let mut ends: Vec<(String, f64)> = stats.checkpoints.iter().map(|checkpoint| (checkpoint.name.clone(), checkpoint.distance)).collect();
+        if ends.is_empty() && interactive {
+            let input: String = dialoguer::Input::with_theme(&dialoguer::theme::ColorfulTheme::default())
+                .with_prompt(format!("{} ({}, {}):", tr("Stage ends"), args.units.distance_unit(), tr("comma separated")))
+                .validate_with(|input: &String| -> Result<(), String> {
+                    input.split(',').try_for_each(|km| km.trim().parse::<f64>().map(|_| ()).map_err(|e| format!("\"{km}\": {e}")))
+                })
+                .interact_text()
+                .or_exit();
+            ends = input.split(',')
+                .enumerate()
+                .map(|(i, distance)| (format!("{} {}", tr("Stage"), i + 1), args.units.to_km(distance.trim().parse::<f64>().unwrap())))
+                .collect();
+        }
+        stats.stages = utils::stages(&stats.points, &ends);
+
+        let write_stages = interactive && dialoguer::Confirm::with_theme(&dialoguer::theme::ColorfulTheme::default())
+            .with_prompt(tr("Write one GPX file per stage ?"))
+            .default(false)
+            .interact()
+            .or_exit();
+        if write_stages {
+            write_stage_gpx(&track, &stats.stages, &gpx_file_path, args);
+        }
+    }
+    stats.splits = utils::distance_splits(&stats.points, args.split_length as f64 / 1000.);
+    options.edit_track_times = false;
+    let reverse_stats = args.reverse.then(|| read_gpx(&mut utils::reversed(&track), &plan.mirrored(stats.distance), &options));
+    // The way back leaves at the turnaround's clock time, breaks going on from the outbound ones.
+    let return_options = AnalysisOptions {
+        start: Some(options.start.unwrap_or_else(OffsetDateTime::now_utc) + stats.duration + stats.break_duration),
+        walked: stats.duration,
+        ..options.clone()
+    };
+    let return_stats = args.round_trip.then(|| read_gpx(&mut utils::reversed(&track), &plan.mirrored(stats.distance), &return_options));
+    if track_indices.len() > 1 {
+        stats.tracks = utils::group_segments(&stats.segments, &segment_counts);
+    }
+
+    if edit_track_times {
+        let mut segments = track.segments.into_iter();
+        for (index, count) in track_indices.iter().zip(&segment_counts) {
+            gpx.tracks[*index].segments = segments.by_ref().take(*count).collect();
+        }
+        let eta = stats.duration + stats.break_duration;
+        set_gpx_metadata(&mut gpx, &track_indices, &track_name, eta, args);
+
+        // Points of the analysed tracks, in the order they were analysed.
+        let mut remaining = stats.points.iter();
+        let etas: Vec<(usize, Vec<&utils::PointStats>)> = if args.gpx_eta {
+            track_indices.iter()
+                .map(|index| {
+                    let count = gpx.tracks[*index].segments.iter().map(|segment| segment.points.len()).sum();
+                    (*index, remaining.by_ref().take(count).collect())
+                })
+                .collect()
+        }
+        else {
+            vec![]
+        };
+        write_timed_gpx(&gpx, &etas, &gpx_file_path, args);
+    }
+
+    if let Some(csv_path) = &args.csv {
+        match export::write_csv(&stats, csv_path) {
+            Ok(()) => eprintln!("{} {}", style(tr("CSV written to")).green(), csv_path.display()),
+            Err(e) => eprintln!("{} {e}", style(tr("Failed to write CSV:")).red())
+        }
+    }
+
+    if let Some(splits_path) = &args.write_splits {
+        match export::write_splits(&stats.splits, args.split_length as f64, splits_path) {
+            Ok(()) => eprintln!("{} {}", style(tr("Splits written to")).green(), splits_path.display()),
+            Err(e) => eprintln!("{} {e}", style(tr("Failed to write splits:")).red())
+        }
+    }
+
+    if let Some(markdown_path) = &args.markdown {
+        match export::write_markdown(&stats, &track_name, args.start, markdown_path) {
+            Ok(()) => eprintln!("{} {}", style(tr("Markdown report written to")).green(), markdown_path.display()),
+            Err(e) => eprintln!("{} {e}", style(tr("Failed to write Markdown report:")).red())
+        }
+    }
+
+    if let Some(kml_path) = &args.kml {
+        match export::write_kml(&stats, &track_name, args.start, kml_path) {
+            Ok(()) => eprintln!("{} {}", style(tr("KML written to")).green(), kml_path.display()),
+            Err(e) => eprintln!("{} {e}", style(tr("Failed to write KML:")).red())
+        }
+    }
+
+    if let Some(ics_path) = &args.ics && let Some(start) = args.start {
+        match export::write_ics(&stats, &track_name, start, ics_path) {
+            Ok(()) => eprintln!("{} {}", style(tr("Calendar written to")).green(), ics_path.display()),
+            Err(e) => eprintln!("{} {e}", style(tr("Failed to write calendar:")).red())
+        }
+    }
+
+    if let Some(geojson_path) = &args.geojson {
+        match export::write_geojson(&stats, &track_name, geojson_path) {
+            Ok(()) => eprintln!("{} {}", style(tr("GeoJSON written to")).green(), geojson_path.display()),
+            Err(e) => eprintln!("{} {e}", style(tr("Failed to write GeoJSON:")).red())
+        }
+    }
+
+    if let Some(png_path) = &args.profile_png {
+        match plot::write_profile_png(&stats, &track_name, args.start, args.units, png_path) {
+            Ok(()) => eprintln!("{} {}", style(tr("Profile written to")).green(), png_path.display()),
+            Err(e) => eprintln!("{} {e}", style(tr("Failed to draw profile:")).red())
+        }
+    }
+
+    if let Some(svg_path) = &args.profile_svg {
+        match plot::write_profile_svg(&stats, &track_name, args.start, args.units, svg_path) {
+            Ok(()) => eprintln!("{} {}", style(tr("Profile written to")).green(), svg_path.display()),
+            Err(e) => eprintln!("{} {e}", style(tr("Failed to draw profile:")).red())
+        }
+    }
+
+    if let Some(html_path) = &args.html_report {
+        match export::write_html_report(&stats, &track_name, html_path) {
+            Ok(()) => eprintln!("{} {}", style(tr("HTML report written to")).green(), html_path.display()),
+            Err(e) => eprintln!("{} {e}", style(tr("Failed to write HTML report:")).red())
+        }
+    }
+
+    // Watching re-analyses the file on every change.
+    if !args.watch {
+        let offset = UtcOffset::current_local_offset().unwrap_or(UtcOffset::UTC);
+        let entry = history::HistoryEntry {
+            date: OffsetDateTime::now_utc().to_offset(offset).format(&time::format_description::well_known::Rfc3339).unwrap_or_default(),
+            file: fs::canonicalize(&gpx_file_path).unwrap_or_else(|_| PathBuf::from(&gpx_file_path)),
+            track: track_name.clone(),
+            tracks: track_indices.clone(),
+            terrain: args.terrain,
+            options: args.options.clone(),
+            distance: stats.distance,
+            d_plus: stats.d_plus,
+            d_minus: stats.d_minus,
+            walking_s: stats.duration.as_secs(),
+            breaks_s: stats.break_duration.as_secs()
+        };
+        if let Err(e) = history::record(&entry) {
+            eprintln!("{} {e}", style(tr("Analysis not added to the history:")).yellow());
+        }
+    }
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&stats).unwrap());
+        return track_indices;
+    }
+    if args.quiet {
+        print_porcelain(&stats, args.start);
+        return track_indices;
+    }
+
+    let units = args.units;
+    for (name, track_stats) in track_names.iter().zip(&stats.tracks) {
+        println!("  {} {}", style(tr("Track")).bold(), style(name).green());
+        println!("    {} {} - {} D+ {} D- - {}", style(">").blue(),
+            units.format_distance(track_stats.distance, 2),
+            units.format_elevation(track_stats.d_plus),
+            units.format_elevation(track_stats.d_minus),
+            UptimeFull::from(track_stats.duration)
+        );
+    }
+
+    println!("  {}", style(tr(if stats.tracks.is_empty() { "Track info:" } else { "Combined total:" })).bold());
+    println!("    {} {} D+ {} D-", style(">").blue(), units.format_elevation(stats.d_plus), units.format_elevation(stats.d_minus));
+    match stats.shape {
+        Some(shape) => println!("    {} {} ({})", style(">").blue(), units.format_distance(stats.distance, 2), tr(shape.name())),
+        None => println!("    {} {}", style(">").blue(), units.format_distance(stats.distance, 2))
+    }
+    if stats.shape == Some(utils::RouteShape::PointToPoint) && !args.round_trip {
+        println!("    {} {}", style("!").yellow().bold(), style(tr("The finish is away from the start, plan the return transport.")).yellow());
+    }
+    println!("    {} {}: {} - {}", style(">").blue(), tr("Range"), units.format_elevation(stats.min_height), units.format_elevation(stats.max_height));
+    print_missing_elevations(&stats, args);
+    if stats.break_duration.is_zero() {
+        println!("    {} {}: {}", style(">").blue(), tr("Time"), UptimeFull::from(stats.duration));
+    }
+    else {
+        println!("    {} {}: {} {}, {} {}", style(">").blue(),
+            tr("Time"),
+            UptimeFull::from(stats.duration),
+            tr("walking"),
+            style(UptimeFull::from(stats.duration + stats.break_duration)).bold(),
+            tr("with breaks")
+        );
+    }
+    if !stats.grade_adjusted_pace.is_zero() {
+        println!("    {} {}: {}", style(">").blue(), tr("Grade-adjusted pace"), units.format_pace(stats.grade_adjusted_pace));
+    }
+    if !stats.uphill_duration.is_zero() || !stats.downhill_duration.is_zero() {
+        println!("    {} {}: {}, {}: {}", style(">").blue(),
+            tr("Uphill"),
+            utils::format_duration(stats.uphill_duration),
+            tr("downhill"),
+            utils::format_duration(stats.downhill_duration)
+        );
+    }
+    if !stats.night_delay.is_zero() {
+        println!("    {} {}: +{}", style(">").blue(), tr("Night penalty"), utils::format_duration(stats.night_delay));
+    }
+    if !stats.heat_delay.is_zero() {
+        println!("    {} {}: +{}", style(">").blue(), tr("Heat penalty"), utils::format_duration(stats.heat_delay));
+    }
+    if let Some(start) = args.start {
+        println!("    {} {}: {} - {}: {}", style(">").blue(),
+            tr("Departure"),
+            utils::format_clock(start, Duration::ZERO),
+            tr("Arrival"),
+            style(utils::format_clock(start, stats.duration + stats.break_duration)).bold()
+        );
+    }
+    if !group_times.is_empty() {
+        let slowest = group_times.iter().map(|(_, duration)| *duration).max().unwrap_or_default();
+        let name_width = group_times.iter().map(|(hiker, _)| hiker.name.chars().count()).max().unwrap_or(0);
+        println!("    {} {}", style(">").blue(), tr("Group:"));
+        for (hiker, duration) in &group_times {
+            if *duration == slowest {
+                println!("        {:<name_width$}  {}  {}", hiker.name, style(utils::format_duration(*duration)).bold(), style(tr("(slowest, used for the plan)")).yellow());
+            }
+            else {
+                println!("        {:<name_width$}  {}", hiker.name, utils::format_duration(*duration));
+            }
+        }
+    }
+    if let Some(recorded) = stats.recorded {
+        let error = |predicted: Duration, actual: Duration| (predicted.as_secs_f64() / actual.as_secs_f64() - 1.) * 100.;
+        println!("    {} {}: {} {} ({:+.0} % {}), {} {} ({:+.0} % {})", style(">").blue(),
+            tr("Recorded"),
+            utils::format_duration(recorded.moving),
+            tr("moving"),
+            error(stats.duration, recorded.moving),
+            tr("predicted"),
+            utils::format_duration(recorded.elapsed),
+            tr("elapsed"),
+            error(stats.duration + stats.break_duration, recorded.elapsed),
+            tr("predicted")
+        );
+    }
+    if let Some(energy) = stats.energy {
+        println!("    {} {}: ~{} kcal", style(">").blue(), tr("Energy"), (energy / 10.).round() * 10.);
+    }
+    if let Some(forecast) = &forecast {
+        println!("    {} {}: {:.0} °C, {:.1} mm/h, {:.0} km/h", style(">").blue(),
+            tr("Weather at the highest point"),
+            forecast.temperature, forecast.precipitation, forecast.wind_speed);
+        if !weather_delay.is_zero() {
+            println!("    {} {}: +{}", style(">").blue(), tr("Heat/rain penalty"), utils::format_duration(weather_delay));
+        }
+    }
+    if args.provisions {
+        let temperature = args.temperature.or(forecast.as_ref().map(|forecast| forecast.temperature)).unwrap_or(20.);
+        let provisions = utils::provisions(&stats, args.water_rate, args.snack_rate, temperature);
+        println!("    {} {}: {:.1} L, {:.0} g", style(">").blue(),
+            tr("Provisions"),
+            (provisions.water * 2.).ceil() / 2.,
+            (provisions.snacks / 50.).ceil() * 50.
+        );
+    }
+    if let Some(start) = args.start && let Some(first) = stats.points.first() {
+        match sun::sun_times(start.date(), first.latitude, first.longitude) {
+            Some((sunrise, sunset)) => {
+                let finish = start + stats.duration + stats.break_duration;
+                println!("    {} {}: {} - {}: {}", style(">").blue(),
+                    tr("Sunrise"),
+                    utils::format_clock(sunrise.to_offset(start.offset()), Duration::ZERO),
+                    tr("Sunset"),
+                    utils::format_clock(sunset.to_offset(start.offset()), Duration::ZERO)
+                );
+
+                let dark = sun::darkness(start, finish, sunrise, sunset);
+                if !dark.is_zero() {
+                    println!("    {} {}", style("!").yellow().bold(), style(format!("{} {}", utils::format_duration(dark), tr("of walking in the dark, bring a headlamp."))).yellow());
+                }
+            },
+            None => println!("    {} {}", style(">").blue(), tr("No sunrise or sunset on that day (polar day or night)."))
+        }
+    }
+    println!("    {} {}: {} ({} {}, {} {} - {})", style(">").blue(),
+        tr("Average altitude"),
+        units.format_elevation(stats.average_altitude),
+        tr("median"),
+        units.format_elevation(stats.median_altitude),
+        tr("quartiles"),
+        units.format_elevation(stats.lower_quartile_altitude),
+        units.format_elevation(stats.upper_quartile_altitude)
+    );
+
+    println!("  {}", style(tr("Grades:")).bold());
+    let longest = stats.grade_distribution.iter().map(|bucket| bucket.distance).fold(0., f64::max);
+    for bucket in &stats.grade_distribution {
+        let label = match (bucket.from, bucket.to) {
+            (None, Some(to)) => format!("< {to} %"),
+            (Some(from), None) => format!("> {from} %"),
+            (Some(from), Some(to)) => format!("{from} {} {to} %", tr("to")),
+            (None, None) => String::new()
+        };
+        println!("    {} {:>12} {:>9} {}", style(">").blue(), label, units.format_distance(bucket.distance, 1), charts::bar(bucket.distance, longest, 30));
+    }
+    let zones = &stats.grade_zones;
+    let walking = (zones.climbing + zones.flat + zones.descending).as_secs_f64().max(1.);
+    let share = |duration: Duration| format!("{} ({:.0} %)", utils::format_duration(duration), duration.as_secs_f64() / walking * 100.);
+    println!("    {} {}: {}, {}: {}, {}: {}", style(">").blue(),
+        tr("Climbing"),
+        share(zones.climbing),
+        tr("flat"),
+        share(zones.flat),
+        tr("descending"),
+        share(zones.descending)
+    );
+    if let Some(ascent_rate) = stats.ascent_rate {
+        println!("    {} {}: {}/h", style(">").blue(), tr("Ascent rate"), units.format_elevation(ascent_rate));
+    }
+    if let Some(vam) = stats.best_vam {
+        println!("    {} {}: {}/h, {} {:.1} - {:.1} ({} min)", style(">").blue(),
+            tr("Best VAM"),
+            units.format_elevation(vam.rate),
+            units.distance_unit(),
+            units.distance(vam.from_km),
+            units.distance(vam.to_km),
+            args.vam_window
+        );
+    }
+
+    println!("    {} {}: {:.1}", style(">").blue(), tr("Effort score"), stats.effort);
+    if let Some(sac) = stats.sac_estimate {
+        println!("    {} {}: {} {}", style(">").blue(), tr("Difficulty"), style(format!("T{sac}")).bold(), style(tr("(estimate only, from slopes and altitude)")).dim());
+    }
+    for (label, section) in [(tr("Steepest climb"), stats.steepest_climb), (tr("Steepest descent"), stats.steepest_descent)] {
+        if let Some(section) = section {
+            println!("    {} {}: {:+.0} % {} {}, {} {:.1} - {:.1} ({} - {})", style(">").blue(),
+                label,
+                section.grade,
+                tr("over"),
+                units.format_distance(section.to_km - section.from_km, 2),
+                units.distance_unit(),
+                units.distance(section.from_km),
+                units.distance(section.to_km),
+                units.format_elevation(section.from_elevation),
+                units.format_elevation(section.to_elevation)
+            );
+        }
+    }
+
+    if !stats.stages.is_empty() {
+        println!("  {}", style(tr("Stages:")).bold());
+        for (i, stage) in stats.stages.iter().enumerate() {
+            println!("    {} {} {}: {}  {} D+  {} D-  {}", style(">").blue(),
+                i + 1,
+                style(&stage.name).green(),
+                units.format_distance(stage.stats.distance, 1),
+                units.format_elevation(stage.stats.d_plus),
+                units.format_elevation(stage.stats.d_minus),
+                utils::format_duration(stage.stats.duration)
+            );
+        }
+    }
+
+    if !stats.climbs.is_empty() {
+        println!("  {}", style(tr("Climbs:")).bold());
+        for climb in &stats.climbs {
+            println!("    {} {} {:>5.1}  {:>8}  {:>7} D+  {:>4.1} %  {}  {}", style(">").blue(),
+                units.distance_unit(),
+                units.distance(climb.from_km),
+                units.format_distance(climb.to_km - climb.from_km, 2),
+                units.format_elevation(climb.gain),
+                climb.grade,
+                utils::format_duration(climb.duration),
+                climb.category.map(|category| style(category).bold().to_string()).unwrap_or_default()
+            );
+        }
+    }
+
+    if args.segments {
+        println!("  {}", style(tr("Segments:")).bold());
+        for (i, segment) in stats.segments.iter().enumerate() {
+            println!("    {} #{:<3} {:>6} {}  {:>9}  {:>7} D+  {:>7} D-  {}",
+                style(">").blue(),
+                i + 1,
+                segment.points,
+                tr("points"),
+                units.format_distance(segment.distance, 2),
+                units.format_elevation(segment.d_plus),
+                units.format_elevation(segment.d_minus),
+                style(utils::format_duration(segment.duration)).bold()
+            );
+        }
+    }
+
+    if !stats.splits.is_empty() {
+        println!("  {}", style(format!("{} ({}):", tr("Splits"), units.format_distance(args.split_length as f64 / 1000., 2))).bold());
+        let mut split_number = [0, 1];
+        for split in &stats.splits {
+            println!("    {} : {} -- {}{}  {}",
+                style(format!("{split_number:?}")).dim(),
+                split.duration.human(humanize_duration::Truncate::Second),
+                split.total_duration.human(humanize_duration::Truncate::Second),
+                clock_suffix(args, split.total_duration),
+                style(format!("GAP {}", units.format_pace(split.grade_adjusted_pace))).dim()
+            );
+
+            split_number[0] += 1;
+            split_number[1] += 1;
+        }
+    }
+
+    if !stats.checkpoints.is_empty() {
+        println!("  {}", style(tr("Checkpoints:")).bold());
+        let name_width = stats.checkpoints.iter().map(|checkpoint| checkpoint.name.chars().count()).max().unwrap_or(0);
+        for checkpoint in &stats.checkpoints {
+            println!("    {} {:<name_width$}  {:>9}  {:>7} D+  {}{}",
+                style(">").blue(),
+                checkpoint.name,
+                units.format_distance(checkpoint.distance, 2),
+                units.format_elevation(checkpoint.d_plus),
+                style(format!("{}{}", utils::format_duration(checkpoint.eta), clock_suffix(args, checkpoint.eta))).bold(),
+                if checkpoint.offset > 200. { style(format!("  ({} {})", units.format_elevation(checkpoint.offset), tr("off track"))).dim().to_string() } else { String::new() }
+            );
+        }
+    }
+
+    if !stats.bail_outs.is_empty() {
+        println!("  {}", style(tr("Bail-out points:")).bold());
+        let name_width = stats.bail_outs.iter().map(|bail_out| bail_out.name.chars().count()).max().unwrap_or(0);
+        for bail_out in &stats.bail_outs {
+            println!("    {} {:<name_width$}  {:>9}  {}{}  {} {} ({}), {} {}",
+                style(">").blue(),
+                bail_out.name,
+                units.format_distance(bail_out.distance, 2),
+                utils::format_duration(bail_out.eta),
+                clock_suffix(args, bail_out.eta),
+                units.format_elevation(bail_out.descent),
+                tr("down"),
+                utils::format_duration(bail_out.escape_duration),
+                style(utils::format_duration(bail_out.time_saved)).bold(),
+                tr("saved")
+            );
+        }
+    }
+
+    if !stats.hourly.is_empty() {
+        println!("  {}", style(tr("Hourly positions:")).bold());
+        for position in &stats.hourly {
+            let near = match (&position.checkpoint, position.checkpoint_km) {
+                (Some(name), Some(km)) if km.abs() < 0.05 => format!("  {} {name}", tr("at")),
+                (Some(name), Some(km)) => format!("  {} {} {name}", units.format_distance(km.abs(), 1), tr(if km > 0. { "before" } else { "after" })),
+                _ => String::new()
+            };
+            println!("    {} {:>3}h{}  {} {:>5.1}  {:.6}, {:.6}{}", style(">").blue(),
+                position.hour,
+                clock_suffix(args, Duration::from_secs(position.hour * 3600)),
+                units.distance_unit(),
+                units.distance(position.distance),
+                position.latitude,
+                position.longitude,
+                near
+            );
+        }
+    }
+
+    if let Some(return_stats) = &return_stats {
+        println!("  {}", style(tr("Round trip:")).bold());
+        println!("    {} {}: {}", style(">").blue(), tr("Outbound"), UptimeFull::from(stats.duration));
+        println!("    {} {}: {}", style(">").blue(), tr("Return"), UptimeFull::from(return_stats.duration));
+        println!("    {} {}: {} - {} - {} D+", style(">").blue(),
+            tr("Total"),
+            style(UptimeFull::from(stats.duration + return_stats.duration)).bold(),
+            units.format_distance(stats.distance * 2., 2),
+            units.format_elevation(stats.d_plus + return_stats.d_plus)
+        );
+        println!("    {} {}: {}{}", style(">").blue(),
+            tr("with breaks"),
+            UptimeFull::from(stats.duration + stats.break_duration + return_stats.duration + return_stats.break_duration),
+            clock_suffix(args, stats.duration + stats.break_duration + return_stats.duration + return_stats.break_duration)
+        );
+    }
+
+    if args.reverse && let Some(reverse_stats) = &reverse_stats {
+        println!("  {}", style(tr("Reverse direction:")).bold());
+        println!("    {} {} D+ {} D-", style(">").blue(), units.format_elevation(reverse_stats.d_plus), units.format_elevation(reverse_stats.d_minus));
+        println!("    {} {}: {} ({})", style(">").blue(),
+            tr("Time"),
+            UptimeFull::from(reverse_stats.duration),
+            utils::format_duration_difference(reverse_stats.duration, stats.duration)
+        );
+    }
+
+    let profile = charts::elevation_profile(&stats.points, 60, 10, units);
+    if !profile.is_empty() {
+        println!("  {}", style(tr("Elevation profile:")).bold());
+        for line in profile {
+            println!("  {}", style(line).green());
+        }
+    }
+
+    let speeds = if args.speed_profile { charts::speed_sparkline(&stats.points, 60, units) } else { vec![] };
+    if !speeds.is_empty() {
+        println!("  {}", style(tr("Speed profile:")).bold());
+        for line in speeds {
+            println!("  {}", style(line).cyan());
+        }
+    }
+
+    track_indices
+}
+
+fn prompt_weight(prompt: &str, default: f64) -> f64 {
+    dialoguer::Input::with_theme(&dialoguer::theme::ColorfulTheme::default())
+        .with_prompt(tr(prompt))
+        .default(default)
+        .interact_text()
+        .or_exit()
+}
+
+fn parse_hiker(input: &str) -> Result<HikerProfile, String> {
+    let (name, speed_factor) = input.rsplit_once('=').ok_or("expected NAME=SPEED")?;
+    let speed_factor: f64 = speed_factor.parse().map_err(|_| format!("invalid speed \"{speed_factor}\""))?;
+    if speed_factor <= 0. {
+        return Err(String::from("speed must be positive"));
+    }
+
+    Ok(HikerProfile { name: String::from(name), speed_factor })
+}
+
+fn parse_positive(input: &str) -> Result<f64, String> {
+    match input.parse::<f64>() {
+        Ok(value) if value > 0. && value.is_finite() => Ok(value),
+        Ok(_) => Err(String::from("must be positive")),
+        Err(_) => Err(format!("invalid number \"{input}\""))
+    }
+}
+
+fn print_porcelain(stats: &utils::PathStats, start: Option<OffsetDateTime>) {
+    if let Err(e) = export::write_porcelain(&mut std::io::stdout().lock(), stats, start) {
+        eprintln!("{} {e}", style(tr("Failed to print the results:")).red());
+        exit(exit_code::FAILURE);
+    }
+}
+
+fn clock_suffix(args: &Args, elapsed: Duration) -> String {
+    match args.start {
+        Some(start) => format!(" ({})", utils::format_clock(start, elapsed)),
+        None => String::new()
+    }
+}
+
+fn parse_start_time(input: &str) -> Result<OffsetDateTime, String> {
+    let offset = UtcOffset::current_local_offset().unwrap_or(UtcOffset::UTC);
+
+    if let Ok(date_time) = PrimitiveDateTime::parse(input, format_description!("[year]-[month]-[day] [hour]:[minute]")) {
+        return Ok(date_time.assume_offset(offset));
+    }
+
+    let time = Time::parse(input, format_description!("[hour]:[minute]"))
+        .map_err(|_| String::from("expected HH:MM or YYYY-MM-DD HH:MM"))?;
+    Ok(OffsetDateTime::now_utc().to_offset(offset).replace_time(time))
+}
+
+fn parse_timezone(input: &str) -> Result<Timezone, String> {
+    let input = input.trim();
+    if input.eq_ignore_ascii_case("auto") {
+        return Ok(Timezone::Auto);
+    }
+    if input.eq_ignore_ascii_case("utc") || input.eq_ignore_ascii_case("z") {
+        return Ok(Timezone::Fixed(UtcOffset::UTC));
+    }
+    if let Some(zone) = timezones::get_by_name(input) {
+        return Ok(Timezone::Named(zone));
+    }
+
+    let (sign, offset) = match input.strip_prefix('-') {
+        Some(offset) => (-1, offset),
+        None => (1, input.strip_prefix('+').unwrap_or(input))
+    };
+    let (hours, minutes) = offset.split_once(':').unwrap_or((offset, "0"));
+    let (Ok(hours), Ok(minutes)) = (hours.parse::<i8>(), minutes.parse::<i8>()) else {
+        return Err(String::from("expected auto, UTC, a zone name like Europe/Paris or an offset like +02:00"));
+    };
+    UtcOffset::from_hms(sign * hours, sign * minutes, 0).map(Timezone::Fixed).map_err(|e| e.to_string())
+}
+
+// --start, read as the wall-clock time of --timezone when there is one (now when unset).
+fn route_start(args: &Args, first: Option<&Waypoint>) -> Option<OffsetDateTime> {
+    let Some(offset) = route_offset(args, first) else {
+        return args.start;
+    };
+    Some(args.start.map_or_else(|| OffsetDateTime::now_utc().to_offset(offset), |start| start.replace_offset(offset)))
+}
+
+// Offset of --timezone at the departure, daylight saving time included for named zones.
+fn route_offset(args: &Args, first: Option<&Waypoint>) -> Option<UtcOffset> {
+    let zone = match args.timezone? {
+        Timezone::Fixed(offset) => return Some(offset),
+        Timezone::Named(zone) => zone,
+        Timezone::Auto => {
+            let point = first?.point();
+            let finder = tzf_rs::DefaultFinder::new();
+            let Some(zone) = timezones::get_by_name(finder.get_tz_name(point.x(), point.y())) else {
+                eprintln!("  {} {}", style(tr("Timezone ignored:")).yellow(), tr("no timezone found at the start, clock times stay local"));
+                return None;
+            };
+            zone
+        }
+    };
+    Some(zone.get_offset_utc(&args.start.unwrap_or_else(OffsetDateTime::now_utc)).to_utc())
+}
+
+fn parse_clock_range(input: &str) -> Result<(Time, Time), String> {
+    let (from, to) = input.split_once('-').ok_or("expected HH:MM-HH:MM")?;
+    let clock = |text: &str| Time::parse(text.trim(), format_description!("[hour]:[minute]")).map_err(|_| format!("invalid time \"{text}\""));
+    Ok((clock(from)?, clock(to)?))
+}
+
+// Modifiers applied on top of the chosen terrains, once the plan is complete.
+fn adjusted_plan(mut plan: PacePlan, args: &Args) -> PacePlan {
+    // Braking only applies to walked legs, the ones past --scramble-grade are climbed down.
+    if args.steep_descent {
+        plan = plan.descent_limited(-args.descent_grade / 100., args.descent_braking);
+    }
+    // Replaces the walking formula, the other adjustements apply on top of it.
+    if let Some(grade) = args.scramble_grade {
+        plan = plan.scrambling(grade / 100., args.scramble_rate);
+    }
+    if args.conditions != Conditions::Dry {
+        plan = plan.scaled(args.conditions.factor());
+    }
+    if let Some(pack_weight) = args.pack_weight {
+        // Calibrated adjustements already include the pack carried on the recordings.
+        let reference = args.calibrated.as_ref().and_then(|profile| profile.pack_weight).unwrap_or(5.);
+        let factor = pace::pack_factor(pack_weight) / pace::pack_factor(reference);
+        if factor != 1. {
+            plan = plan.scaled(factor);
+        }
+    }
+    if let Some(threshold) = args.altitude_threshold {
+        plan = plan.altitude_penalized(threshold, args.altitude_penalty / 100.);
+    }
+    plan
 }
 
-impl From<usize> for Terrain {
-    fn from(value: usize) -> Self {
-        match value {
-            0 => Self::Road,
-            1 => Self::Path,
-            2 => Self::Track,
-            3 => Self::Alpine,
-            _ => Self::Unknown
+// Darkness from --night-hours, otherwise from sunset to sunrise at `first`, the start of the track.
+fn night_penalty(args: &Args, start: Option<OffsetDateTime>, first: Option<&Waypoint>) -> Option<NightPenalty> {
+    let slowdown = args.night_penalty? / 100.;
+    let (from, to) = match args.night_hours {
+        Some(hours) => hours,
+        None => {
+            let (start, first) = (start?, first?);
+            let (sunrise, sunset) = sun::sun_times(start.date(), first.point().y(), first.point().x())?;
+            (sunset.to_offset(start.offset()).time(), sunrise.to_offset(start.offset()).time())
         }
+    };
+
+    Some(NightPenalty { from, to, slowdown })
+}
+
+// Name, description and creator from the command line, otherwise asked for with the source's as
+// defaults. The analysed tracks get the name and description too.
+fn set_gpx_metadata(gpx: &mut Gpx, track_indices: &[usize], track_name: &str, eta: Duration, args: &Args) {
+    let metadata = gpx.metadata.get_or_insert_with(Default::default);
+    let prompt = |prompt: &str, value: &Option<String>, default: String| -> String {
+        value.clone().unwrap_or_else(|| dialoguer::Input::with_theme(&dialoguer::theme::ColorfulTheme::default())
+            .with_prompt(tr(prompt))
+            .with_initial_text(default)
+            .allow_empty(true)
+            .interact_text()
+            .or_exit())
+    };
+
+    let name = prompt("GPX name:", &args.gpx_name, metadata.name.clone().unwrap_or_else(|| track_name.to_string()));
+    let planned = format!("{} {}", tr("Planned with mountain_snail, ETA"), utils::format_duration(eta));
+    let description = prompt("GPX description:", &args.gpx_description, match metadata.description.as_deref() {
+        Some(description) if !description.is_empty() => format!("{description} - {planned}"),
+        _ => planned
+    });
+    let creator = prompt("GPX creator:", &args.gpx_creator, String::from("mountain_snail"));
+
+    let non_empty = |text: String| (!text.trim().is_empty()).then_some(text);
+    metadata.name = non_empty(name.clone());
+    metadata.description = non_empty(description.clone());
+    gpx.creator = non_empty(creator);
+    for index in track_indices {
+        gpx.tracks[*index].name = non_empty(name.clone());
+        gpx.tracks[*index].description = non_empty(description.clone());
     }
 }
 
-fn main() {
-    println!("Mountain snail - Hiking time calculator.");
+fn write_timed_gpx(gpx: &Gpx, etas: &[(usize, Vec<&utils::PointStats>)], source_path: &str, args: &Args) {
+    let source_path = Path::new(source_path);
+    let default_path = source_path.with_file_name(format!("{}_timed.gpx", source_path.file_stem().unwrap_or_default().to_string_lossy()));
+
+    let output_path: String = dialoguer::Input::with_theme(&dialoguer::theme::ColorfulTheme::default())
+        .with_prompt(tr("Timed GPX output path:"))
+        .with_initial_text(default_path.to_string_lossy())
+        .interact_text()
+        .or_exit();
 
-    let (is_gpx_file, file_path) = get_path();
-    let speed_adjustement = get_speed_adjustement();
+    let extras = export::GpxExtras { version: args.gpx_version.unwrap_or_default(), etas, extensions: source_extensions(args, &source_path.to_string_lossy()) };
+    match export::write_gpx(gpx, &extras, Path::new(&output_path)) {
+        Ok(()) => eprintln!("{} {}", style(tr("Timed GPX written to")).green(), output_path),
+        Err(e) => eprintln!("{} {e}", style(tr("Failed to write timed GPX:")).red())
+    }
+}
 
-    if is_gpx_file {
-        analyse_gpx(file_path, speed_adjustement as f64);
+// Extensions of the points of the source GPX, or of the merged files, to carry over to the written GPX.
+fn source_extensions(args: &Args, source_path: &str) -> import::PointExtensions {
+    let sources: Vec<&Path> = if args.files.is_empty() { vec![Path::new(source_path)] } else { args.files.iter().map(PathBuf::as_path).collect() };
+    let mut extensions = import::PointExtensions::new();
+    for path in sources {
+        if path.extension().is_none_or(|extension| !extension.eq_ignore_ascii_case("gpx")) {
+            continue;
+        }
+
+        match fs::File::open(path).map_err(anyhow::Error::from).and_then(|file| import::read_point_extensions(std::io::BufReader::new(file))) {
+            Ok(found) => extensions.extend(found),
+            Err(e) => eprintln!("  {} {}: {e}", style(tr("Extensions not kept:")).yellow(), path.display())
+        }
     }
-    else {
-        analyse_by_splits(file_path, speed_adjustement);
+    extensions
+}
+
+// Each file becomes one track, named after the file when it has several, in the given order.
+fn merge_files(files: &[PathBuf], args: &Args) -> anyhow::Result<Gpx> {
+    let mut merged = Gpx {
+        version: GpxVersion::Gpx11,
+        creator: Some(String::from("mountain_snail")),
+        ..Default::default()
+    };
+
+    for path in files {
+        let gpx = read_track_file(path, args).map_err(|e| e.context(path.display().to_string()))?;
+        let mut tracks = gpx.tracks.into_iter();
+        let Some(mut track) = tracks.next() else {
+            continue;
+        };
+        for other in tracks {
+            track.segments.extend(other.segments);
+        }
+        if track.name.is_none() || files.len() > 1 {
+            track.name = path.file_stem().map(|stem| stem.to_string_lossy().to_string());
+        }
+
+        merged.tracks.push(track);
+        merged.waypoints.extend(gpx.waypoints);
+    }
+
+    Ok(merged)
+}
+
+// With --lenient, GPX files that can't be parsed are read again leaving out what is broken.
+fn read_track_file(path: &Path, args: &Args) -> anyhow::Result<Gpx> {
+    match import::read_file(path) {
+        Err(e) if args.lenient && path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("gpx")) => {
+            read_leniently(&e, std::io::BufReader::new(fs::File::open(path)?))
+        },
+        result => result
+    }
+}
+
+fn read_leniently<R: std::io::Read>(error: &anyhow::Error, reader: R) -> anyhow::Result<Gpx> {
+    eprintln!("{} {error:#}", style(tr("Invalid GPX, reading what can be:")).yellow());
+    let (gpx, recovery) = import::read_gpx_lenient(reader)?;
+
+    let skipped: Vec<String> = [
+        (recovery.skipped_points, "points without valid coordinates"),
+        (recovery.empty_segments, "empty segments"),
+        (recovery.invalid_values, "unreadable times or elevations")
+    ]
+        .into_iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, what)| format!("{count} {}", tr(what)))
+        .collect();
+    if !skipped.is_empty() {
+        eprintln!("  {} {}", style(tr("Left out:")).yellow(), skipped.join(", "));
+    }
+    if let Some(e) = &recovery.error {
+        eprintln!("  {} {e}, {}", style(tr("Stopped at:")).yellow(), tr("the rest of the file is ignored"));
+    }
+    Ok(gpx)
+}
+
+// Stage files are written next to the source as <stem>_stage_<n>.gpx.
+fn write_stage_gpx(track: &Track, stages: &[utils::Stage], source_path: &str, args: &Args) {
+    let extras = export::GpxExtras { version: args.gpx_version.unwrap_or_default(), extensions: source_extensions(args, source_path), ..Default::default() };
+    let source_path = Path::new(source_path);
+    let points: Vec<&Waypoint> = track.segments.iter().flat_map(|segment| segment.points.iter()).collect();
+
+    for (i, stage) in stages.iter().enumerate() {
+        let mut segment = TrackSegment::new();
+        segment.points = points[stage.points.0..=stage.points.1].iter().map(|point| (*point).clone()).collect();
+        let mut stage_track = Track::new();
+        stage_track.name = Some(stage.name.clone());
+        stage_track.segments.push(segment);
+
+        let stage_gpx = Gpx {
+            version: GpxVersion::Gpx11,
+            creator: Some(String::from("mountain_snail")),
+            tracks: vec![stage_track],
+            ..Default::default()
+        };
+
+        let path = source_path.with_file_name(format!("{}_stage_{}.gpx", source_path.file_stem().unwrap_or_default().to_string_lossy(), i + 1));
+        match export::write_gpx(&stage_gpx, &extras, &path) {
+            Ok(()) => eprintln!("{} {}", style(tr("Stage GPX written to")).green(), path.display()),
+            Err(e) => eprintln!("{} {e}", style(tr("Failed to write stage GPX:")).red())
+        }
     }
 }
 
-fn analyse_gpx(gpx_file_path: String, speed_adjustement: f64) {
-    let file = File::open(gpx_file_path).unwrap();
-    let reader = BufReader::new(file);
+fn stream_file(path: &Path, plan: &PacePlan, args: &Args) {
+    if path.extension().is_none_or(|extension| !extension.eq_ignore_ascii_case("gpx")) {
+        eprintln!("  {} {} ({})", style(tr("Skipping")).yellow(), path.display(), tr("only GPX files can be streamed"));
+        return;
+    }
+
+    match fs::File::open(path) {
+        Ok(file) => analyse_stream(&path.display().to_string(), std::io::BufReader::new(file), import::peek_first_point(path).as_ref(), plan, args),
+        Err(e) => {
+            eprintln!("{} {e}", style(format!("{} {}:", tr("Error reading"), path.display())).red());
+            exit(exit_code::INPUT);
+        }
+    }
+}
 
-    let gpx: Gpx = match read(reader) {
-        Ok(gpx) => gpx,
+// Summary of a GPX document analysed while it is read, see stream::analyse_gpx.
+// `first` is the first track point when known beforehand, for --timezone auto.
+fn analyse_stream<R: std::io::Read>(name: &str, reader: R, first: Option<&Waypoint>, plan: &PacePlan, args: &Args) {
+    let plan = adjusted_plan(plan.clone(), args);
+    let options = analysis_options(args, first, false);
+    let stats = match stream::analyse_gpx(reader, &plan, &options) {
+        Ok(stats) => stats,
         Err(e) => {
-            println!("{} {e:?}", style(format!("Error reading GPX file:")).red());
-            exit(-2);
+            eprintln!("{} {e:#}", style(format!("{} {name}:", tr("Error reading"))).red());
+            exit(exit_code::INPUT);
+        }
+    };
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&stats).unwrap());
+        return;
+    }
+    if args.quiet {
+        print_porcelain(&stats, options.start);
+        return;
+    }
+
+    let units = args.units;
+    println!("  {} {}", style(tr("Track")).bold(), style(name).green());
+    println!("    {} {} D+ {} D-", style(">").blue(), units.format_elevation(stats.d_plus), units.format_elevation(stats.d_minus));
+    println!("    {} {}", style(">").blue(), units.format_distance(stats.distance, 2));
+    println!("    {} {}: {} - {}", style(">").blue(), tr("Range"), units.format_elevation(stats.min_height), units.format_elevation(stats.max_height));
+    print_missing_elevations(&stats, args);
+    println!("    {} {}: {} {}, {} {}", style(">").blue(),
+        tr("Time"),
+        UptimeFull::from(stats.duration),
+        tr("walking"),
+        style(UptimeFull::from(stats.duration + stats.break_duration)).bold(),
+        tr("with breaks")
+    );
+    println!("    {} {}: {} ({} {}, {} {} - {})", style(">").blue(),
+        tr("Average altitude"),
+        units.format_elevation(stats.average_altitude),
+        tr("median"),
+        units.format_elevation(stats.median_altitude),
+        tr("quartiles"),
+        units.format_elevation(stats.lower_quartile_altitude),
+        units.format_elevation(stats.upper_quartile_altitude)
+    );
+}
+
+// `first` is the first point of the route, for its timezone and sunset.
+fn analysis_options(args: &Args, first: Option<&Waypoint>, edit_track_times: bool) -> AnalysisOptions {
+    let start = route_start(args, first);
+    AnalysisOptions {
+        edit_track_times,
+        smoothing_window: args.smoothing,
+        elevation_threshold: args.elevation_threshold,
+        distance_algorithm: args.distance,
+        breaks: BreakPolicy {
+            interval: args.break_every.map(|minutes| Duration::from_secs(minutes * 60)),
+            length: Duration::from_secs(args.break_length * 60),
+            lunch_after: args.lunch_after.map(|minutes| Duration::from_secs(minutes * 60)),
+            lunch_length: Duration::from_secs(args.lunch_length * 60)
         },
+        start,
+        night: night_penalty(args, start, first),
+        heat: heat_penalty(args, args.temperature),
+        interpolate_elevations: args.interpolate_elevation,
+        walked: Duration::ZERO
+    }
+}
+
+// Points without elevation are walked as flat, which shortens the time of climbs.
+fn print_missing_elevations(stats: &utils::PathStats, args: &Args) {
+    if stats.missing_elevations == 0 {
+        return;
+    }
+
+    let total: usize = stats.segments.iter().map(|segment| segment.points).sum();
+    let share = stats.missing_elevations as f64 / total.max(1) as f64 * 100.;
+    println!("    {} {}: {} ({share:.0} %){}", style(">").blue(),
+        tr("Missing elevation"),
+        stats.missing_elevations,
+        if args.interpolate_elevation { format!(", {}", tr("interpolated")) } else { String::new() }
+    );
+    if share > args.missing_elevation_threshold {
+        let advice = if args.interpolate_elevation { "" } else { " (--interpolate-elevation)" };
+        println!("    {} {}", style("!").yellow().bold(), style(format!("{share:.0} % {}{advice}", tr("of the points have no elevation, grades and times are unreliable"))).yellow().bold());
+    }
+}
+
+fn heat_penalty(args: &Args, temperature: Option<f64>) -> Option<HeatPenalty> {
+    Some(HeatPenalty { temperature: temperature?, threshold: args.heat_threshold, slowdown: args.heat_penalty? / 100. })
+}
+
+// Every track of a file is analysed as one itinerary, files that can't be read are skipped.
+fn analyse_directory(dir: &Path, plan: &PacePlan, args: &Args) {
+    let mut paths: Vec<PathBuf> = match read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| import::is_supported(path))
+            .collect(),
+        Err(e) => {
+            eprintln!("{} {e}", style(format!("{} {}:", tr("Cannot read"), dir.display())).red());
+            exit(exit_code::INPUT);
+        }
     };
+    paths.sort();
 
-    println!("GPX file has {} track(s), {} route(s).", style(gpx.tracks.len()).bold(), style(&gpx.routes.len()).bold());
+    summarise_files(&paths, plan, args, false);
+}
 
-    let mut track_index: usize = 0;
-    if gpx.tracks.len() > 1 {
-        let names: Vec<String> = gpx.tracks.iter().map(|track| {
-            return match &track.name {
-                Some(name) => name.clone(),
-                None => String::from(""),
+// One row per file, `ranked` numbering them (by estimated time unless --sort says otherwise).
+fn summarise_files(paths: &[PathBuf], plan: &PacePlan, args: &Args, ranked: bool) {
+    let plan = &adjusted_plan(plan.clone(), args);
+    let mut rows = Vec::new();
+    for path in paths {
+        let gpx = match read_track_file(path, args) {
+            Ok(gpx) => gpx,
+            Err(e) => {
+                eprintln!("  {} {} {e}", style(tr("Skipping")).yellow(), path.display());
+                continue;
             }
-        }).collect();
+        };
+        let mut tracks = gpx.tracks.into_iter();
+        let Some(mut track) = tracks.next() else {
+            eprintln!("  {} {} ({})", style(tr("Skipping")).yellow(), path.display(), tr("no track"));
+            continue;
+        };
+        for other in tracks {
+            track.segments.extend(other.segments);
+        }
 
-        track_index = Select::with_theme(&dialoguer::theme::ColorfulTheme::default())
-            .with_prompt("Select GPX track")
-            .items(&names)
-            .interact()
-            .unwrap();
+        let name = path.file_stem().unwrap().to_string_lossy().to_string();
+        let recorded = utils::recorded_times(&track, args.distance, args.stop_speed);
+        let first = track.segments.iter().find_map(|segment| segment.points.first()).cloned();
+        let options = analysis_options(args, first.as_ref(), false);
+        let mut stats = read_gpx(&mut track, plan, &options);
+        stats.recorded = recorded;
+        stats.effort = utils::effort_score(&stats);
+        rows.push((name, stats, options.start));
+    }
+    match args.sort.unwrap_or(if ranked { SortKey::Time } else { SortKey::Name }) {
+        SortKey::Name => rows.sort_by(|a, b| a.0.cmp(&b.0)),
+        SortKey::Distance => rows.sort_by(|a, b| a.1.distance.total_cmp(&b.1.distance)),
+        SortKey::Time => rows.sort_by_key(|(_, stats, _)| stats.duration + stats.break_duration),
+        SortKey::Effort => rows.sort_by(|a, b| a.1.effort.total_cmp(&b.1.effort))
     }
 
-    println!("{} {} {}",
-        style("Chosen track:").bold(),
-        style("·").black().bright(),
-        style(format!("\"{}\" (track n°{})", 
-            match &gpx.tracks[track_index].name { Some(name) => name.clone(), None => String::from("Default") },
-            track_index + 1
-        )).green()
-    );
+    if args.json {
+        let summary: Vec<serde_json::Value> = rows.iter()
+            .map(|(name, stats, _)| serde_json::json!({ "name": name, "stats": stats }))
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&summary).unwrap());
+        return;
+    }
+    if args.quiet {
+        // One block per file, introduced by its name.
+        for (name, stats, start) in &rows {
+            println!("file={name}");
+            print_porcelain(stats, *start);
+        }
+        return;
+    }
 
-    let edit_track_times = dialoguer::Confirm::with_theme(&dialoguer::theme::ColorfulTheme::default())
-        .with_prompt("Add time to GPX points ?")
-        .interact()
-        .unwrap();
+    let width = rows.iter().map(|(name, _, _)| name.chars().count()).max().unwrap_or(0).max(4);
+    let units = args.units;
+    // Recorded columns only when some of the files have timestamps.
+    let recorded = rows.iter().any(|(_, stats, _)| stats.recorded.is_some());
+    let mut header = format!("{:<width$}  {:>8}  {:>7}  {:>6}  {:>9}  {:>6}", tr("Name"), tr("Distance"), "D+", tr("Time"), tr("w/ breaks"), tr("Effort"));
+    if ranked {
+        header = format!("{:>2}  {header}", "#");
+    }
+    if recorded {
+        header += &format!("  {:>6}  {:>7}", tr("Moving"), tr("Elapsed"));
+    }
+    println!("{}", style(header).bold());
+    for (rank, (name, stats, _)) in rows.iter().enumerate() {
+        let mut row = format!("{:<width$}  {:>8}  {:>7}  {:>6}  {:>9}  {:>6.1}",
+            name,
+            units.format_distance(stats.distance, 1),
+            units.format_elevation(stats.d_plus),
+            utils::format_duration(stats.duration),
+            utils::format_duration(stats.duration + stats.break_duration),
+            stats.effort
+        );
+        if ranked {
+            row = format!("{:>2}  {row}", rank + 1);
+        }
+        if recorded {
+            let (moving, elapsed) = stats.recorded
+                .map(|recorded| (utils::format_duration(recorded.moving), utils::format_duration(recorded.elapsed)))
+                .unwrap_or_default();
+            row += &format!("  {moving:>6}  {elapsed:>7}");
+        }
+        println!("{row}");
+    }
+}
 
-    let track = gpx.tracks[track_index].clone();
-    
-    let stats = read_gpx(&track, speed_adjustement, edit_track_times);
+// Legs of a recording as (length in m, grade) pairs, walked in `moving` time.
+struct Recording {
+    terrain: Terrain,
+    legs: Vec<(f64, f64)>,
+    moving: Duration
+}
+
+// Recordings of the same terrain are fitted together, weighting each by its length.
+fn calibrate(files: &[PathBuf], args: &Args) {
+    let plan = PacePlan::new(Rc::new(ExponentialPace::new(0.)));
+    let options = analysis_options(args, None, false);
+    let mut recordings: Vec<Recording> = Vec::new();
 
-    println!("  {}", style("Track info:").bold());
-    println!("    {} {} m D+ {} m D-", style(">").blue(), stats.d_plus.round_ties_even(), stats.d_minus.round_ties_even());
-    println!("    {} {} km", style(">").blue(), (stats.distance * 100.).round() / 100.);
-    println!("    {} Range: {} m - {} m", style(">").blue(), stats.min_height, stats.max_height);
-    println!("    {} Time: {}", style(">").blue(), UptimeFull::from(stats.duration));
-    println!("    {} Average altitude: {} m", style(">").blue(), stats.average_altitude.round_ties_even());
+    for path in files {
+        let gpx = match import::read_file(path) {
+            Ok(gpx) => gpx,
+            Err(e) => {
+                eprintln!("  {} {} {e}", style(tr("Skipping")).yellow(), path.display());
+                continue;
+            }
+        };
+        let mut tracks = gpx.tracks.into_iter();
+        let Some(mut track) = tracks.next() else {
+            eprintln!("  {} {} ({})", style(tr("Skipping")).yellow(), path.display(), tr("no track"));
+            continue;
+        };
+        for other in tracks {
+            track.segments.extend(other.segments);
+        }
+        let Some(recorded) = utils::recorded_times(&track, args.distance, args.stop_speed) else {
+            eprintln!("  {} {} ({})", style(tr("Skipping")).yellow(), path.display(), tr("no timestamps"));
+            continue;
+        };
+
+        let stats = read_gpx(&mut track, &plan, &options);
+        let legs: Vec<(f64, f64)> = stats.points.windows(2)
+            .map(|pair| ((pair[1].distance - pair[0].distance) * 1000., pair[1].grade))
+            .filter(|(distance_m, _)| *distance_m > 0.)
+            .collect();
+
+        eprintln!("{} {}, {} {}", style(path.display()).green(), args.units.format_distance(stats.distance, 1), utils::format_duration(recorded.moving), tr("moving"));
+        let terrain = get_terrain();
+        recordings.push(Recording { terrain, legs, moving: recorded.moving });
+    }
+
+    if recordings.is_empty() {
+        eprintln!("{}", style(tr("No timestamped recording to calibrate from.")).red());
+        exit(exit_code::INPUT);
+    }
+
+    // Terrains without a recording keep their previous calibration, or the built-in constant.
+    let mut profiles = config::load_profiles();
+    let mut calibrated = profiles.calibrated().cloned().unwrap_or_default();
+    calibrated.name = String::from(config::CALIBRATED_PROFILE);
+    calibrated.pack_weight = Some(args.pack_weight.unwrap_or_else(|| prompt_weight("Pack weight carried on the recordings (kg):", 5.)));
+
+    println!("  {}", style(tr("Calibrated speed adjustements:")).bold());
+    for terrain in [Terrain::Road, Terrain::Path, Terrain::Track, Terrain::Alpine] {
+        let matching: Vec<&Recording> = recordings.iter().filter(|recording| recording.terrain == terrain).collect();
+        let legs: Vec<(f64, f64)> = matching.iter().flat_map(|recording| recording.legs.iter().copied()).collect();
+        let moving: Duration = matching.iter().map(|recording| recording.moving).sum();
+
+        if let Some(adjustement) = ExponentialPace::fit(&legs, moving) {
+            println!("    {} {}: {:.3} ({} {})", style(">").blue(), terrain.name(), adjustement, matching.len(), tr("recording(s)"));
+            calibrated.set_adjustement(terrain, adjustement);
+        }
+    }
+
+    let save = dialoguer::Confirm::with_theme(&dialoguer::theme::ColorfulTheme::default())
+        .with_prompt(format!("{} \"{}\" ({}) ?", tr("Save as the profile"), config::CALIBRATED_PROFILE, tr("used by default")))
+        .default(true)
+        .interact()
+        .or_exit();
+    if save {
+        profiles.upsert(calibrated);
+        match config::save_profiles(&profiles) {
+            Ok(path) => eprintln!("{} {} {}", style(">").blue(), tr("Profile saved to"), path.display()),
+            Err(e) => eprintln!("{} {e}", style(tr("Profile not saved:")).red())
+        }
+    }
 }
 
-fn analyse_by_splits(splits_file_path: String, speed_adjustement: f32) {
-    let splits_string: String = dialoguer::Input::with_theme(&dialoguer::theme::ColorfulTheme::default())
-        .with_prompt("Splits (meters): ")
-        .with_initial_text("1000")
-        .validate_with(|input: &String| -> Result<(), String> {
-            let result= input.parse::<i32>();
-            // Path invalid or fs error:
-            if result.is_err() {
-                Err(result.err().unwrap().to_string())
+// Polls the modification time every second and runs the analysis again after each save.
+fn watch(path: &Path, is_gpx_file: bool, plan: &PacePlan, args: &Args) -> ! {
+    let modified = || fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+    let mut analysed = None;
+
+    loop {
+        let current = modified();
+        if current.is_some() && current != analysed {
+            analysed = current;
+            let _ = console::Term::stdout().clear_screen();
+
+            if is_gpx_file {
+                match import::read_file(path) {
+                    Ok(gpx) => {
+                        analyse_gpx(gpx, path.to_string_lossy().to_string(), plan.clone(), args);
+                    },
+                    // Often a file caught half written, the next save will be picked up.
+                    Err(e) => eprintln!("{} {e:#}", style(tr("Error reading GPX file:")).red())
+                }
             }
             else {
-                Ok(())
+                analyse_by_splits(path.to_string_lossy().to_string(), plan, args);
             }
-        })
-        .interact_text()
-        .unwrap();
-    let splits_length = splits_string.parse::<i32>().expect("Split length not parseable into i32");
+            eprintln!("{}", style(format!("{} {} {}", tr("Watching"), path.display(), tr("for changes (Ctrl+C to stop)..."))).dim());
+        }
 
-    let splits: utils::Splits = serde_json::from_reader(
-        std::io::BufReader::new(fs::File::open(splits_file_path).expect("Cannot open splits file.")))
-        .expect("Failed to read splits file.");
+        std::thread::sleep(Duration::from_secs(1));
+    }
+}
 
-    println!("{} split(s) found.\nPath info: {}", 
-        style(format!("{}", splits.splits.len())).bold(), 
-        style(format!("{}", utils::stats(&splits, splits_length))).bold()
-    );
+fn analyse_by_splits(splits_file_path: String, plan: &PacePlan, args: &Args) {
+    let units = args.units;
+    let splits_length = if args.watch {
+        args.split_length as i32
+    }
+    else {
+        let length: String = dialoguer::Input::with_theme(&dialoguer::theme::ColorfulTheme::default())
+            .with_prompt(format!("{} ({}): ", tr("Split length"), units.distance_unit()))
+            .with_initial_text(format!("{:.2}", units.distance(args.split_length as f64 / 1000.)))
+            .validate_with(|input: &String| -> Result<(), String> {
+                match input.parse::<f64>() {
+                    Ok(length) if length > 0. => Ok(()),
+                    Ok(_) => Err(String::from("must be positive")),
+                    Err(e) => Err(e.to_string())
+                }
+            })
+            .interact_text()
+            .or_exit();
+        (units.to_km(length.parse::<f64>().expect("Split length not parseable into f64")) * 1000.).round() as i32
+    };
 
-    println!("Splits:");
-    let times = calculate_travel_time(&splits.splits, splits_length, speed_adjustement as f64);
+    let splits = match read_splits(Path::new(&splits_file_path)) {
+        Ok(splits) => splits,
+        Err(e) => {
+            eprintln!("{} {e:#}", style(tr("Error reading splits file:")).red());
+            exit(exit_code::INPUT);
+        }
+    };
+
+    // More elevation change than length means a mistyped value rather than a via ferrata.
+    for (i, split) in splits.splits.iter().enumerate() {
+        let length = split.length(splits_length);
+        if split.d_plus + split.d_minus > length {
+            eprintln!("  {} {} {}: +{} / -{} {} {}", style(tr("Implausible:")).yellow(), tr("Split"), i + 1,
+                units.format_elevation(split.d_plus as f64),
+                units.format_elevation(split.d_minus as f64),
+                tr("over"),
+                units.format_elevation(length as f64)
+            );
+        }
+    }
+
+    let mut path_stats = utils::stats(&splits, splits_length);
+    let plan = splits_plan(&splits, splits_length, plan, args.calibrated.as_ref());
+
+    let decorated = !args.json && !args.quiet;
+    if decorated {
+        println!("{} {}\n{} {}", 
+            style(format!("{}", splits.splits.len())).bold(), 
+            tr("split(s) found."),
+            tr("Path info:"),
+            style(format!("{} - {} D+ - {} D-", units.format_distance(path_stats.distance, 2),
+                units.format_elevation(path_stats.d_plus), units.format_elevation(path_stats.d_minus))).bold()
+        );
+        println!("{}", tr("Splits:"));
+    }
+
+    let times = calculate_travel_time(&splits.splits, splits_length, &plan);
     let mut total_time = Duration::new(0, 0);
     let mut split_number = [0, 1];
-    for duration in times {
+    let mut flat_distance = 0.;
+    for (split, duration) in splits.splits.iter().zip(times) {
         total_time += duration;
+        let length = split.length(splits_length) as f64;
+        let split_flat_distance = utils::flat_equivalent(length / 1000., (split.d_plus - split.d_minus) as f64 / length);
+        flat_distance += split_flat_distance;
 
-        println!("{} : {} -- {}", 
-            style(format!("{split_number:?}")).dim(),
-            duration.human(humanize_duration::Truncate::Second),
-            total_time.human(humanize_duration::Truncate::Second)
-        );
+        path_stats.splits.push(utils::SplitStats {
+            distance: length / 1000.,
+            d_plus: split.d_plus as f64,
+            d_minus: split.d_minus as f64,
+            duration,
+            total_duration: total_time,
+            grade_adjusted_pace: utils::grade_adjusted_pace(duration, split_flat_distance)
+        });
+
+        if decorated {
+            println!("{} {} +{} -{} : {} -- {}  {}", 
+                style(format!("{split_number:?}")).dim(),
+                units.format_distance(length / 1000., 2),
+                units.format_elevation(split.d_plus as f64),
+                units.format_elevation(split.d_minus as f64),
+                duration.human(humanize_duration::Truncate::Second),
+                total_time.human(humanize_duration::Truncate::Second),
+                style(format!("GAP {}", units.format_pace(utils::grade_adjusted_pace(duration, split_flat_distance)))).dim()
+            );
+        }
         
         split_number[0] += 1;
         split_number[1] += 1;
     }
+    path_stats.duration = total_time;
+    path_stats.grade_adjusted_pace = utils::grade_adjusted_pace(total_time, flat_distance);
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&path_stats).unwrap());
+    }
+    else if args.quiet {
+        print_porcelain(&path_stats, route_start(args, None));
+    }
+    else {
+        println!("{} {}", tr("Total time:"), style(total_time.human(humanize_duration::Truncate::Minute)).bold());
+    }
+}
+
+fn edit_splits(path: &Path, units: Units) {
+    let mut splits = if path.exists() {
+        match read_splits(path) {
+            Ok(splits) => splits,
+            Err(e) => {
+                eprintln!("{} {e:#}", style(tr("Error reading splits file:")).red());
+                exit(exit_code::INPUT);
+            }
+        }
+    }
+    else {
+        utils::Splits { splits: vec![] }
+    };
+
+    loop {
+        let count = splits.splits.len();
+        let mut items: Vec<String> = splits.splits.iter()
+            .enumerate()
+            .map(|(i, split)| format!("{:>3}  +{} / -{}{}{}", i + 1,
+                units.format_elevation(split.d_plus as f64),
+                units.format_elevation(split.d_minus as f64),
+                split.length.map(|length| format!(", {}", units.format_elevation(length as f64))).unwrap_or_default(),
+                split.terrain.map(|terrain| format!(", {}", terrain.name())).unwrap_or_default()
+            ))
+            .collect();
+        items.extend([tr("add a split"), tr("save and quit"), tr("quit without saving")].map(String::from));
+
+        let index = dialoguer::Select::with_theme(&dialoguer::theme::ColorfulTheme::default())
+            .with_prompt(tr("Splits"))
+            .items(&items)
+            .default(count)
+            .max_length(15)
+            .interact()
+            .or_exit();
+
+        match index.checked_sub(count) {
+            None => {
+                let actions = [tr("modify"), tr("insert before"), tr("remove"), tr("back")];
+                let action = dialoguer::Select::with_theme(&dialoguer::theme::ColorfulTheme::default())
+                    .with_prompt(format!("{} {}", tr("Split"), index + 1))
+                    .items(&actions)
+                    .default(0)
+                    .interact()
+                    .or_exit();
+                match action {
+                    0 => splits.splits[index] = get_split(Some(&splits.splits[index]), units),
+                    1 => splits.splits.insert(index, get_split(None, units)),
+                    2 => {
+                        splits.splits.remove(index);
+                    },
+                    _ => {}
+                }
+            },
+            Some(0) => splits.splits.push(get_split(None, units)),
+            Some(1) => {
+                match export::save_splits(&splits, path) {
+                    Ok(()) => eprintln!("{} {}", style(tr("Splits written to")).green(), path.display()),
+                    Err(e) => {
+                        eprintln!("{} {e}", style(tr("Failed to write splits:")).red());
+                        exit(exit_code::FAILURE);
+                    }
+                }
+                return;
+            },
+            _ => return
+        }
+    }
+}
+
+// Prompts for a split's fields, `current` giving the defaults. The adjustement, if any, is kept.
+// Values are asked in the chosen units and stored in meters.
+fn get_split(current: Option<&utils::Split>, units: Units) -> utils::Split {
+    let theme = dialoguer::theme::ColorfulTheme::default();
+    let shown = |m: i32| units.elevation(m as f64).round() as i32;
+    let elevation = |label: &str, default: i32| dialoguer::Input::<i32>::with_theme(&theme)
+        .with_prompt(format!("{label} ({})", units.elevation_unit()))
+        .default(default)
+        .validate_with(|value: &i32| if *value < 0 { Err("must not be negative") } else { Ok(()) })
+        .interact_text()
+        .or_exit();
+    let d_plus = elevation("D+", current.map(|split| shown(split.d_plus)).unwrap_or(0));
+    let d_minus = elevation("D-", current.map(|split| shown(split.d_minus)).unwrap_or(0));
+
+    let length = dialoguer::Input::<String>::with_theme(&theme)
+        .with_prompt(format!("{} ({}), {}", tr("Length"), units.elevation_unit(), tr("empty for the split length")))
+        .with_initial_text(current.and_then(|split| split.length).map(|length| shown(length).to_string()).unwrap_or_default())
+        .allow_empty(true)
+        .validate_with(|input: &String| -> Result<(), &str> {
+            match input.trim() {
+                "" => Ok(()),
+                input => input.parse::<i32>().ok().filter(|length| *length > 0).map(|_| ()).ok_or("must be a positive number")
+            }
+        })
+        .interact_text()
+        .or_exit();
+    let stored = |value: i32| units.to_m(value as f64).round() as i32;
+
+    let terrains = [Terrain::Road, Terrain::Path, Terrain::Track, Terrain::Alpine];
+    let mut labels = vec![tr("same as the trip")];
+    labels.extend(terrains.iter().map(|terrain| terrain.name()));
+    let terrain = dialoguer::Select::with_theme(&theme)
+        .with_prompt(tr("Terrain"))
+        .items(&labels)
+        .default(current.and_then(|split| split.terrain).and_then(|terrain| terrains.iter().position(|t| *t == terrain)).map(|index| index + 1).unwrap_or(0))
+        .interact()
+        .or_exit();
+
+    utils::Split {
+        d_plus: stored(d_plus),
+        d_minus: stored(d_minus),
+        length: length.trim().parse::<i32>().ok().map(stored),
+        terrain: terrain.checked_sub(1).map(|index| terrains[index]),
+        adjustement: current.and_then(|split| split.adjustement)
+    }
+}
+
+// Splits carrying their own terrain or adjustement become sections of the plan.
+fn splits_plan(splits: &utils::Splits, split_length: i32, plan: &PacePlan, calibrated: Option<&SpeedProfile>) -> PacePlan {
+    let mut plan = plan.clone();
+    let mut from_km = 0.;
+    for split in &splits.splits {
+        let to_km = from_km + split.length(split_length) as f64 / 1000.;
+        let adjustement = split.adjustement.or(split.terrain.map(|terrain| get_speed_adjustement(terrain, calibrated) as f64));
+        if let Some(adjustement) = adjustement {
+            plan.sections.push(PaceSection { from_km, to_km, model: Rc::new(ExponentialPace::new(adjustement)) });
+        }
+        from_km = to_km;
+    }
+    plan
+}
+
+fn read_splits(path: &Path) -> anyhow::Result<utils::Splits> {
+    let file = fs::File::open(path).with_context(|| path.display().to_string())?;
+    if path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("csv")) {
+        return import::read_csv_splits(file).with_context(|| format!("{} is not a splits file", path.display()));
+    }
+    import::read_json_splits(std::io::BufReader::new(file)).with_context(|| format!("{} is not a splits file", path.display()))
+}
+
+// Prompts fail without a terminal to ask on (piped input, CI) or when interrupted.
+trait OrExit<T> {
+    fn or_exit(self) -> T;
+}
 
-    println!("Total time: {}", style(total_time.human(humanize_duration::Truncate::Minute)).bold());
+impl<T> OrExit<T> for dialoguer::Result<T> {
+    fn or_exit(self) -> T {
+        self.unwrap_or_else(|e| {
+            eprintln!("{} {e}", style(tr("Cannot prompt:")).red());
+            exit(exit_code::NO_TERMINAL);
+        })
+    }
 }
 
 fn get_terrain() -> Terrain {
-    let choices = vec!["road", "path", "track", "alpine", "manual"];
+    let choices: Vec<&str> = ["road", "path", "track", "alpine"].into_iter().map(tr).collect();
     dialoguer::Select::with_theme(&dialoguer::theme::ColorfulTheme::default())
-        .with_prompt("Terrain")
+        .with_prompt(tr("Terrain"))
         .items(&choices)
         .default(1)
         .interact()
-        .unwrap()
+        .or_exit()
         .into()
 }
 
-fn get_path() -> (bool, String) {
-    let choices = vec!["GPX", "JSON splits"];
-    let is_gpx_file = dialoguer::Select::with_theme(&dialoguer::theme::ColorfulTheme::default())
-        .with_prompt("Type")
+// The last element is true when the last analysis is to be repeated.
+fn get_path(config: &Config, last: Option<&LastAnalysis>) -> (bool, String, bool) {
+    let mut choices = vec![String::from("GPX / TCX / FIT / KML"), String::from("JSON / CSV splits")];
+    if let Some(last) = last {
+        let terrain = last.terrain.map(|terrain| format!(", {}", terrain.name())).unwrap_or_default();
+        choices.insert(0, format!("{} ({}{terrain})", tr("Repeat last analysis"), last.file.display()));
+    }
+    let mut choice = dialoguer::Select::with_theme(&dialoguer::theme::ColorfulTheme::default())
+        .with_prompt(tr("Type"))
         .items(&choices)
+        .default(0)
         .interact()
-        .unwrap() == 0;
+        .or_exit();
+    if let Some(last) = last {
+        if choice == 0 {
+            return (last.is_gpx_file, last.file.to_string_lossy().to_string(), true);
+        }
+        choice -= 1;
+    }
+    let is_gpx_file = choice == 0;
 
     if is_gpx_file {
-        match home_dir() {
-            Some(home_dir) => {
-                let entries = fs::read_dir(home_dir.join("Documents"))
-                .map(|result| result.map(|e| e.unwrap().path()))
-                .unwrap()
-                .collect::<Vec<PathBuf>>();
-
-                let selections = entries.iter()
-                    .map(|path|{
-                        if path.is_dir() {
-                            read_dir(path)
-                                .map(|result|  result.map(|e| e.unwrap().path()))
-                                .unwrap()
-                                .collect::<Vec<PathBuf>>()
-                            }
-                            else {
-                                vec![path.clone()]
-                            }})
-                    .flatten()
-                    .filter(|path_buf| {
-                        if let Some(extension) = path_buf.extension() {
-                            extension == "gpx"
-                        }
-                        else {
-                            false
-                        }
-                    })
-                    .map(|path_buf| String::from(path_buf.to_str().unwrap()))
-                    .collect::<Vec<String>>();
-
-                let selection = dialoguer::Select::with_theme(&dialoguer::theme::ColorfulTheme::default())
-                    .with_prompt("Choose file")
-                    .items(&selections)
-                    .interact_opt()
-                    .unwrap();
-
-                if let Some(index) = selection {
-                    return (true, selections[index].to_owned());
-                }
-            },
-            None => {},
+        let mut files = Vec::new();
+        for dir in config.gpx_dirs() {
+            find_gpx_files(&dir, config.search_depth(), &mut files);
+        }
+        let selections = files.iter()
+            .map(|path| match import::peek_track_name(path) {
+                Some(name) => format!("{} · {name}", path.display()),
+                None => path.display().to_string()
+            })
+            .collect::<Vec<String>>();
+
+        if !selections.is_empty() {
+            let selection = dialoguer::FuzzySelect::with_theme(&dialoguer::theme::ColorfulTheme::default())
+                .with_prompt(tr("Choose file (type to filter)"))
+                .items(&selections)
+                .interact_opt()
+                .or_exit();
+
+            if let Some(index) = selection {
+                return (true, files[index].to_string_lossy().to_string(), false);
+            }
         }
     }
     
     let mut splits_file_path_input_history = dialoguer::BasicHistory::new().max_entries(1);
     let string = dialoguer::Input::with_theme(&dialoguer::theme::ColorfulTheme::default())
-        .with_prompt(if is_gpx_file { "GPX file path:" } else { "Splits file path:" })
+        .with_prompt(tr(if is_gpx_file { "Track file path (GPX, TCX, FIT, KML/KMZ):" } else { "Splits file path (JSON, CSV):" }))
         .with_initial_text(if is_gpx_file { "" } else { "./splits.json" })
         .history_with(&mut splits_file_path_input_history)
         .validate_with(|input: &String| -> Result<(), &str> {
             let result = fs::exists(input);
             // Path invalid or fs error:
             if result.is_err() || !result.unwrap() {
-                Err("Path doesn't exist")
+                Err(tr("Path doesn't exist"))
             }
             else {
                 Ok(())
             }
         })
         .interact_text()
-        .unwrap();
+        .or_exit();
+
+    (is_gpx_file, string, false)
+}
+
+// Unreadable directories and entries are skipped, the search stops `depth` levels below `dir`.
+fn find_gpx_files(dir: &Path, depth: usize, files: &mut Vec<PathBuf>) {
+    let entries = match read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("{} {} {e}", style(tr("Cannot read")).yellow(), dir.display());
+            return;
+        }
+    };
+
+    let mut paths: Vec<PathBuf> = entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect();
+    paths.sort();
+    for path in paths {
+        if path.is_dir() {
+            if depth > 0 {
+                find_gpx_files(&path, depth - 1, files);
+            }
+        }
+        else if import::is_supported(&path) {
+            files.push(path);
+        }
+    }
+}
+
+fn get_pace_plan(config: &Config, args: &Args) -> PacePlan {
+    choose_pace_plan(config, args).0
+}
+
+// Also returns the terrain, when the whole route was given a plain one.
+fn choose_pace_plan(config: &Config, args: &Args) -> (PacePlan, Option<Terrain>) {
+    if let Some(terrain) = args.terrain {
+        return (PacePlan::new(Rc::new(ExponentialPace::new(get_speed_adjustement(terrain, args.calibrated.as_ref()) as f64))), Some(terrain));
+    }
+
+    let units = args.units;
+    let default_terrain = config.default_terrain.as_deref();
+    let (model, mut terrain) = get_pace_model(default_terrain);
+    let mut plan = PacePlan::new(model);
+
+    let mut add_section = dialoguer::Confirm::with_theme(&dialoguer::theme::ColorfulTheme::default())
+        .with_prompt(tr("Assign a different terrain to sections of the route ?"))
+        .default(false)
+        .interact()
+        .or_exit();
+
+    while add_section {
+        let from: f64 = dialoguer::Input::with_theme(&dialoguer::theme::ColorfulTheme::default())
+            .with_prompt(format!("{} ({}):", tr("Section start"), units.distance_unit()))
+            .interact_text()
+            .or_exit();
+        let to: f64 = dialoguer::Input::with_theme(&dialoguer::theme::ColorfulTheme::default())
+            .with_prompt(format!("{} ({}):", tr("Section end"), units.distance_unit()))
+            .validate_with(|input: &f64| -> Result<(), &str> {
+                if *input > from {
+                    Ok(())
+                }
+                else {
+                    Err(tr("Section end must be after its start"))
+                }
+            })
+            .interact_text()
+            .or_exit();
+
+        terrain = None;
+        let (mut model, _) = get_pace_model(default_terrain);
+        // The trip's conditions are applied to every section later on.
+        if let Some(conditions) = get_section_conditions(args.conditions) {
+            model = Rc::new(ScaledPace { inner: model, factor: conditions.factor() / args.conditions.factor() });
+        }
+        plan.sections.push(PaceSection { from_km: units.to_km(from), to_km: units.to_km(to), model });
+
+        add_section = dialoguer::Confirm::with_theme(&dialoguer::theme::ColorfulTheme::default())
+            .with_prompt(tr("Add another section ?"))
+            .default(false)
+            .interact()
+            .or_exit();
+    }
+
+    (plan, terrain)
+}
+
+// None keeps the trip's conditions.
+fn get_section_conditions(trip: Conditions) -> Option<Conditions> {
+    let mut labels = vec![format!("{} ({})", tr("same as the trip"), tr(trip.name()))];
+    labels.extend(Conditions::ALL.iter().map(|conditions| tr(conditions.name()).to_string()));
+
+    let index = dialoguer::Select::with_theme(&dialoguer::theme::ColorfulTheme::default())
+        .with_prompt(tr("Conditions"))
+        .items(&labels)
+        .default(0)
+        .interact()
+        .or_exit();
+    index.checked_sub(1).map(|index| Conditions::ALL[index]).filter(|conditions| *conditions != trip)
+}
+
+// The terrain is only given for the built-in ones.
+fn get_pace_model(default_terrain: Option<&str>) -> (Rc<dyn PaceModel>, Option<Terrain>) {
+    let mut profiles = config::load_profiles();
+    let mut choices: Vec<String> = ["road", "path", "track", "alpine", "manual", "custom formula"]
+        .iter()
+        .map(|choice| choice.to_string())
+        .collect();
+    choices.extend(profiles.profiles.iter().map(|profile| format!("profile: {}", profile.name)));
+    choices.push(String::from("new profile..."));
+
+    // default_terrain names either a terrain or a saved profile, the calibrated profile comes next.
+    let default = default_terrain
+        .or(profiles.calibrated().map(|profile| profile.name.as_str()))
+        .and_then(|terrain| choices.iter().position(|choice| choice == terrain || *choice == format!("profile: {terrain}")))
+        .unwrap_or(1);
+
+    let labels: Vec<String> = choices.iter()
+        .map(|choice| match choice.strip_prefix("profile: ") {
+            Some(name) => format!("{}: {name}", tr("profile")),
+            None => tr(choice).to_string()
+        })
+        .collect();
+
+    let index = dialoguer::Select::with_theme(&dialoguer::theme::ColorfulTheme::default())
+        .with_prompt(tr("Terrain"))
+        .items(&labels)
+        .default(default)
+        .interact()
+        .or_exit();
+
+    if index < 6 {
+        return match Terrain::from(index) {
+            Terrain::Formula => (Rc::new(get_pace_formula()), None),
+            Terrain::Unknown => (Rc::new(ExponentialPace::new(get_speed_adjustement(Terrain::Unknown, None) as f64)), None),
+            terrain => (Rc::new(ExponentialPace::new(get_speed_adjustement(terrain, profiles.calibrated()) as f64)), Some(terrain))
+        };
+    }
+
+    let profile = match profiles.profiles.get(index - 6) {
+        Some(profile) => profile.clone(),
+        None => {
+            let profile = get_new_profile();
+            profiles.profiles.push(profile.clone());
+            match config::save_profiles(&profiles) {
+                Ok(path) => eprintln!("{} {} {}", style(">").blue(), tr("Profile saved to"), path.display()),
+                Err(e) => eprintln!("{} {e}", style(tr("Profile not saved:")).red())
+            }
+            profile
+        }
+    };
+
+    let adjustement = profile.adjustement(get_terrain()).unwrap();
+    (Rc::new(ExponentialPace::new(adjustement)), None)
+}
+
+fn get_new_profile() -> SpeedProfile {
+    let defaults = SpeedProfile::default();
+    let name: String = dialoguer::Input::with_theme(&dialoguer::theme::ColorfulTheme::default())
+        .with_prompt(tr("Profile name:"))
+        .interact_text()
+        .or_exit();
+
+    let prompt = |terrain: &str, default: f64| -> f64 {
+        dialoguer::Input::with_theme(&dialoguer::theme::ColorfulTheme::default())
+            .with_prompt(format!("{terrain} {}", tr("speed adjustement (bigger == slower):")))
+            .default(default)
+            .interact_text()
+            .or_exit()
+    };
+
+    SpeedProfile {
+        name,
+        road: prompt("Road", defaults.road),
+        path: prompt("Path", defaults.path),
+        track: prompt("Track", defaults.track),
+        alpine: prompt("Alpine", defaults.alpine),
+        pack_weight: None
+    }
+}
+
+fn get_pace_formula() -> ExpressionPace {
+    let formula: String = dialoguer::Input::with_theme(&dialoguer::theme::ColorfulTheme::default())
+        .with_prompt(tr("Speed formula in km/h (variables: grade, distance, altitude):"))
+        .with_initial_text("3.6 / (0.6 * math::exp(3.5 * (grade + 0.16)))")
+        .validate_with(|input: &String| -> Result<(), String> {
+            ExpressionPace::new(input).map(|_| ()).map_err(|e| e.to_string())
+        })
+        .interact_text()
+        .or_exit();
 
-    (is_gpx_file, string)
+    ExpressionPace::new(&formula).expect("Formula not parseable")
 }
 
-fn get_speed_adjustement() -> f32 {
+fn get_speed_adjustement(terrain: Terrain, calibrated: Option<&SpeedProfile>) -> f32 {
     // let use_known_speed_values = dialoguer::Confirm::with_theme(&dialoguer::theme::ColorfulTheme::default())
     //     .with_prompt("Choose hiking terrain")
     //     .interact()
     //     .unwrap();
 
-    return match get_terrain() {
-        Terrain::Unknown => {
+    match terrain {
+        // A formula has no adjustement of its own, one is asked for like for an unknown terrain.
+        Terrain::Unknown | Terrain::Formula => {
             let variable_string = dialoguer::Input::with_theme(&dialoguer::theme::ColorfulTheme::default())
-                .with_prompt("Walking speed adjustement (bigger == slower):")
+                .with_prompt(tr("Walking speed adjustement (bigger == slower):"))
                 .with_initial_text("0.16")
                 .validate_with(|input: &String| -> Result<(), String> {
                     let result= input.parse::<f32>();
@@ -257,13 +2756,11 @@ fn get_speed_adjustement() -> f32 {
                     }
                 })
                 .interact_text()
-                .unwrap();
+                .or_exit();
 
             variable_string.parse::<f32>().expect("Variable not parseable into f32")
         },
-        Terrain::Road => 0.05,
-        Terrain::Path => 0.08,
-        Terrain::Track => 0.175,
-        Terrain::Alpine => 0.28,
+        // The calibrated profile, when there is one, replaces the built-in constants.
+        terrain => calibrated.cloned().unwrap_or_default().adjustement(terrain).unwrap() as f32
     }
 }
\ No newline at end of file
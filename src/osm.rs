@@ -0,0 +1,155 @@
+use anyhow::Result;
+use gpx::Track;
+use serde_json::Value;
+
+use crate::Terrain;
+use crate::utils::{distance, DistanceAlgorithm};
+
+const OVERPASS_URL: &str = "https://overpass-api.de/api/interpreter";
+const SAMPLE_SPACING_KM: f64 = 0.1;
+const SEARCH_RADIUS_M: u32 = 20;
+
+pub struct TerrainSection {
+    pub from_km: f64,
+    pub to_km: f64,
+    pub terrain: Terrain
+}
+
+struct Way {
+    terrain: Terrain,
+    geometry: Vec<(f64, f64)>
+}
+
+pub fn detect_terrain(track: &Track, section_km: f64) -> Result<Vec<TerrainSection>> {
+    let samples = sample_track(track);
+    let mut sections: Vec<TerrainSection> = vec![];
+
+    let mut start = 0;
+    while start < samples.len() {
+        let from_km = samples[start].0;
+        let end = samples[start..].iter()
+            .position(|sample| sample.0 >= from_km + section_km)
+            .map(|offset| start + offset)
+            .unwrap_or(samples.len());
+        let to_km = if end < samples.len() { samples[end].0 } else { samples[end - 1].0 };
+
+        if let Some(terrain) = section_terrain(&samples[start..end])? {
+            match sections.last_mut() {
+                Some(last) if last.terrain == terrain && last.to_km == from_km => last.to_km = to_km,
+                _ => sections.push(TerrainSection { from_km, to_km, terrain })
+            }
+        }
+
+        start = end;
+    }
+
+    Ok(sections)
+}
+
+// (km from start, latitude, longitude) roughly every SAMPLE_SPACING_KM.
+fn sample_track(track: &Track) -> Vec<(f64, f64, f64)> {
+    let mut samples = vec![];
+    let mut km = 0.;
+    let mut next_sample = 0.;
+
+    for segment in &track.segments {
+        for (i, point) in segment.points.iter().enumerate() {
+            if i > 0 {
+                km += distance(&segment.points[i - 1], point, DistanceAlgorithm::Haversine);
+            }
+
+            if km >= next_sample {
+                samples.push((km, point.point().y(), point.point().x()));
+                next_sample = km + SAMPLE_SPACING_KM;
+            }
+        }
+    }
+
+    samples
+}
+
+fn section_terrain(samples: &[(f64, f64, f64)]) -> Result<Option<Terrain>> {
+    let ways = query_ways(samples)?;
+    if ways.is_empty() {
+        return Ok(None);
+    }
+
+    // Each sample votes for the terrain of the closest way, ties go to the harder terrain.
+    let votes: Vec<Terrain> = samples.iter()
+        .filter_map(|(_, lat, lon)| {
+            ways.iter()
+                .map(|way| (way.terrain, distance_to_way(*lat, *lon, &way.geometry)))
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(terrain, _)| terrain)
+        })
+        .collect();
+
+    Ok([Terrain::Road, Terrain::Path, Terrain::Track, Terrain::Alpine].into_iter()
+        .max_by_key(|terrain| votes.iter().filter(|vote| *vote == terrain).count()))
+}
+
+fn query_ways(samples: &[(f64, f64, f64)]) -> Result<Vec<Way>> {
+    let coordinates = samples.iter()
+        .map(|(_, lat, lon)| format!("{lat:.6},{lon:.6}"))
+        .collect::<Vec<String>>()
+        .join(",");
+    let query = format!("[out:json][timeout:60];way(around:{SEARCH_RADIUS_M},{coordinates})[highway];out tags geom;");
+
+    let response: Value = ureq::post(OVERPASS_URL)
+        .send_form(&[("data", query.as_str())])?
+        .into_json()?;
+
+    let ways = response["elements"].as_array()
+        .map(|elements| elements.iter()
+            .map(|element| Way {
+                terrain: classify(&element["tags"]),
+                geometry: element["geometry"].as_array()
+                    .map(|nodes| nodes.iter()
+                        .filter_map(|node| Some((node["lat"].as_f64()?, node["lon"].as_f64()?)))
+                        .collect())
+                    .unwrap_or_default()
+            })
+            .collect())
+        .unwrap_or_default();
+
+    Ok(ways)
+}
+
+fn classify(tags: &Value) -> Terrain {
+    let tag = |key: &str| tags[key].as_str().unwrap_or("");
+
+    match tag("sac_scale") {
+        "demanding_mountain_hiking" | "alpine_hiking" | "demanding_alpine_hiking" | "difficult_alpine_hiking" => return Terrain::Alpine,
+        "mountain_hiking" => return Terrain::Track,
+        _ => {}
+    }
+
+    match tag("surface") {
+        "rock" | "scree" | "stone" | "shingle" => return Terrain::Track,
+        "asphalt" | "paved" | "concrete" | "paving_stones" | "sett" => return Terrain::Road,
+        _ => {}
+    }
+
+    // OSM tracks are wide farm or forest roads, which walk like this tool's "path".
+    match tag("highway") {
+        "path" | "footway" | "bridleway" | "steps" | "track" | "cycleway" => Terrain::Path,
+        _ => Terrain::Road
+    }
+}
+
+// Distance in meters using an equirectangular projection centered on the point.
+fn distance_to_way(lat: f64, lon: f64, geometry: &[(f64, f64)]) -> f64 {
+    let scale = lat.to_radians().cos();
+    let project = |(node_lat, node_lon): (f64, f64)| ((node_lon - lon) * scale * 111_320., (node_lat - lat) * 110_540.);
+
+    geometry.windows(2)
+        .map(|nodes| {
+            let a = project(nodes[0]);
+            let b = project(nodes[1]);
+            let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+            let length = dx * dx + dy * dy;
+            let t = if length == 0. { 0. } else { (-(a.0 * dx + a.1 * dy) / length).clamp(0., 1.) };
+            ((a.0 + t * dx).powi(2) + (a.1 + t * dy).powi(2)).sqrt()
+        })
+        .fold(f64::MAX, f64::min)
+}
@@ -0,0 +1,472 @@
+use std::{cell::Cell, rc::Rc, time::Duration};
+
+use anyhow::{bail, Result};
+use evalexpr::{build_operator_tree, ContextWithMutableVariables, HashMapContext, Node, Value};
+use tracing::warn;
+
+pub trait PaceModel {
+    fn time(&self, distance_m: f64, delta_elev_m: f64) -> Duration;
+
+    fn time_at(&self, distance_m: f64, delta_elev_m: f64, _altitude_m: f64) -> Duration {
+        self.time(distance_m, delta_elev_m)
+    }
+}
+
+// Default model for the whole route, with per-section overrides given in kilometers from the start.
+#[derive(Clone)]
+pub struct PacePlan {
+    pub default: Rc<dyn PaceModel>,
+    pub sections: Vec<PaceSection>
+}
+
+#[derive(Clone)]
+pub struct PaceSection {
+    pub from_km: f64,
+    pub to_km: f64,
+    pub model: Rc<dyn PaceModel>
+}
+
+impl PacePlan {
+    pub fn new(default: Rc<dyn PaceModel>) -> Self {
+        Self { default, sections: vec![] }
+    }
+
+    pub fn model_at(&self, km: f64) -> &dyn PaceModel {
+        // Sections added last take precedence when ranges overlap.
+        self.sections.iter()
+            .rev()
+            .find(|section| km >= section.from_km && km < section.to_km)
+            .map(|section| section.model.as_ref())
+            .unwrap_or(self.default.as_ref())
+    }
+
+    pub fn scaled(&self, factor: f64) -> PacePlan {
+        self.wrapped(|inner| Rc::new(ScaledPace { inner, factor }))
+    }
+
+    pub fn altitude_penalized(&self, threshold_m: f64, slowdown: f64) -> PacePlan {
+        self.wrapped(|inner| Rc::new(AltitudePace { inner, threshold_m, slowdown }))
+    }
+
+    pub fn descent_limited(&self, steep_grade: f64, braking: f64) -> PacePlan {
+        self.wrapped(|inner| Rc::new(DescentPace { inner, steep_grade, braking }))
+    }
+
+    pub fn scrambling(&self, grade: f64, vertical_rate: f64) -> PacePlan {
+        self.wrapped(|inner| Rc::new(ScramblePace { inner, grade, vertical_rate }))
+    }
+
+    // Same plan with every model wrapped by `wrap`.
+    fn wrapped(&self, wrap: impl Fn(Rc<dyn PaceModel>) -> Rc<dyn PaceModel>) -> PacePlan {
+        PacePlan {
+            default: wrap(self.default.clone()),
+            sections: self.sections.iter()
+                .map(|section| PaceSection {
+                    from_km: section.from_km,
+                    to_km: section.to_km,
+                    model: wrap(section.model.clone())
+                })
+                .collect()
+        }
+    }
+
+    // Same plan for the route walked backwards, `length_km` being the route's total length.
+    pub fn mirrored(&self, length_km: f64) -> PacePlan {
+        PacePlan {
+            default: self.default.clone(),
+            sections: self.sections.iter()
+                .map(|section| PaceSection {
+                    from_km: length_km - section.to_km,
+                    to_km: length_km - section.from_km,
+                    model: section.model.clone()
+                })
+                .collect()
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct HikerProfile {
+    pub name: String,
+    // Relative walking speed, 1 being the terrain's nominal pace.
+    pub speed_factor: f64
+}
+
+// Ground conditions, slowing down whatever the terrain.
+#[derive(Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Conditions {
+    #[default]
+    Dry,
+    Wet,
+    // Snow with a broken trail.
+    PackedSnow,
+    // Trail breaking in deep snow.
+    DeepSnow
+}
+
+impl Conditions {
+    pub const ALL: [Conditions; 4] = [Self::Dry, Self::Wet, Self::PackedSnow, Self::DeepSnow];
+
+    pub fn factor(self) -> f64 {
+        match self {
+            Self::Dry => 1.,
+            Self::Wet => 1.1,
+            Self::PackedSnow => 1.25,
+            Self::DeepSnow => 1.8
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Dry => "dry",
+            Self::Wet => "wet",
+            Self::PackedSnow => "snow, trail broken",
+            Self::DeepSnow => "deep snow"
+        }
+    }
+}
+
+// Multiplies another model's times, `factor` > 1 being slower.
+pub struct ScaledPace {
+    pub inner: Rc<dyn PaceModel>,
+    pub factor: f64
+}
+
+impl PaceModel for ScaledPace {
+    fn time(&self, distance_m: f64, delta_elev_m: f64) -> Duration {
+        self.inner.time(distance_m, delta_elev_m).mul_f64(self.factor)
+    }
+
+    fn time_at(&self, distance_m: f64, delta_elev_m: f64, altitude_m: f64) -> Duration {
+        self.inner.time_at(distance_m, delta_elev_m, altitude_m).mul_f64(self.factor)
+    }
+}
+
+// Slows another model down by `slowdown` (0.1 = 10 %) per 1000 m above `threshold_m`, for the thinner air.
+pub struct AltitudePace {
+    pub inner: Rc<dyn PaceModel>,
+    pub threshold_m: f64,
+    pub slowdown: f64
+}
+
+impl PaceModel for AltitudePace {
+    fn time(&self, distance_m: f64, delta_elev_m: f64) -> Duration {
+        self.inner.time(distance_m, delta_elev_m)
+    }
+
+    fn time_at(&self, distance_m: f64, delta_elev_m: f64, altitude_m: f64) -> Duration {
+        let above = (altitude_m - self.threshold_m).max(0.) / 1000.;
+        self.inner.time_at(distance_m, delta_elev_m, altitude_m).mul_f64(1. + self.slowdown * above)
+    }
+}
+
+// Steep descents are walked braking: below `steep_grade` (-0.2 = -20 %) the pace is the one at
+// `steep_grade`, slowed down by e^(braking * extra grade) instead of getting ever faster.
+pub struct DescentPace {
+    pub inner: Rc<dyn PaceModel>,
+    pub steep_grade: f64,
+    pub braking: f64
+}
+
+impl PaceModel for DescentPace {
+    fn time(&self, distance_m: f64, delta_elev_m: f64) -> Duration {
+        self.time_at(distance_m, delta_elev_m, 0.)
+    }
+
+    fn time_at(&self, distance_m: f64, delta_elev_m: f64, altitude_m: f64) -> Duration {
+        let grade = delta_elev_m / distance_m;
+        if !grade.is_finite() || grade >= self.steep_grade {
+            return self.inner.time_at(distance_m, delta_elev_m, altitude_m);
+        }
+
+        self.inner.time_at(distance_m, self.steep_grade * distance_m, altitude_m)
+            .mul_f64((self.braking * (self.steep_grade - grade)).exp())
+    }
+}
+
+// Legs steeper than `grade` (0.45 = 45 %), up or down, are climbed hands on rock at
+// `vertical_rate` meters per hour instead of walked.
+pub struct ScramblePace {
+    pub inner: Rc<dyn PaceModel>,
+    pub grade: f64,
+    pub vertical_rate: f64
+}
+
+impl PaceModel for ScramblePace {
+    fn time(&self, distance_m: f64, delta_elev_m: f64) -> Duration {
+        self.time_at(distance_m, delta_elev_m, 0.)
+    }
+
+    fn time_at(&self, distance_m: f64, delta_elev_m: f64, altitude_m: f64) -> Duration {
+        if distance_m <= 0. || (delta_elev_m / distance_m).abs() <= self.grade {
+            return self.inner.time_at(distance_m, delta_elev_m, altitude_m);
+        }
+
+        Duration::from_secs_f64(delta_elev_m.abs() / self.vertical_rate * 3600.)
+    }
+}
+
+// Pace factor for the carried load: 1.5 % slower per kg above a 5 kg daypack, a 20 kg pack
+// taking about 22 % longer.
+pub fn pack_factor(pack_weight_kg: f64) -> f64 {
+    1. + 0.015 * (pack_weight_kg - 5.).max(0.)
+}
+
+// Exponential slope formula: pace (s/m) = 0.6 * e^(3.5 * (grade + adjustement)).
+pub struct ExponentialPace {
+    pub adjustement: f64
+}
+
+impl ExponentialPace {
+    pub fn new(adjustement: f64) -> Self {
+        Self { adjustement }
+    }
+
+    // Adjustement making the formula take `actual` over legs of (distance (m), grade). The time
+    // scales with e^(3.5 * adjustement), so it is solved in closed form.
+    pub fn fit(legs: &[(f64, f64)], actual: Duration) -> Option<f64> {
+        let base: f64 = legs.iter()
+            .map(|(distance_m, grade)| 0.6 * (3.5 * grade).exp() * distance_m)
+            .sum();
+
+        if base <= 0. || actual.is_zero() {
+            return None;
+        }
+        Some((actual.as_secs_f64() / base).ln() / 3.5)
+    }
+}
+
+impl PaceModel for ExponentialPace {
+    fn time(&self, distance_m: f64, delta_elev_m: f64) -> Duration {
+        if distance_m <= 0. {
+            return Duration::ZERO;
+        }
+
+        // Legs steeper than 45° are GPS noise (points a few cm apart), the formula overflowing on them.
+        let grade = (delta_elev_m / distance_m).clamp(-1., 1.);
+        let segment_speed = 0.6_f64 * (3.5 * (grade + self.adjustement)).exp();
+        Duration::from_secs_f64(segment_speed * distance_m)
+    }
+}
+
+// User supplied formula evaluating to a walking speed in km/h, with `grade` (rise over run),
+// `distance` (m) and `altitude` (m) as variables.
+pub struct ExpressionPace {
+    expression: Node,
+    warned: Cell<bool>
+}
+
+impl ExpressionPace {
+    // Slowest speed (km/h) a leg is walked at, when the formula gives nothing usable.
+    const MIN_SPEED: f64 = 0.5;
+
+    pub fn new(expression: &str) -> Result<Self> {
+        let pace = Self { expression: build_operator_tree(expression)?, warned: Cell::new(false) };
+
+        // Grades from -100 % to +100 % by steps of 5 %.
+        for grade in (-20..=20).map(|step| step as f64 * 0.05) {
+            let speed = pace.speed(1000., grade * 1000., 0.)?;
+            if !speed.is_finite() || speed <= 0. {
+                bail!("formula must evaluate to a positive speed (got {speed} km/h at a {:.0} % grade)", grade * 100.);
+            }
+        }
+
+        Ok(pace)
+    }
+
+    fn speed(&self, distance_m: f64, delta_elev_m: f64, altitude_m: f64) -> Result<f64> {
+        let mut context = HashMapContext::new();
+        context.set_value("grade".into(), Value::Float(delta_elev_m / distance_m))?;
+        context.set_value("distance".into(), Value::Float(distance_m))?;
+        context.set_value("altitude".into(), Value::Float(altitude_m))?;
+
+        Ok(self.expression.eval_number_with_context(&context)?)
+    }
+}
+
+impl PaceModel for ExpressionPace {
+    fn time(&self, distance_m: f64, delta_elev_m: f64) -> Duration {
+        self.time_at(distance_m, delta_elev_m, 0.)
+    }
+
+    fn time_at(&self, distance_m: f64, delta_elev_m: f64, altitude_m: f64) -> Duration {
+        if distance_m <= 0. {
+            return Duration::ZERO;
+        }
+
+        let speed = match self.speed(distance_m, delta_elev_m, altitude_m) {
+            Ok(speed) if speed.is_finite() && speed >= Self::MIN_SPEED => speed,
+            result => {
+                if !self.warned.replace(true) {
+                    match result {
+                        Ok(speed) => warn!("formula gave {speed} km/h at {altitude_m:.0} m, walking at {} km/h there", Self::MIN_SPEED),
+                        Err(e) => warn!("formula failed at {altitude_m:.0} m ({e}), walking at {} km/h there", Self::MIN_SPEED)
+                    }
+                }
+                Self::MIN_SPEED
+            }
+        };
+        Duration::from_secs_f64(distance_m / (speed / 3.6))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seconds(duration: Duration) -> f64 {
+        duration.as_secs_f64()
+    }
+
+    #[test]
+    fn exponential_pace_walks_flat_ground_at_0_6_s_per_meter() {
+        assert!((seconds(ExponentialPace::new(0.).time(1000., 0.)) - 600.).abs() < 1e-6);
+    }
+
+    #[test]
+    fn exponential_pace_is_slower_uphill_and_with_a_positive_adjustement() {
+        let pace = ExponentialPace::new(0.);
+        let flat = pace.time(1000., 0.);
+        assert!(pace.time(1000., 100.) > flat);
+        assert!(pace.time(1000., -100.) < flat);
+        assert!(ExponentialPace::new(0.1).time(1000., 0.) > flat);
+    }
+
+    #[test]
+    fn exponential_pace_takes_no_time_over_no_distance() {
+        assert_eq!(ExponentialPace::new(0.).time(0., 10.), Duration::ZERO);
+    }
+
+    #[test]
+    fn exponential_pace_clamps_vertical_legs() {
+        let pace = ExponentialPace::new(0.);
+        assert_eq!(pace.time(0.01, 1000.), pace.time(0.01, 0.01));
+    }
+
+    #[test]
+    fn expression_pace_times_legs_at_the_formula_speed() {
+        let pace = ExpressionPace::new("5 - grade * 2").unwrap();
+        assert!((seconds(pace.time(1000., 0.)) - 720.).abs() < 1e-6);
+        assert!((seconds(pace.time(1000., 500.)) - 900.).abs() < 1e-6);
+    }
+
+    #[test]
+    fn expression_pace_rejects_formulas_that_are_not_positive_on_some_grade() {
+        assert!(ExpressionPace::new("5 - grade * 2").is_ok());
+        assert!(ExpressionPace::new("grade * 4").is_err());
+        assert!(ExpressionPace::new("4 +").is_err());
+    }
+
+    #[test]
+    fn expression_pace_walks_at_least_at_the_minimum_speed() {
+        // Positive on every grade the formula is checked against, but not at altitude.
+        let pace = ExpressionPace::new("4 - altitude / 500").unwrap();
+        assert!((seconds(pace.time_at(500., 0., 3000.)) - 3600.).abs() < 1e-6);
+        assert_eq!(pace.time_at(0., 0., 3000.), Duration::ZERO);
+    }
+
+    #[test]
+    fn pace_plan_uses_the_last_section_covering_a_point() {
+        let mut plan = PacePlan::new(Rc::new(ExponentialPace::new(0.)));
+        plan.sections.push(PaceSection { from_km: 2., to_km: 6., model: Rc::new(ExponentialPace::new(0.1)) });
+        plan.sections.push(PaceSection { from_km: 4., to_km: 8., model: Rc::new(ExponentialPace::new(0.2)) });
+
+        let flat = |km: f64| plan.model_at(km).time(1000., 0.);
+        assert_eq!(flat(1.), ExponentialPace::new(0.).time(1000., 0.));
+        assert_eq!(flat(3.), ExponentialPace::new(0.1).time(1000., 0.));
+        assert_eq!(flat(5.), ExponentialPace::new(0.2).time(1000., 0.));
+        assert_eq!(flat(8.), ExponentialPace::new(0.).time(1000., 0.));
+    }
+
+    #[test]
+    fn mirrored_pace_plan_flips_sections_along_the_route() {
+        let mut plan = PacePlan::new(Rc::new(ExponentialPace::new(0.)));
+        plan.sections.push(PaceSection { from_km: 1., to_km: 3., model: Rc::new(ExponentialPace::new(0.1)) });
+
+        let mirrored = plan.mirrored(10.);
+        assert_eq!(mirrored.sections[0].from_km, 7.);
+        assert_eq!(mirrored.sections[0].to_km, 9.);
+        assert_eq!(mirrored.model_at(8.).time(1000., 0.), ExponentialPace::new(0.1).time(1000., 0.));
+        assert_eq!(mirrored.model_at(2.).time(1000., 0.), ExponentialPace::new(0.).time(1000., 0.));
+    }
+
+    #[test]
+    fn scaled_pace_plan_multiplies_every_model() {
+        let mut plan = PacePlan::new(Rc::new(ExponentialPace::new(0.)));
+        plan.sections.push(PaceSection { from_km: 1., to_km: 3., model: Rc::new(ExponentialPace::new(0.1)) });
+
+        let scaled = plan.scaled(1.5);
+        assert_eq!(scaled.model_at(0.).time(1000., 0.), plan.model_at(0.).time(1000., 0.).mul_f64(1.5));
+        assert_eq!(scaled.model_at(2.).time(1000., 50.), plan.model_at(2.).time(1000., 50.).mul_f64(1.5));
+    }
+
+    #[test]
+    fn fit_recovers_the_adjustement_of_a_recorded_time() {
+        let legs = [(1000., 0.), (500., 0.2), (800., -0.1)];
+        let pace = ExponentialPace::new(0.08);
+        let actual = legs.iter()
+            .map(|(distance_m, grade)| pace.time(*distance_m, grade * distance_m))
+            .sum();
+
+        let adjustement = ExponentialPace::fit(&legs, actual).unwrap();
+        assert!((adjustement - 0.08).abs() < 1e-6);
+    }
+
+    #[test]
+    fn fit_needs_legs_and_a_time() {
+        assert_eq!(ExponentialPace::fit(&[], Duration::from_secs(3600)), None);
+        assert_eq!(ExponentialPace::fit(&[(1000., 0.)], Duration::ZERO), None);
+    }
+
+    #[test]
+    fn altitude_pace_slows_down_above_the_threshold_only() {
+        let flat = ExponentialPace::new(0.).time(1000., 0.);
+        let plan = PacePlan::new(Rc::new(ExponentialPace::new(0.))).altitude_penalized(2500., 0.1);
+        let pace = plan.model_at(0.);
+
+        assert_eq!(pace.time_at(1000., 0., 1500.), flat);
+        assert_eq!(pace.time_at(1000., 0., 2500.), flat);
+        assert!((seconds(pace.time_at(1000., 0., 4500.)) - seconds(flat) * 1.2).abs() < 1e-6);
+        assert_eq!(pace.time(1000., 0.), flat);
+    }
+
+    #[test]
+    fn pack_factor_counts_the_weight_above_a_daypack() {
+        assert_eq!(pack_factor(0.), 1.);
+        assert_eq!(pack_factor(5.), 1.);
+        assert!((pack_factor(20.) - 1.225).abs() < 1e-9);
+    }
+
+    #[test]
+    fn conditions_only_ever_slow_down() {
+        assert_eq!(Conditions::default().factor(), 1.);
+        let factors: Vec<f64> = Conditions::ALL.iter().map(|conditions| conditions.factor()).collect();
+        assert!(factors.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn descent_pace_brakes_below_the_steep_grade() {
+        let inner = ExponentialPace::new(0.);
+        let plan = PacePlan::new(Rc::new(ExponentialPace::new(0.))).descent_limited(-0.2, 3.);
+        let pace = plan.model_at(0.);
+
+        assert_eq!(pace.time(1000., -100.), inner.time(1000., -100.));
+        let steep = inner.time(1000., -200.);
+        let expected = seconds(steep) * (3. * 0.2_f64).exp();
+        assert!((seconds(pace.time(1000., -400.)) - expected).abs() < 1e-6);
+        assert!(pace.time(1000., -400.) > steep);
+    }
+
+    #[test]
+    fn scramble_pace_climbs_steep_legs_at_the_vertical_rate() {
+        let inner = ExponentialPace::new(0.);
+        let plan = PacePlan::new(Rc::new(ExponentialPace::new(0.))).scrambling(0.45, 300.);
+        let pace = plan.model_at(0.);
+
+        assert_eq!(pace.time(100., 40.), inner.time(100., 40.));
+        assert_eq!(pace.time(100., 60.), Duration::from_secs(720));
+        assert_eq!(pace.time(100., -60.), Duration::from_secs(720));
+        assert_eq!(pace.time(0., 10.), Duration::ZERO);
+    }
+}
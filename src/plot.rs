@@ -0,0 +1,62 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use plotters::{coord::Shift, prelude::*};
+use time::OffsetDateTime;
+
+use crate::units::Units;
+use crate::utils::{format_clock, hourly_positions, PathStats};
+
+const SIZE: (u32, u32) = (1600, 600);
+
+// Elevation profile with a mark at each full hour of walking (breaks included), labelled with the
+// clock time when the departure is known.
+pub fn write_profile_png(stats: &PathStats, name: &str, start: Option<OffsetDateTime>, units: Units, path: &Path) -> Result<()> {
+    let root = BitMapBackend::new(path, SIZE).into_drawing_area();
+    draw_profile(&root, stats, name, start, units).map_err(|e| anyhow!("{e}"))
+}
+
+// Same profile as a vector image, for web pages and print.
+pub fn write_profile_svg(stats: &PathStats, name: &str, start: Option<OffsetDateTime>, units: Units, path: &Path) -> Result<()> {
+    let root = SVGBackend::new(path, SIZE).into_drawing_area();
+    draw_profile(&root, stats, name, start, units).map_err(|e| anyhow!("{e}"))
+}
+
+fn draw_profile<DB: DrawingBackend>(root: &DrawingArea<DB, Shift>, stats: &PathStats, name: &str, start: Option<OffsetDateTime>, units: Units) -> Result<(), DrawingAreaErrorKind<DB::ErrorType>> {
+    let profile: Vec<(f64, f64)> = stats.points.iter()
+        .filter_map(|point| Some((units.distance(point.distance), units.elevation(point.elevation?))))
+        .collect();
+    let length = profile.last().map(|(distance, _)| *distance).unwrap_or(0.).max(0.1);
+    let (low, high) = profile.iter().fold((f64::MAX, f64::MIN), |(low, high), (_, elevation)| (low.min(*elevation), high.max(*elevation)));
+    let (low, high) = if low <= high { (low, high) } else { (0., 1.) };
+    let margin = ((high - low) * 0.15).max(10.);
+    let floor = low - margin;
+
+    root.fill(&WHITE)?;
+    let mut chart = ChartBuilder::on(root)
+        .caption(name, ("sans-serif", 28))
+        .margin(20)
+        .x_label_area_size(40)
+        .y_label_area_size(60)
+        .build_cartesian_2d(0_f64..length, floor..high + margin)?;
+    chart.configure_mesh()
+        .x_desc(units.distance_unit())
+        .y_desc(units.elevation_unit())
+        .draw()?;
+
+    chart.draw_series(AreaSeries::new(profile.iter().copied(), floor, GREEN.mix(0.2)).border_style(GREEN.stroke_width(2)))?;
+
+    for position in hourly_positions(&stats.points, &[]) {
+        let x = units.distance(position.distance);
+        let y = position.elevation.map(|elevation| units.elevation(elevation)).unwrap_or(floor);
+        let label = match start {
+            Some(start) => format_clock(start, std::time::Duration::from_secs(position.hour * 3600)),
+            None => format!("{}h", position.hour)
+        };
+        chart.draw_series(std::iter::once(PathElement::new(vec![(x, floor), (x, y)], BLACK.mix(0.4))))?;
+        chart.draw_series(std::iter::once(Circle::new((x, y), 4, BLACK.filled())))?;
+        chart.draw_series(std::iter::once(Text::new(label, (x, y + margin / 3.), ("sans-serif", 18))))?;
+    }
+
+    root.present()
+}
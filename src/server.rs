@@ -0,0 +1,101 @@
+use std::{io::{Cursor, Read}, rc::Rc};
+
+use anyhow::{anyhow, bail, Result};
+use clap::ValueEnum;
+use gpx::Track;
+use serde_json::json;
+use tiny_http::{Header, Method, Request, Response, Server};
+use tracing::info;
+
+use crate::pace::{ExponentialPace, PacePlan};
+use crate::utils::{self, read_gpx};
+use crate::{adjusted_plan, analysis_options, get_speed_adjustement, import, Args, Terrain};
+
+// Larger bodies are cut, ten times a long multi-day recording.
+const MAX_BODY: u64 = 64 * 1024 * 1024;
+
+// POST /analyse with a track file as body answers the stats and splits as JSON, like --json. The
+// query string may set format (gpx, tcx, fit, kml, kmz), terrain, split_length (m) and track (from
+// 1, every track otherwise), the other settings coming from the command line. Requests are answered
+// one at a time, from any origin.
+pub fn serve(address: &str, args: &Args) -> Result<()> {
+    let server = Server::http(address).map_err(|e| anyhow!("{e}"))?;
+    eprintln!("Listening on http://{address}/analyse");
+
+    for mut request in server.incoming_requests() {
+        let method = request.method().clone();
+        let url = request.url().to_string();
+        let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+
+        let (status, body) = match (&method, path) {
+            (Method::Options, _) => (204, String::new()),
+            (Method::Post, "/analyse") => match analyse(&mut request, query, args) {
+                Ok(body) => (200, body),
+                Err(e) => (400, json!({ "error": format!("{e:#}") }).to_string())
+            },
+            (_, "/analyse") => (405, json!({ "error": "POST a track file" }).to_string()),
+            _ => (404, json!({ "error": "not found" }).to_string())
+        };
+        info!("{method} {url} -> {status}");
+
+        let response = Response::from_string(body)
+            .with_status_code(status)
+            .with_header(header("Content-Type", "application/json"))
+            .with_header(header("Access-Control-Allow-Origin", "*"))
+            .with_header(header("Access-Control-Allow-Methods", "POST, OPTIONS"))
+            .with_header(header("Access-Control-Allow-Headers", "Content-Type"));
+        if let Err(e) = request.respond(response) {
+            eprintln!("{} {e}", console::style("Cannot answer:").yellow());
+        }
+    }
+    Ok(())
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("static header")
+}
+
+fn analyse(request: &mut Request, query: &str, args: &Args) -> Result<String> {
+    let parameter = |key: &str| query.split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(name, _)| *name == key)
+        .map(|(_, value)| value.to_string());
+
+    let mut body = Vec::new();
+    request.as_reader().take(MAX_BODY).read_to_end(&mut body)?;
+    let gpx = match parameter("format").as_deref().unwrap_or("gpx") {
+        "gpx" => gpx::read(body.as_slice()).map_err(|e| anyhow!("{e}"))?,
+        "tcx" => import::read_tcx(body.as_slice())?,
+        "fit" => import::read_fit(&mut body.as_slice())?,
+        "kml" => import::read_kml(body.as_slice())?,
+        "kmz" => import::read_kmz(Cursor::new(body))?,
+        format => bail!("unknown format \"{format}\", expected one of {}", import::EXTENSIONS.join(", "))
+    };
+
+    let terrain = match parameter("terrain") {
+        Some(terrain) => Terrain::from_str(&terrain, true).map_err(|e| anyhow!("terrain: {e}"))?,
+        None => args.terrain.unwrap_or(Terrain::Path)
+    };
+    let split_length = match parameter("split_length") {
+        Some(length) => length.parse::<u32>().map_err(|e| anyhow!("split_length: {e}"))?,
+        None => args.split_length
+    };
+    let number = parameter("track").map(|number| number.parse::<usize>()).transpose().map_err(|e| anyhow!("track: {e}"))?;
+
+    // Selected tracks form one itinerary, as on the command line.
+    let mut track = Track::new();
+    for (i, source) in gpx.tracks.into_iter().enumerate() {
+        if number.is_none_or(|number| number == i + 1) {
+            track.segments.extend(source.segments);
+        }
+    }
+    if track.segments.iter().all(|segment| segment.points.is_empty()) {
+        bail!("no track point");
+    }
+
+    let plan = adjusted_plan(PacePlan::new(Rc::new(ExponentialPace::new(get_speed_adjustement(terrain, args.calibrated.as_ref()) as f64))), args);
+    let first = track.segments.iter().find_map(|segment| segment.points.first()).cloned();
+    let mut stats = read_gpx(&mut track, &plan, &analysis_options(args, first.as_ref(), false));
+    stats.splits = utils::distance_splits(&stats.points, split_length as f64 / 1000.);
+    Ok(serde_json::to_string(&stats)?)
+}
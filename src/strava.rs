@@ -0,0 +1,123 @@
+use std::env;
+
+use anyhow::{anyhow, Result};
+use geo_types::Point;
+use gpx::{Gpx, GpxVersion, Track, TrackSegment, Waypoint};
+use serde_json::Value;
+use time::{format_description::well_known::Rfc3339, Duration, OffsetDateTime};
+
+use crate::config::StravaConfig;
+
+const API_URL: &str = "https://www.strava.com/api/v3";
+const TOKEN_URL: &str = "https://www.strava.com/oauth/token";
+
+#[derive(Clone)]
+pub enum StravaSource {
+    Route(u64),
+    Activity(u64)
+}
+
+impl StravaSource {
+    // Accepts strava.com/routes/ID and strava.com/activities/ID URLs, a bare ID is an activity.
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let input = input.trim().trim_end_matches('/');
+        let id = |text: &str| text.split(['?', '#']).next().unwrap_or("").parse::<u64>()
+            .map_err(|_| format!("invalid Strava ID \"{text}\""));
+
+        if let Some((_, id_text)) = input.split_once("/routes/") {
+            Ok(Self::Route(id(id_text)?))
+        }
+        else if let Some((_, id_text)) = input.split_once("/activities/") {
+            Ok(Self::Activity(id(id_text)?))
+        }
+        else {
+            Ok(Self::Activity(id(input)?))
+        }
+    }
+
+    pub fn file_name(&self) -> String {
+        match self {
+            Self::Route(id) => format!("strava_route_{id}.gpx"),
+            Self::Activity(id) => format!("strava_activity_{id}.gpx")
+        }
+    }
+}
+
+// STRAVA_ACCESS_TOKEN wins, otherwise the refresh token from config.toml is exchanged for one.
+pub fn access_token(config: Option<&StravaConfig>) -> Result<String> {
+    if let Ok(token) = env::var("STRAVA_ACCESS_TOKEN") {
+        return Ok(token);
+    }
+
+    let config = config.ok_or(anyhow!("set STRAVA_ACCESS_TOKEN or a [strava] section in config.toml"))?;
+    let response: Value = ureq::post(TOKEN_URL)
+        .send_form(&[
+            ("client_id", config.client_id.as_str()),
+            ("client_secret", config.client_secret.as_str()),
+            ("refresh_token", config.refresh_token.as_str()),
+            ("grant_type", "refresh_token")
+        ])?
+        .into_json()?;
+
+    response["access_token"].as_str()
+        .map(|token| token.to_string())
+        .ok_or(anyhow!("Strava did not return an access token"))
+}
+
+pub fn download(source: &StravaSource, token: &str) -> Result<Gpx> {
+    let authorization = format!("Bearer {token}");
+
+    match source {
+        StravaSource::Route(id) => {
+            let response = ureq::get(&format!("{API_URL}/routes/{id}/export_gpx"))
+                .set("Authorization", &authorization)
+                .call()?;
+            gpx::read(response.into_reader()).map_err(|e| anyhow!("{e}"))
+        },
+        StravaSource::Activity(id) => {
+            let activity: Value = ureq::get(&format!("{API_URL}/activities/{id}"))
+                .set("Authorization", &authorization)
+                .call()?
+                .into_json()?;
+            let streams: Value = ureq::get(&format!("{API_URL}/activities/{id}/streams"))
+                .query("keys", "latlng,altitude,time")
+                .query("key_by_type", "true")
+                .set("Authorization", &authorization)
+                .call()?
+                .into_json()?;
+
+            activity_gpx(&activity, &streams)
+        }
+    }
+}
+
+// Activities have no GPX export in the API, the track is rebuilt from the latlng/altitude/time streams.
+fn activity_gpx(activity: &Value, streams: &Value) -> Result<Gpx> {
+    let positions = streams["latlng"]["data"].as_array().ok_or(anyhow!("activity has no GPS stream"))?;
+    let start = activity["start_date"].as_str().and_then(|date| OffsetDateTime::parse(date, &Rfc3339).ok());
+
+    let mut segment = TrackSegment::new();
+    for (index, position) in positions.iter().enumerate() {
+        let (Some(latitude), Some(longitude)) = (position[0].as_f64(), position[1].as_f64()) else {
+            continue;
+        };
+
+        let mut waypoint = Waypoint::new(Point::new(longitude, latitude));
+        waypoint.elevation = streams["altitude"]["data"][index].as_f64();
+        if let (Some(start), Some(seconds)) = (start, streams["time"]["data"][index].as_i64()) {
+            waypoint.time = Some((start + Duration::seconds(seconds)).into());
+        }
+        segment.points.push(waypoint);
+    }
+
+    let mut track = Track::new();
+    track.name = activity["name"].as_str().map(|name| name.to_string());
+    track.segments.push(segment);
+
+    Ok(Gpx {
+        version: GpxVersion::Gpx11,
+        creator: Some(String::from("mountain_snail")),
+        tracks: vec![track],
+        ..Default::default()
+    })
+}
@@ -0,0 +1,159 @@
+use std::{collections::BTreeMap, io::Read, time::Instant};
+
+use anyhow::{anyhow, Result};
+use geo_types::Point;
+use gpx::Waypoint;
+use time::OffsetDateTime;
+use tracing::info;
+use xml::reader::{EventReader, XmlEvent};
+
+use crate::pace::PacePlan;
+use crate::utils::{distance, flat_equivalent, grade_adjusted_pace, weighted_quantile, AnalysisOptions, PathStats, SegmentStats};
+
+// Analyses a GPX document trackpoint by trackpoint while it is parsed, keeping only the previous
+// point, so that recordings too big to be loaded can still be summed up. Every track is walked as
+// one itinerary. There is no smoothing and nothing that needs the points afterwards (splits,
+// checkpoints, climbs, profile).
+pub fn analyse_gpx<R: Read>(reader: R, plan: &PacePlan, options: &AnalysisOptions) -> Result<PathStats> {
+    let started = Instant::now();
+    let start = options.start.unwrap_or_else(OffsetDateTime::now_utc);
+    let mut stats = PathStats { min_height: f64::MAX, ..Default::default() };
+
+    let mut text = String::new();
+    let mut point: Option<Waypoint> = None;
+    let mut previous: Option<Waypoint> = None;
+    let mut reference_elevation: Option<f64> = None;
+    let mut segment_start = SegmentStats::default();
+    let mut total_points = 0;
+
+    // Leg lengths per rounded meter of altitude, enough for the quantiles without keeping the points.
+    let mut altitudes: BTreeMap<i64, f64> = BTreeMap::new();
+    let (mut altitude_sum, mut total_weight) = (0., 0.);
+    let mut flat_distance = 0.;
+
+    for event in EventReader::new(reader) {
+        match event? {
+            XmlEvent::StartElement { name, attributes, .. } => {
+                match name.local_name.as_str() {
+                    "trkseg" => {
+                        (previous, reference_elevation) = (None, None);
+                        segment_start = SegmentStats {
+                            points: total_points,
+                            distance: stats.distance,
+                            d_plus: stats.d_plus,
+                            d_minus: stats.d_minus,
+                            duration: stats.duration
+                        };
+                    },
+                    "trkpt" => {
+                        let coordinate = |key: &str| attributes.iter()
+                            .find(|attribute| attribute.name.local_name == key)
+                            .and_then(|attribute| attribute.value.trim().parse::<f64>().ok());
+                        let (Some(latitude), Some(longitude)) = (coordinate("lat"), coordinate("lon")) else {
+                            return Err(anyhow!("track point without lat/lon after {total_points} points"));
+                        };
+                        point = Some(Waypoint::new(Point::new(longitude, latitude)));
+                    },
+                    _ => {}
+                }
+                text.clear();
+            },
+            XmlEvent::Characters(characters) | XmlEvent::CData(characters) => text.push_str(&characters),
+            XmlEvent::EndElement { name } => {
+                match name.local_name.as_str() {
+                    "ele" => if let Some(point) = &mut point {
+                        point.elevation = text.trim().parse::<f64>().ok();
+                    },
+                    "trkpt" => if let Some(b) = point.take() {
+                        total_points += 1;
+                        if b.elevation.is_none() {
+                            stats.missing_elevations += 1;
+                        }
+                        let Some(a) = previous.replace(b) else {
+                            continue;
+                        };
+                        let Some(b) = &previous else {
+                            continue;
+                        };
+
+                        let kilometers = distance(&a, b, options.distance_algorithm);
+                        let pace = plan.model_at(stats.distance);
+                        stats.distance += kilometers;
+
+                        let mut delta_elevation = 0.;
+                        if let (Some(a_elevation), Some(b_elevation)) = (a.elevation, b.elevation) {
+                            // Same D+/D- threshold as the in-memory analysis.
+                            let reference = *reference_elevation.get_or_insert(a_elevation);
+                            if b_elevation - reference >= options.elevation_threshold && b_elevation > reference {
+                                stats.d_plus += b_elevation - reference;
+                                reference_elevation = Some(b_elevation);
+                            }
+                            else if reference - b_elevation >= options.elevation_threshold && b_elevation < reference {
+                                stats.d_minus += reference - b_elevation;
+                                reference_elevation = Some(b_elevation);
+                            }
+
+                            stats.max_height = stats.max_height.max(b_elevation);
+                            stats.min_height = stats.min_height.min(b_elevation);
+
+                            delta_elevation = b_elevation - a_elevation;
+                            let altitude = (a_elevation + b_elevation) / 2.;
+                            *altitudes.entry(altitude.round() as i64).or_insert(0.) += kilometers;
+                            altitude_sum += altitude * kilometers;
+                            total_weight += kilometers;
+                        }
+
+                        if kilometers > 0. {
+                            flat_distance += flat_equivalent(kilometers, delta_elevation / (kilometers * 1000.));
+                        }
+                        let mut leg_duration = pace.time_at(kilometers * 1000., delta_elevation, b.elevation.unwrap_or(0.));
+                        if let Some(heat) = &options.heat && kilometers > 0. {
+                            let penalized = heat.apply(leg_duration, delta_elevation / (kilometers * 1000.));
+                            stats.heat_delay += penalized - leg_duration;
+                            leg_duration = penalized;
+                        }
+                        if let Some(night) = &options.night {
+                            let penalized = night.apply(leg_duration, start + stats.duration + stats.break_duration);
+                            stats.night_delay += penalized - leg_duration;
+                            leg_duration = penalized;
+                        }
+                        stats.duration += leg_duration;
+                        if delta_elevation > 0. {
+                            stats.uphill_duration += leg_duration;
+                        }
+                        else if delta_elevation < 0. {
+                            stats.downhill_duration += leg_duration;
+                        }
+                        stats.break_duration += options.breaks.breaks_between(stats.duration - leg_duration, stats.duration);
+                    },
+                    "trkseg" => stats.segments.push(SegmentStats {
+                        points: total_points - segment_start.points,
+                        distance: stats.distance - segment_start.distance,
+                        d_plus: stats.d_plus - segment_start.d_plus,
+                        d_minus: stats.d_minus - segment_start.d_minus,
+                        duration: stats.duration - segment_start.duration
+                    }),
+                    _ => {}
+                }
+                text.clear();
+            },
+            _ => {}
+        }
+    }
+
+    if total_points == 0 {
+        return Err(anyhow!("no track point in GPX file"));
+    }
+    info!("{} points streamed in {:.2?}", total_points, started.elapsed());
+
+    let altitudes: Vec<(f64, f64)> = altitudes.into_iter().map(|(altitude, weight)| (altitude as f64, weight)).collect();
+    if total_weight > 0. {
+        stats.average_altitude = altitude_sum / total_weight;
+    }
+    stats.grade_adjusted_pace = grade_adjusted_pace(stats.duration, flat_distance);
+    stats.median_altitude = weighted_quantile(&altitudes, total_weight, 0.5);
+    stats.lower_quartile_altitude = weighted_quantile(&altitudes, total_weight, 0.25);
+    stats.upper_quartile_altitude = weighted_quantile(&altitudes, total_weight, 0.75);
+
+    Ok(stats)
+}
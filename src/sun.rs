@@ -0,0 +1,44 @@
+use std::{f64::consts::PI, time::Duration};
+
+use time::{Date, OffsetDateTime};
+
+// Sunrise and sunset (UTC) for the given day, using NOAA's approximate solar equations.
+// `None` during polar day or night.
+pub fn sun_times(date: Date, latitude: f64, longitude: f64) -> Option<(OffsetDateTime, OffsetDateTime)> {
+    let gamma = 2. * PI / 365. * (date.ordinal() as f64 - 0.5);
+    let equation_of_time = 229.18 * (0.000075 + 0.001868 * gamma.cos() - 0.032077 * gamma.sin()
+        - 0.014615 * (2. * gamma).cos() - 0.040849 * (2. * gamma).sin());
+    let declination = 0.006918 - 0.399912 * gamma.cos() + 0.070257 * gamma.sin()
+        - 0.006758 * (2. * gamma).cos() + 0.000907 * (2. * gamma).sin()
+        - 0.002697 * (3. * gamma).cos() + 0.00148 * (3. * gamma).sin();
+
+    let latitude = latitude.to_radians();
+    let cos_hour_angle = 90.833_f64.to_radians().cos() / (latitude.cos() * declination.cos()) - latitude.tan() * declination.tan();
+    if !(-1. ..=1.).contains(&cos_hour_angle) {
+        return None;
+    }
+    let hour_angle = cos_hour_angle.acos().to_degrees();
+
+    let midnight = date.midnight().assume_utc();
+    let sunrise = 720. - 4. * (longitude + hour_angle) - equation_of_time;
+    let sunset = 720. - 4. * (longitude - hour_angle) - equation_of_time;
+
+    Some((
+        midnight + time::Duration::seconds_f64(sunrise * 60.),
+        midnight + time::Duration::seconds_f64(sunset * 60.)
+    ))
+}
+
+// Time spent walking in the dark between `start` and `finish`, for a single day.
+pub fn darkness(start: OffsetDateTime, finish: OffsetDateTime, sunrise: OffsetDateTime, sunset: OffsetDateTime) -> Duration {
+    let mut dark = Duration::ZERO;
+
+    if start < sunrise {
+        dark += (finish.min(sunrise) - start).unsigned_abs();
+    }
+    if finish > sunset {
+        dark += (finish - start.max(sunset)).unsigned_abs();
+    }
+
+    dark
+}
@@ -0,0 +1,220 @@
+use std::{path::PathBuf, rc::Rc};
+
+use anyhow::Result;
+use gpx::Gpx;
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout},
+    style::{Color, Modifier, Style},
+    symbols::Marker,
+    text::Line,
+    widgets::{Axis, Block, Chart, Dataset, GraphType, List, ListState, Paragraph},
+    DefaultTerminal, Frame
+};
+
+use crate::config::Config;
+use crate::i18n::tr;
+use crate::pace::{ExponentialPace, PacePlan};
+use crate::utils::{self, read_gpx, PathStats};
+use crate::{adjusted_plan, analysis_options, find_gpx_files, get_speed_adjustement, import, Args, Terrain};
+
+const TERRAINS: [Terrain; 4] = [Terrain::Road, Terrain::Path, Terrain::Track, Terrain::Alpine];
+
+#[derive(PartialEq, Clone, Copy)]
+enum Focus {
+    Files,
+    Tracks
+}
+
+struct App<'a> {
+    args: &'a Args,
+    files: Vec<PathBuf>,
+    file_list: ListState,
+    gpx: Option<Gpx>,
+    track_list: ListState,
+    focus: Focus,
+    terrain: usize,
+    reverse: bool,
+    stats: Option<PathStats>,
+    // Why the selected file couldn't be analysed.
+    error: Option<String>
+}
+
+// Files given on the command line, otherwise those of the configured GPX directories.
+pub fn run(config: &Config, args: &Args) -> Result<()> {
+    let mut files = args.files.clone();
+    if files.is_empty() {
+        for dir in config.gpx_dirs() {
+            find_gpx_files(&dir, config.search_depth(), &mut files);
+        }
+    }
+
+    let mut app = App {
+        args,
+        files,
+        file_list: ListState::default(),
+        gpx: None,
+        track_list: ListState::default(),
+        focus: Focus::Files,
+        terrain: args.terrain.and_then(|terrain| TERRAINS.iter().position(|t| *t == terrain)).unwrap_or(1),
+        reverse: false,
+        stats: None,
+        error: None
+    };
+    if !app.files.is_empty() {
+        app.file_list.select(Some(0));
+        app.load_file();
+    }
+
+    let mut terminal = ratatui::init();
+    let result = app.event_loop(&mut terminal);
+    ratatui::restore();
+    result
+}
+
+impl App<'_> {
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Tab => self.focus = if self.focus == Focus::Files { Focus::Tracks } else { Focus::Files },
+                KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+                KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+                KeyCode::Enter if self.focus == Focus::Files => self.load_file(),
+                KeyCode::Char('t') => {
+                    self.terrain = (self.terrain + 1) % TERRAINS.len();
+                    self.analyse();
+                },
+                KeyCode::Char('r') => {
+                    self.reverse = !self.reverse;
+                    self.analyse();
+                },
+                _ => {}
+            }
+        }
+    }
+
+    // Files are only opened on Enter, parsing big ones on every key press would lag.
+    fn move_selection(&mut self, step: isize) {
+        let (list, len) = match self.focus {
+            Focus::Files => (&mut self.file_list, self.files.len()),
+            Focus::Tracks => (&mut self.track_list, self.gpx.as_ref().map(|gpx| gpx.tracks.len()).unwrap_or(0))
+        };
+        if len == 0 {
+            return;
+        }
+
+        list.select(Some(list.selected().unwrap_or(0).saturating_add_signed(step).min(len - 1)));
+        if self.focus == Focus::Tracks {
+            self.analyse();
+        }
+    }
+
+    fn load_file(&mut self) {
+        let Some(path) = self.file_list.selected().and_then(|index| self.files.get(index)) else {
+            return;
+        };
+
+        match import::read_file(path) {
+            Ok(gpx) => {
+                (self.gpx, self.stats, self.error) = (Some(gpx), None, None);
+                self.track_list.select(Some(0));
+                self.analyse();
+            },
+            Err(e) => {
+                (self.gpx, self.stats) = (None, None);
+                self.error = Some(format!("{}: {e:#}", path.display()));
+            }
+        }
+    }
+
+    fn analyse(&mut self) {
+        let track = self.gpx.as_ref()
+            .zip(self.track_list.selected())
+            .and_then(|(gpx, index)| gpx.tracks.get(index));
+        let Some(track) = track else {
+            return;
+        };
+
+        let mut track = if self.reverse { utils::reversed(track) } else { track.clone() };
+        let plan = adjusted_plan(PacePlan::new(Rc::new(ExponentialPace::new(get_speed_adjustement(TERRAINS[self.terrain], self.args.calibrated.as_ref()) as f64))), self.args);
+        let first = track.segments.iter().find_map(|segment| segment.points.first()).cloned();
+        self.stats = Some(read_gpx(&mut track, &plan, &analysis_options(self.args, first.as_ref(), false)));
+        self.error = None;
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [left, right] = Layout::horizontal([Constraint::Percentage(35), Constraint::Fill(1)]).areas(frame.area());
+        let [files_area, tracks_area] = Layout::vertical([Constraint::Fill(2), Constraint::Fill(1)]).areas(left);
+        let [stats_area, profile_area, help_area] = Layout::vertical([Constraint::Length(9), Constraint::Fill(1), Constraint::Length(1)]).areas(right);
+
+        let block = |title: &str, focused: bool| Block::bordered()
+            .title(title.to_string())
+            .border_style(if focused { Style::default().fg(Color::Cyan) } else { Style::default() });
+        let highlight = Style::default().add_modifier(Modifier::REVERSED);
+
+        let files = List::new(self.files.iter().map(|path| path.file_name().unwrap_or_default().to_string_lossy().to_string()))
+            .block(block("Files", self.focus == Focus::Files))
+            .highlight_style(highlight);
+        frame.render_stateful_widget(files, files_area, &mut self.file_list);
+
+        let track_names: Vec<String> = self.gpx.iter()
+            .flat_map(|gpx| gpx.tracks.iter())
+            .map(|track| track.name.clone().unwrap_or_else(|| String::from("Default")))
+            .collect();
+        let tracks = List::new(track_names)
+            .block(block(tr("Track"), self.focus == Focus::Tracks))
+            .highlight_style(highlight);
+        frame.render_stateful_widget(tracks, tracks_area, &mut self.track_list);
+
+        let units = self.args.units;
+        let lines: Vec<Line> = match (&self.stats, &self.error) {
+            (_, Some(error)) => vec![Line::styled(error.clone(), Style::default().fg(Color::Red))],
+            (Some(stats), None) => vec![
+                Line::from(format!("{} - {} D+ {} D-", units.format_distance(stats.distance, 2), units.format_elevation(stats.d_plus), units.format_elevation(stats.d_minus))),
+                Line::from(format!("{}: {} - {}", tr("Range"), units.format_elevation(stats.min_height), units.format_elevation(stats.max_height))),
+                Line::styled(
+                    format!("{}: {} {}, {} {}", tr("Time"), utils::format_duration(stats.duration), tr("walking"), utils::format_duration(stats.duration + stats.break_duration), tr("with breaks")),
+                    Style::default().add_modifier(Modifier::BOLD)
+                ),
+                Line::from(format!("{}: {}", tr("Average altitude"), units.format_elevation(stats.average_altitude))),
+                Line::from(format!("{}: {}{}", tr("Terrain"), TERRAINS[self.terrain].name(), if self.reverse { format!(" - {}", tr("Reverse direction:").trim_end_matches([' ', ':'])) } else { String::new() }))
+            ],
+            (None, None) => vec![Line::from("Select a file and press Enter")]
+        };
+        frame.render_widget(Paragraph::new(lines).block(block(tr("Track info:").trim_end_matches([' ', ':']), false)), stats_area);
+
+        let profile: Vec<(f64, f64)> = self.stats.iter()
+            .flat_map(|stats| stats.points.iter())
+            .filter_map(|point| Some((units.distance(point.distance), units.elevation(point.elevation?))))
+            .collect();
+        let profile_block = block(tr("Elevation profile:").trim_end_matches([' ', ':']), false);
+        if let Some(&(length, _)) = profile.last() {
+            let (low, high) = profile.iter().fold((f64::MAX, f64::MIN), |(low, high), (_, elevation)| (low.min(*elevation), high.max(*elevation)));
+            let dataset = Dataset::default()
+                .marker(Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(Color::Green))
+                .data(&profile);
+            let chart = Chart::new(vec![dataset])
+                .block(profile_block)
+                .x_axis(Axis::default().bounds([0., length]).labels([String::from("0"), format!("{length:.1} {}", units.distance_unit())]))
+                .y_axis(Axis::default().bounds([low, high]).labels([format!("{low:.0}"), format!("{high:.0} {}", units.elevation_unit())]));
+            frame.render_widget(chart, profile_area);
+        }
+        else {
+            frame.render_widget(profile_block, profile_area);
+        }
+
+        frame.render_widget(Line::styled("↑↓ move  Enter open  Tab files/tracks  t terrain  r reverse  q quit", Style::default().add_modifier(Modifier::DIM)), help_area);
+    }
+}
@@ -0,0 +1,120 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+
+const KM_PER_MILE: f64 = 1.609344;
+const FEET_PER_METER: f64 = 3.280839895;
+
+#[derive(Deserialize, Default, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum Units {
+    #[default]
+    Metric,
+    Imperial
+}
+
+// Analysis is done in km and m, values are only converted for display and prompts.
+impl Units {
+    pub fn distance(self, km: f64) -> f64 {
+        match self {
+            Self::Metric => km,
+            Self::Imperial => km / KM_PER_MILE
+        }
+    }
+
+    pub fn to_km(self, distance: f64) -> f64 {
+        match self {
+            Self::Metric => distance,
+            Self::Imperial => distance * KM_PER_MILE
+        }
+    }
+
+    pub fn distance_unit(self) -> &'static str {
+        match self {
+            Self::Metric => "km",
+            Self::Imperial => "mi"
+        }
+    }
+
+    pub fn speed_unit(self) -> &'static str {
+        match self {
+            Self::Metric => "km/h",
+            Self::Imperial => "mph"
+        }
+    }
+
+    pub fn elevation(self, m: f64) -> f64 {
+        match self {
+            Self::Metric => m,
+            Self::Imperial => m * FEET_PER_METER
+        }
+    }
+
+    pub fn to_m(self, elevation: f64) -> f64 {
+        match self {
+            Self::Metric => elevation,
+            Self::Imperial => elevation / FEET_PER_METER
+        }
+    }
+
+    pub fn elevation_unit(self) -> &'static str {
+        match self {
+            Self::Metric => "m",
+            Self::Imperial => "ft"
+        }
+    }
+
+    // Distance with its unit, e.g. "12.35 km".
+    pub fn format_distance(self, km: f64, decimals: usize) -> String {
+        format!("{:.decimals$} {}", self.distance(km), self.distance_unit())
+    }
+
+    // Pace from a duration per km, e.g. "12:30 /km".
+    pub fn format_pace(self, per_km: Duration) -> String {
+        let seconds = match self {
+            Self::Metric => per_km.as_secs_f64(),
+            Self::Imperial => per_km.as_secs_f64() * KM_PER_MILE
+        }.round() as u64;
+        format!("{}:{:02} /{}", seconds / 60, seconds % 60, self.distance_unit())
+    }
+
+    // Elevation with its unit, rounded to the meter or foot.
+    pub fn format_elevation(self, m: f64) -> String {
+        format!("{:.0} {}", self.elevation(m), self.elevation_unit())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions_round_trip() {
+        for units in [Units::Metric, Units::Imperial] {
+            assert!((units.to_km(units.distance(12.5)) - 12.5).abs() < 1e-9);
+            assert!((units.to_m(units.elevation(1234.)) - 1234.).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn imperial_converts_to_miles_and_feet() {
+        assert!((Units::Imperial.distance(KM_PER_MILE) - 1.).abs() < 1e-9);
+        assert!((Units::Imperial.elevation(1000.) - 3280.84).abs() < 0.01);
+        assert_eq!(Units::Metric.distance(12.5), 12.5);
+    }
+
+    #[test]
+    fn formats_distances_and_elevations_with_their_unit() {
+        assert_eq!(Units::Metric.format_distance(12.345, 2), "12.35 km");
+        assert_eq!(Units::Imperial.format_distance(16.09344, 1), "10.0 mi");
+        assert_eq!(Units::Metric.format_elevation(1234.4), "1234 m");
+        assert_eq!(Units::Imperial.format_elevation(1000.), "3281 ft");
+    }
+
+    #[test]
+    fn formats_paces_per_unit_distance() {
+        assert_eq!(Units::Metric.format_pace(Duration::from_secs(750)), "12:30 /km");
+        assert_eq!(Units::Imperial.format_pace(Duration::from_secs(600)), "16:06 /mi");
+    }
+}
@@ -1,153 +1,1412 @@
-use std::{fmt::Display, time::Duration};
+use std::{sync::LazyLock, time::Duration};
+#[cfg(feature = "cli")]
+use std::time::Instant;
 
-use anyhow::Result;
-use console::style;
+use geographiclib_rs::{Geodesic, InverseGeodesic};
 use gpx::{Track, Waypoint};
-use serde::Deserialize;
-use time::OffsetDateTime;
+#[cfg(feature = "cli")]
+use indicatif::{ProgressBar, ProgressStyle};
+#[cfg(feature = "cli")]
+use rayon::prelude::*;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use time::{OffsetDateTime, Time};
+use tracing::{debug, info};
 use vincenty_core::{self, distance_from_coords};
 
-#[derive(Deserialize)]
+use crate::pace::PacePlan;
+use crate::Terrain;
+
+static GEODESIC: LazyLock<Geodesic> = LazyLock::new(Geodesic::wgs84);
+
+#[derive(Deserialize, Serialize)]
 pub struct Splits {
-    pub splits: Vec<(i32, i32)>
+    #[serde(deserialize_with = "deserialize_splits")]
+    pub splits: Vec<Split>
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct Split {
+    pub d_plus: i32,
+    pub d_minus: i32,
+    // Length in meters, the chosen split length when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub length: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub terrain: Option<Terrain>,
+    // Speed adjustement, instead of the terrain's.
+    #[serde(default, alias = "adjustment", skip_serializing_if = "Option::is_none")]
+    pub adjustement: Option<f64>
+}
+
+impl Split {
+    pub fn length(&self, split_length: i32) -> i32 {
+        self.length.unwrap_or(split_length)
+    }
+}
+
+// Entries are either (d_plus, d_minus) tuples, the original format, or objects.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SplitEntry {
+    Tuple(i32, i32),
+    Object(Split)
 }
 
+fn deserialize_splits<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Split>, D::Error> {
+    let entries = Vec::<SplitEntry>::deserialize(deserializer)?;
+    Ok(entries.into_iter()
+        .map(|entry| match entry {
+            SplitEntry::Tuple(d_plus, d_minus) => Split { d_plus, d_minus, length: None, terrain: None, adjustement: None },
+            SplitEntry::Object(split) => split
+        })
+        .collect())
+}
+
+#[derive(Serialize, Default)]
 pub struct PathStats {
     pub distance: f64,
     pub d_plus: f64,
     pub d_minus: f64,
+    #[serde(serialize_with = "serialize_seconds")]
     pub duration: Duration,
+    #[serde(serialize_with = "serialize_seconds")]
+    pub break_duration: Duration,
+    // Walking time split by the legs' direction, flat legs being in neither.
+    #[serde(serialize_with = "serialize_seconds")]
+    pub uphill_duration: Duration,
+    #[serde(serialize_with = "serialize_seconds")]
+    pub downhill_duration: Duration,
+    // Time per flat-equivalent km, comparable between hilly and flat routes.
+    #[serde(serialize_with = "serialize_seconds")]
+    pub grade_adjusted_pace: Duration,
+    // Time added by the night penalty.
+    #[serde(serialize_with = "serialize_seconds")]
+    pub night_delay: Duration,
+    // Time added by the heat penalty.
+    #[serde(serialize_with = "serialize_seconds")]
+    pub heat_delay: Duration,
     pub min_height: f64,
     pub max_height: f64,
-    pub average_altitude: f64
+    pub average_altitude: f64,
+    pub median_altitude: f64,
+    pub lower_quartile_altitude: f64,
+    pub upper_quartile_altitude: f64,
+    // Estimated energy expenditure in kcal.
+    pub energy: Option<f64>,
+    pub segments: Vec<SegmentStats>,
+    pub tracks: Vec<SegmentStats>,
+    pub splits: Vec<SplitStats>,
+    pub checkpoints: Vec<Checkpoint>,
+    // Times of the original recording when the GPX was timestamped.
+    pub recorded: Option<RecordedTimes>,
+    pub grade_distribution: Vec<GradeBucket>,
+    pub grade_zones: GradeZones,
+    // Vertical meters per hour over the climbing zone.
+    pub ascent_rate: Option<f64>,
+    pub best_vam: Option<Vam>,
+    pub steepest_climb: Option<SteepSection>,
+    pub steepest_descent: Option<SteepSection>,
+    pub climbs: Vec<Climb>,
+    pub stages: Vec<Stage>,
+    pub hourly: Vec<HourlyPosition>,
+    pub bail_outs: Vec<BailOut>,
+    pub shape: Option<RouteShape>,
+    pub effort: f64,
+    // SAC hiking scale grade, 1 to 6, guessed from the slopes and altitude.
+    pub sac_estimate: Option<u8>,
+    // Track points without <ele>, walked as flat unless interpolated.
+    pub missing_elevations: usize,
+    #[serde(skip)]
+    pub points: Vec<PointStats>
+}
+
+// One entry per track point, `duration` being the time to walk from the previous point.
+#[derive(Clone)]
+pub struct PointStats {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub elevation: Option<f64>,
+    pub distance: f64,
+    pub d_plus: f64,
+    pub d_minus: f64,
+    pub grade: f64,
+    pub duration: Duration,
+    pub total_duration: Duration,
+    // Walking time plus breaks since the start.
+    pub elapsed: Duration
+}
+
+#[derive(Serialize, Default)]
+pub struct SegmentStats {
+    pub points: usize,
+    pub distance: f64,
+    pub d_plus: f64,
+    pub d_minus: f64,
+    #[serde(serialize_with = "serialize_seconds")]
+    pub duration: Duration
 }
 
-impl Display for PathStats {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{} meters - {}m D+ - {}m D-", self.distance, self.d_plus, self.d_minus)
+#[derive(Serialize)]
+pub struct SplitStats {
+    pub distance: f64,
+    pub d_plus: f64,
+    pub d_minus: f64,
+    #[serde(serialize_with = "serialize_seconds")]
+    pub duration: Duration,
+    #[serde(serialize_with = "serialize_seconds")]
+    pub total_duration: Duration,
+    // Per flat-equivalent km.
+    #[serde(serialize_with = "serialize_seconds")]
+    pub grade_adjusted_pace: Duration
+}
+
+// Named waypoint matched to its closest track point, `offset` being how far (m) it lies from the track.
+#[derive(Serialize)]
+pub struct Checkpoint {
+    pub name: String,
+    pub distance: f64,
+    pub d_plus: f64,
+    pub offset: f64,
+    #[serde(serialize_with = "serialize_seconds")]
+    pub eta: Duration
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RouteShape {
+    Loop,
+    OutAndBack,
+    PointToPoint
+}
+
+impl RouteShape {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Loop => "loop",
+            Self::OutAndBack => "out-and-back",
+            Self::PointToPoint => "point-to-point"
+        }
     }
 }
 
-impl Default for PathStats {
-    fn default() -> Self {
-        Self { distance: Default::default(), d_plus: Default::default(), d_minus: Default::default(), duration: Default::default(), min_height: Default::default(), max_height: Default::default(), average_altitude: Default::default() }
+// Leaving the track at a checkpoint: `descent` (m) is what is left to go down to the finish's
+// altitude, `time_saved` the time to the finish minus the escape's.
+#[derive(Serialize)]
+pub struct BailOut {
+    pub name: String,
+    pub distance: f64,
+    #[serde(serialize_with = "serialize_seconds")]
+    pub eta: Duration,
+    pub descent: f64,
+    #[serde(serialize_with = "serialize_seconds")]
+    pub escape_duration: Duration,
+    #[serde(serialize_with = "serialize_seconds")]
+    pub time_saved: Duration
+}
+
+// Distance (km) walked on grades between `from` and `to` percent, open-ended when None.
+#[derive(Serialize)]
+pub struct GradeBucket {
+    pub from: Option<f64>,
+    pub to: Option<f64>,
+    pub distance: f64
+}
+
+// Walking time by grade zone, flat being between the two zone boundaries.
+#[derive(Serialize, Default)]
+pub struct GradeZones {
+    #[serde(serialize_with = "serialize_seconds")]
+    pub climbing: Duration,
+    #[serde(serialize_with = "serialize_seconds")]
+    pub flat: Duration,
+    #[serde(serialize_with = "serialize_seconds")]
+    pub descending: Duration
+}
+
+// Fastest ascent rate (m/h) held over a stretch of at least the VAM window.
+#[derive(Serialize, Clone, Copy)]
+pub struct Vam {
+    pub from_km: f64,
+    pub to_km: f64,
+    pub rate: f64
+}
+
+// Stretch of the track, `grade` being its average in percent.
+#[derive(Serialize, Clone, Copy)]
+pub struct SteepSection {
+    pub from_km: f64,
+    pub to_km: f64,
+    pub from_elevation: f64,
+    pub to_elevation: f64,
+    pub grade: f64
+}
+
+// Part of the track ending at a named point, `points` being the indices of its first and last points.
+#[derive(Serialize)]
+pub struct Stage {
+    pub name: String,
+    pub from_km: f64,
+    #[serde(flatten)]
+    pub stats: SplitStats,
+    #[serde(skip)]
+    pub points: (usize, usize)
+}
+
+#[derive(Serialize)]
+pub struct Climb {
+    pub from_km: f64,
+    pub to_km: f64,
+    pub gain: f64,
+    pub grade: f64,
+    #[serde(serialize_with = "serialize_seconds")]
+    pub duration: Duration,
+    pub category: Option<&'static str>
+}
+
+// Where the hiker should be after `hour` hours (breaks included), `checkpoint_km` being how far
+// along the track the closest checkpoint lies from there, negative when already passed.
+#[derive(Serialize)]
+pub struct HourlyPosition {
+    pub hour: u64,
+    pub distance: f64,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub elevation: Option<f64>,
+    pub checkpoint: Option<String>,
+    pub checkpoint_km: Option<f64>
+}
+
+const GRADE_BOUNDS: [f64; 7] = [-20., -10., -5., 0., 5., 10., 20.];
+
+#[derive(Serialize, Clone, Copy)]
+pub struct RecordedTimes {
+    #[serde(serialize_with = "serialize_seconds")]
+    pub elapsed: Duration,
+    #[serde(serialize_with = "serialize_seconds")]
+    pub moving: Duration
+}
+
+fn serialize_seconds<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(duration.as_secs())
+}
+
+#[derive(Clone, Default)]
+pub struct AnalysisOptions {
+    pub edit_track_times: bool,
+    // Moving average over this many points, 0 or 1 to disable.
+    pub smoothing_window: usize,
+    // Elevation changes smaller than this (m) are not counted in D+/D-.
+    pub elevation_threshold: f64,
+    pub distance_algorithm: DistanceAlgorithm,
+    pub breaks: BreakPolicy,
+    // Departure time used for GPX timestamps, now when unset.
+    pub start: Option<OffsetDateTime>,
+    pub night: Option<NightPenalty>,
+    pub heat: Option<HeatPenalty>,
+    // Fill missing elevations from the neighbouring points.
+    pub interpolate_elevations: bool,
+    // Walking time already behind at the start, the breaks carrying on from it.
+    pub walked: Duration
+}
+
+#[derive(Clone)]
+pub struct NightPenalty {
+    // Local clock times at which darkness starts and ends, wrapping over midnight.
+    pub from: Time,
+    pub to: Time,
+    // 0.3 walks 30 % slower in the dark.
+    pub slowdown: f64
+}
+
+impl NightPenalty {
+    pub fn is_dark(&self, time: OffsetDateTime) -> bool {
+        let clock = time.time();
+        if self.from > self.to {
+            clock >= self.from || clock < self.to
+        }
+        else {
+            clock >= self.from && clock < self.to
+        }
+    }
+
+    // Slows a leg walked from `time` on, when it starts in the dark.
+    pub fn apply(&self, leg_duration: Duration, time: OffsetDateTime) -> Duration {
+        if self.is_dark(time) { leg_duration.mul_f64(1. + self.slowdown) } else { leg_duration }
+    }
+}
+
+#[derive(Clone)]
+pub struct HeatPenalty {
+    // Expected temperature in °C.
+    pub temperature: f64,
+    // Temperature above which the pace suffers.
+    pub threshold: f64,
+    // 0.02 walks 2 % slower per degree above the threshold.
+    pub slowdown: f64
+}
+
+impl HeatPenalty {
+    // Climbs are walked at a higher effort and suffer twice as much.
+    pub fn apply(&self, leg_duration: Duration, grade: f64) -> Duration {
+        let degrees = (self.temperature - self.threshold).max(0.);
+        let effort = if grade > 0.05 { 2. } else { 1. };
+        leg_duration.mul_f64(1. + self.slowdown * degrees * effort)
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct BreakPolicy {
+    // Short break after every `interval` of walking.
+    pub interval: Option<Duration>,
+    pub length: Duration,
+    // One longer break once the walking time reaches `lunch_after`.
+    pub lunch_after: Option<Duration>,
+    pub lunch_length: Duration
+}
+
+impl BreakPolicy {
+    // Break time to insert when the walking time goes from `before` to `after`.
+    pub fn breaks_between(&self, before: Duration, after: Duration) -> Duration {
+        let mut breaks = Duration::ZERO;
+
+        if let Some(interval) = self.interval && interval.as_secs() > 0 {
+            let crossed = after.as_secs() / interval.as_secs() - before.as_secs() / interval.as_secs();
+            breaks += self.length * crossed as u32;
+        }
+        if let Some(lunch_after) = self.lunch_after && before < lunch_after && after >= lunch_after {
+            breaks += self.lunch_length;
+        }
+
+        breaks
     }
 }
 
+#[derive(Clone, Copy, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum DistanceAlgorithm {
+    // Accurate, falls back to Haversine for the rare point pairs where it doesn't converge.
+    #[default]
+    Vincenty,
+    // Spherical approximation, fastest.
+    Haversine,
+    // Karney's geodesic algorithm, accurate and always converges.
+    Geodesic
+}
+
+pub fn read_gpx(track: &mut Track, plan: &PacePlan, options: &AnalysisOptions) -> PathStats {
+    let segments = &mut track.segments;
+    info!("{} segments found", segments.len());
 
-pub fn read_gpx(track: &Track, speed_adjustement: f64, edit_track_times: bool) -> PathStats {
-    let segments = &track.segments;
-    println!("  {} segments found.", style(segments.len()).bold());
+    // Only long recordings take long enough to deserve a progress bar. Neither it nor the timing
+    // exist without the cli feature, Instant panicking on wasm32.
+    #[cfg(feature = "cli")]
+    let started = Instant::now();
+    #[cfg(feature = "cli")]
+    let total_points: usize = segments.iter().map(|segment| segment.points.len()).sum();
+    #[cfg(feature = "cli")]
+    let progress = if total_points >= 50_000 {
+        ProgressBar::new(total_points as u64).with_style(
+            ProgressStyle::with_template("  {bar:40.cyan/blue} {pos}/{len} points ({eta})")
+                .unwrap_or_else(|_| ProgressStyle::default_bar())
+        )
+    }
+    else {
+        ProgressBar::hidden()
+    };
 
-    let now = OffsetDateTime::now_utc();
+    let start = options.start.unwrap_or_else(OffsetDateTime::now_utc);
 
     let mut d_plus = 0.;
     let mut d_minus = 0.;
     
     let mut max_height = 0.0;
     let mut min_height = f64::MAX;
-    let mut average_altitude = 0.0;
+    // (elevation, leg length) pairs for distance-weighted altitude statistics.
+    let mut altitudes: Vec<(f64, f64)> = vec![];
     
     let mut track_length = 0.0;
 
     let mut duration: Duration = Duration::default();
+    let mut break_duration: Duration = Duration::default();
+    let mut night_delay = Duration::ZERO;
+    let mut heat_delay = Duration::ZERO;
+    let (mut uphill_duration, mut downhill_duration) = (Duration::ZERO, Duration::ZERO);
+    let mut flat_distance = 0.;
+    let mut missing_elevations = 0;
+    let mut segment_stats: Vec<SegmentStats> = vec![];
+    let mut points: Vec<PointStats> = vec![];
     
-    for segment in segments {
-        println!("  {} points.", &segment.points.len());
+    for segment in segments.iter_mut() {
+        debug!("segment of {} points", segment.points.len());
+        let segment_start = (track_length, d_plus, d_minus, duration);
+        let mut elevations = smooth_elevations(&segment.points, options.smoothing_window);
+        // Distances are the costly part and independent, the cumulative pass below stays sequential.
+        #[cfg(feature = "cli")]
+        let legs = segment.points.par_windows(2);
+        #[cfg(not(feature = "cli"))]
+        let legs = segment.points.windows(2);
+        let distances: Vec<f64> = legs
+            .map(|leg| distance(&leg[0], &leg[1], options.distance_algorithm))
+            .collect();
+        missing_elevations += segment.points.iter().filter(|point| point.elevation.is_none()).count();
+        if options.interpolate_elevations {
+            interpolate_elevations(&mut elevations, &distances);
+        }
+        let mut reference_elevation: Option<f64> = None;
 
+        if let Some(first) = segment.points.first_mut() {
+            if options.edit_track_times {
+                first.time = Some((start + duration + break_duration).into());
+            }
+            points.push(PointStats {
+                latitude: first.point().y(),
+                longitude: first.point().x(),
+                elevation: elevations[0],
+                distance: track_length,
+                d_plus,
+                d_minus,
+                grade: 0.,
+                duration: Duration::ZERO,
+                total_duration: duration,
+                elapsed: duration + break_duration
+            });
+        }
+
+        #[cfg(feature = "cli")]
+        progress.inc(1);
         for i in 1..segment.points.len() {
-            let a = &segment.points[i - 1];
+            #[cfg(feature = "cli")]
+            progress.inc(1);
             let b = &segment.points[i];
+            let mut grade = 0.;
 
-            if let Ok(distance) = distance_3d(a, b) {
-                track_length += distance;
+            let distance = distances[i - 1];
+            let pace = plan.model_at(track_length);
+            track_length += distance;
 
-                let mut delta_elevation = 0.0;
+            let mut delta_elevation = 0.0;
+            let altitude = elevations[i].unwrap_or(0.);
 
-                let a_elevation = a.elevation;
-                if let Some(b_elevation) = b.elevation && a_elevation.is_some() {
-                    let a_elevation = a_elevation.unwrap();
-                    if b_elevation > a_elevation {
-                        d_plus += b_elevation - a_elevation;
-                    }
-                    else {
-                        d_minus += a_elevation - b_elevation;
-                    }
-
-                    if max_height < b_elevation {
-                        max_height = b_elevation;
-                    }
-                    if min_height > b_elevation {
-                        min_height = b_elevation;
-                    }
-
-                    delta_elevation = b_elevation - a_elevation;
-                    average_altitude = (average_altitude + b_elevation) / 2.;
+            if let Some(b_elevation) = elevations[i] && let Some(a_elevation) = elevations[i - 1] {
+                // D+/D- only move once the elevation drifted past the threshold from the last counted point.
+                let reference = *reference_elevation.get_or_insert(a_elevation);
+                if b_elevation - reference >= options.elevation_threshold && b_elevation > reference {
+                    d_plus += b_elevation - reference;
+                    reference_elevation = Some(b_elevation);
+                }
+                else if reference - b_elevation >= options.elevation_threshold && b_elevation < reference {
+                    d_minus += reference - b_elevation;
+                    reference_elevation = Some(b_elevation);
                 }
 
-                if edit_track_times {
-                    let time = (now + duration).into();
-                    a.time = Some(time);
+                if max_height < b_elevation {
+                    max_height = b_elevation;
                 }
-                duration += slope_speed(delta_elevation, distance * 1000.0, speed_adjustement);
+                if min_height > b_elevation {
+                    min_height = b_elevation;
+                }
+
+                delta_elevation = b_elevation - a_elevation;
+                altitudes.push(((a_elevation + b_elevation) / 2., distance));
+            }
+
+            if distance > 0. {
+                grade = delta_elevation / (distance * 1000.0);
+            }
+            let mut leg_duration = pace.time_at(distance * 1000.0, delta_elevation, altitude);
+            if let Some(heat) = &options.heat {
+                let penalized = heat.apply(leg_duration, grade);
+                heat_delay += penalized - leg_duration;
+                leg_duration = penalized;
+            }
+            if let Some(night) = &options.night {
+                let penalized = night.apply(leg_duration, start + duration + break_duration);
+                night_delay += penalized - leg_duration;
+                leg_duration = penalized;
+            }
+            duration += leg_duration;
+            break_duration += options.breaks.breaks_between(options.walked + duration - leg_duration, options.walked + duration);
+            if delta_elevation > 0. {
+                uphill_duration += leg_duration;
             }
-            else {
-                println!("  {}", style(format!("failed to calculate distance between point {} and {}", i - 1, i)).red());
+            else if delta_elevation < 0. {
+                downhill_duration += leg_duration;
+            }
+            flat_distance += flat_equivalent(distance, grade);
+
+            points.push(PointStats {
+                latitude: b.point().y(),
+                longitude: b.point().x(),
+                elevation: elevations[i],
+                distance: track_length,
+                d_plus,
+                d_minus,
+                grade,
+                duration: leg_duration,
+                total_duration: duration,
+                elapsed: duration + break_duration
+            });
+
+            if options.edit_track_times {
+                segment.points[i].time = Some((start + duration + break_duration).into());
             }
         }
+
+        segment_stats.push(SegmentStats {
+            points: segment.points.len(),
+            distance: track_length - segment_start.0,
+            d_plus: d_plus - segment_start.1,
+            d_minus: d_minus - segment_start.2,
+            duration: duration - segment_start.3
+        });
     }
 
+    #[cfg(feature = "cli")]
+    progress.finish_and_clear();
+    #[cfg(feature = "cli")]
+    info!("{} points processed in {:.2?}", total_points, started.elapsed());
+
+    let total_weight: f64 = altitudes.iter().map(|(_, weight)| weight).sum();
+    let average_altitude = if total_weight > 0. {
+        altitudes.iter().map(|(elevation, weight)| elevation * weight).sum::<f64>() / total_weight
+    }
+    else {
+        0.
+    };
+    altitudes.sort_by(|a, b| a.0.total_cmp(&b.0));
+
     PathStats { 
         distance: track_length, 
         d_plus, 
         d_minus, 
         duration, 
+        break_duration,
+        uphill_duration,
+        downhill_duration,
+        grade_adjusted_pace: grade_adjusted_pace(duration, flat_distance),
+        night_delay,
+        heat_delay,
         min_height, 
         max_height,
-        average_altitude
+        average_altitude,
+        median_altitude: weighted_quantile(&altitudes, total_weight, 0.5),
+        lower_quartile_altitude: weighted_quantile(&altitudes, total_weight, 0.25),
+        upper_quartile_altitude: weighted_quantile(&altitudes, total_weight, 0.75),
+        energy: None,
+        segments: segment_stats,
+        tracks: vec![],
+        splits: vec![],
+        checkpoints: vec![],
+        missing_elevations,
+        points,
+        ..Default::default()
+    }
+}
+
+// `sorted` holds (value, weight) pairs in ascending value order.
+pub fn weighted_quantile(sorted: &[(f64, f64)], total_weight: f64, quantile: f64) -> f64 {
+    let mut cumulated = 0.;
+    for (value, weight) in sorted {
+        cumulated += weight;
+        if cumulated >= total_weight * quantile {
+            return *value;
+        }
+    }
+    sorted.last().map(|(value, _)| *value).unwrap_or(0.)
+}
+
+fn smooth_elevations(points: &[Waypoint], window: usize) -> Vec<Option<f64>> {
+    let half = window / 2;
+
+    (0..points.len())
+        .map(|i| {
+            points[i].elevation?;
+            if window <= 1 {
+                return points[i].elevation;
+            }
+
+            let neighbours: Vec<f64> = points[i.saturating_sub(half)..(i + half + 1).min(points.len())].iter()
+                .filter_map(|point| point.elevation)
+                .collect();
+            Some(neighbours.iter().sum::<f64>() / neighbours.len() as f64)
+        })
+        .collect()
+}
+
+// Linear in distance between the closest points with an elevation, the nearest one's before the
+// first and after the last. `distances` holds the length of each leg.
+fn interpolate_elevations(elevations: &mut [Option<f64>], distances: &[f64]) {
+    let mut positions = vec![0.];
+    for distance in distances {
+        positions.push(positions[positions.len() - 1] + distance);
+    }
+
+    let known: Vec<usize> = (0..elevations.len()).filter(|i| elevations[*i].is_some()).collect();
+    for i in 0..elevations.len() {
+        if elevations[i].is_some() {
+            continue;
+        }
+
+        let next = known.partition_point(|known| *known < i);
+        elevations[i] = match (next.checked_sub(1).map(|before| known[before]), known.get(next)) {
+            (Some(a), Some(&b)) if positions[b] > positions[a] => {
+                let (a_elevation, b_elevation) = (elevations[a].unwrap_or(0.), elevations[b].unwrap_or(0.));
+                Some(a_elevation + (b_elevation - a_elevation) * (positions[i] - positions[a]) / (positions[b] - positions[a]))
+            },
+            (Some(a), _) => elevations[a],
+            (None, Some(&b)) => elevations[b],
+            (None, None) => None
+        };
     }
 }
 
 pub fn stats(splits: &Splits, split_length: i32) -> PathStats {   
-    /*PathStats { 
-        distance: (splits.splits.len()) as f64 * split_length  as f64, 
-        d_plus: splits.splits.iter().fold(0., |sum, tuple| sum + tuple.0 as f64), 
-        d_minus: splits.splits.iter().fold(0., |sum, tuple| sum + tuple.1 as f64),
-        duration: Duration::default(),
-        min_height: 0.,
-        max_height: 0.
-    }*/
-    PathStats::default()
-}
-
-pub fn calculate_travel_time(splits: &Vec<(i32, i32)>, split_length: i32, formula_adjustement: f64) -> Vec<Duration> {
+    PathStats { 
+        distance: splits.splits.iter().map(|split| split.length(split_length) as f64).sum::<f64>() / 1000., 
+        d_plus: splits.splits.iter().fold(0., |sum, split| sum + split.d_plus as f64), 
+        d_minus: splits.splits.iter().fold(0., |sum, split| sum + split.d_minus as f64),
+        ..Default::default()
+    }
+}
+
+// Sums consecutive segments, `counts` giving how many segments belong to each group.
+pub fn group_segments(segments: &[SegmentStats], counts: &[usize]) -> Vec<SegmentStats> {
+    let mut segments = segments.iter();
+
+    counts.iter()
+        .map(|count| segments.by_ref().take(*count).fold(SegmentStats::default(), |sum, segment| SegmentStats {
+            points: sum.points + segment.points,
+            distance: sum.distance + segment.distance,
+            d_plus: sum.d_plus + segment.d_plus,
+            d_minus: sum.d_minus + segment.d_minus,
+            duration: sum.duration + segment.duration
+        }))
+        .collect()
+}
+
+pub fn distance_splits(points: &[PointStats], split_km: f64) -> Vec<SplitStats> {
+    let mut splits: Vec<SplitStats> = vec![];
+    let Some(first) = points.first() else {
+        return splits;
+    };
+
+    let mut start = first;
+    let mut flat_distance = 0.;
+    for leg in points.windows(2) {
+        let point = &leg[1];
+        flat_distance += flat_equivalent(point.distance - leg[0].distance, point.grade);
+        if point.distance >= first.distance + split_km * (splits.len() + 1) as f64 {
+            splits.push(split_between(start, point, flat_distance));
+            (start, flat_distance) = (point, 0.);
+        }
+    }
+
+    if let Some(last) = points.last() && last.distance > start.distance {
+        splits.push(split_between(start, last, flat_distance));
+    }
+
+    splits
+}
+
+fn split_between(start: &PointStats, end: &PointStats, flat_distance: f64) -> SplitStats {
+    let duration = end.total_duration - start.total_duration;
+    SplitStats {
+        distance: end.distance - start.distance,
+        d_plus: end.d_plus - start.d_plus,
+        d_minus: end.d_minus - start.d_minus,
+        duration,
+        total_duration: end.elapsed,
+        grade_adjusted_pace: grade_adjusted_pace(duration, flat_distance)
+    }
+}
+
+// T1 to T6 from the steepest sustained grade, one grade more above 3000 m and when over 5 % of
+// the distance is steeper than 20 %. Trail conditions aren't in a GPX file, this is only a guess.
+pub fn sac_estimate(stats: &PathStats) -> Option<u8> {
+    let steepest = [stats.steepest_climb, stats.steepest_descent].into_iter()
+        .flatten()
+        .map(|section| section.grade.abs())
+        .fold(None, |max: Option<f64>, grade| Some(max.map_or(grade, |max| max.max(grade))))?;
+
+    let mut grade = match steepest {
+        grade if grade < 15. => 1,
+        grade if grade < 25. => 2,
+        grade if grade < 35. => 3,
+        grade if grade < 45. => 4,
+        grade if grade < 60. => 5,
+        _ => 6
+    };
+    if stats.max_height > 3000. {
+        grade += 1;
+    }
+    let steep: f64 = stats.grade_distribution.iter()
+        .filter(|bucket| bucket.to.is_some_and(|to| to <= -20.) || bucket.from.is_some_and(|from| from >= 20.))
+        .map(|bucket| bucket.distance)
+        .sum();
+    if stats.distance > 0. && steep / stats.distance > 0.05 {
+        grade += 1;
+    }
+
+    Some(grade.min(6))
+}
+
+// Distance plus one km per 100 m of D+, the usual hiking difficulty yardstick.
+pub fn km_effort(stats: &PathStats) -> f64 {
+    stats.distance + stats.d_plus / 100.
+}
+
+// km-effort plus another 2 per km steeper than 20 %, up or down, the whole raised by 10 % per
+// 1000 m of average altitude above 2000 m.
+pub fn effort_score(stats: &PathStats) -> f64 {
+    let steep_km: f64 = stats.points.windows(2)
+        .filter(|pair| pair[1].grade.abs() > 0.2)
+        .map(|pair| pair[1].distance - pair[0].distance)
+        .sum();
+    let altitude = 1. + 0.1 * (stats.average_altitude - 2000.).max(0.) / 1000.;
+
+    (km_effort(stats) + 2. * steep_km) * altitude
+}
+
+// Flat kilometers as long to walk as `distance_km` at `grade`, by the exponential formula without
+// terrain adjustement (0.6 * e^(3.5 * grade) s/m).
+pub fn flat_equivalent(distance_km: f64, grade: f64) -> f64 {
+    distance_km * (3.5 * grade).exp()
+}
+
+// Flat equivalent of the whole of `points`.
+fn flat_distance(points: &[PointStats]) -> f64 {
+    points.windows(2)
+        .map(|leg| flat_equivalent(leg[1].distance - leg[0].distance, leg[1].grade))
+        .sum()
+}
+
+pub fn grade_adjusted_pace(duration: Duration, flat_km: f64) -> Duration {
+    if flat_km > 0. { duration.div_f64(flat_km) } else { Duration::ZERO }
+}
+
+// Cuts the track at each (name, km) boundary, the last stage ending at the finish.
+pub fn stages(points: &[PointStats], ends: &[(String, f64)]) -> Vec<Stage> {
+    let mut stages = vec![];
+    if points.is_empty() {
+        return stages;
+    }
+
+    let mut start = 0;
+    let finish = (String::from("Finish"), f64::MAX);
+    for (name, km) in ends.iter().chain([&finish]) {
+        let end = points.iter().position(|point| point.distance >= *km).unwrap_or(points.len() - 1);
+        if end <= start {
+            continue;
+        }
+
+        stages.push(Stage {
+            name: name.clone(),
+            from_km: points[start].distance,
+            stats: split_between(&points[start], &points[end], flat_distance(&points[start..=end])),
+            points: (start, end)
+        });
+        start = end;
+    }
+
+    stages
+}
+
+pub fn checkpoints(waypoints: &[Waypoint], points: &[PointStats]) -> Vec<Checkpoint> {
+    let mut checkpoints: Vec<Checkpoint> = waypoints.iter()
+        .filter_map(|waypoint| {
+            let name = waypoint.name.clone()?;
+            let (lat, lon) = (waypoint.point().y(), waypoint.point().x());
+
+            points.iter()
+                .map(|point| (point, approximate_distance(lat, lon, point.latitude, point.longitude)))
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(point, offset)| Checkpoint {
+                    name,
+                    distance: point.distance,
+                    d_plus: point.d_plus,
+                    offset,
+                    eta: point.elapsed
+                })
+        })
+        .collect();
+
+    checkpoints.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+    checkpoints
+}
+
+// Finishing within 200 m (or 2 % of the length) of the start makes a loop, an out-and-back when
+// the second half mostly walks back over the first one.
+pub fn route_shape(points: &[PointStats]) -> Option<RouteShape> {
+    let (first, last) = (points.first()?, points.last()?);
+    let gap = approximate_distance(first.latitude, first.longitude, last.latitude, last.longitude);
+    if gap > (last.distance * 20.).max(200.) {
+        return Some(RouteShape::PointToPoint);
+    }
+
+    let half = points.partition_point(|point| point.distance < last.distance / 2.);
+    let (outward, back) = points.split_at(half);
+    // A few hundred points of the way back are enough to tell.
+    let sampled: Vec<&PointStats> = back.iter().step_by((back.len() / 200).max(1)).collect();
+    let retraced = sampled.iter()
+        .filter(|point| outward.iter().any(|other| approximate_distance(point.latitude, point.longitude, other.latitude, other.longitude) < 50.))
+        .count();
+
+    Some(if !sampled.is_empty() && retraced as f64 >= sampled.len() as f64 * 0.8 { RouteShape::OutAndBack } else { RouteShape::Loop })
+}
+
+// The escape is walked down to the finish's altitude at `escape_grade` (e.g. 0.15), at the pace
+// planned where the track is left.
+pub fn bail_out(checkpoint: &Checkpoint, points: &[PointStats], plan: &PacePlan, escape_grade: f64) -> Option<BailOut> {
+    let point = points.iter().find(|point| point.distance >= checkpoint.distance)?;
+    let last = points.last()?;
+
+    let descent = match (point.elevation, last.elevation) {
+        (Some(elevation), Some(finish)) => (elevation - finish).max(0.),
+        _ => 0.
+    };
+    let escape_duration = if descent > 0. {
+        plan.model_at(point.distance).time_at(descent / escape_grade, -descent, point.elevation.unwrap_or(0.))
+    }
+    else {
+        Duration::ZERO
+    };
+
+    Some(BailOut {
+        name: checkpoint.name.clone(),
+        distance: checkpoint.distance,
+        eta: point.elapsed,
+        descent,
+        escape_duration,
+        time_saved: (last.elapsed - point.elapsed).saturating_sub(escape_duration)
+    })
+}
+
+// First point reached after each full hour of elapsed time.
+pub fn hourly_positions(points: &[PointStats], checkpoints: &[Checkpoint]) -> Vec<HourlyPosition> {
+    let mut positions = vec![];
+
+    for point in points {
+        let hour = positions.len() as u64 + 1;
+        if point.elapsed.as_secs() < hour * 3600 {
+            continue;
+        }
+
+        let closest = checkpoints.iter().min_by(|a, b| (a.distance - point.distance).abs().total_cmp(&(b.distance - point.distance).abs()));
+        positions.push(HourlyPosition {
+            hour,
+            distance: point.distance,
+            latitude: point.latitude,
+            longitude: point.longitude,
+            elevation: point.elevation,
+            checkpoint: closest.map(|checkpoint| checkpoint.name.clone()),
+            checkpoint_km: closest.map(|checkpoint| checkpoint.distance - point.distance)
+        });
+    }
+
+    positions
+}
+
+// Elapsed and moving time of a recorded track, legs slower than `stop_speed` (km/h) counting as stops.
+// Segment boundaries (pauses of the recording) only count toward the elapsed time.
+pub fn recorded_times(track: &Track, algorithm: DistanceAlgorithm, stop_speed: f64) -> Option<RecordedTimes> {
+    fn times(segment: &gpx::TrackSegment) -> Vec<(&Waypoint, OffsetDateTime)> {
+        segment.points.iter()
+            .filter_map(|point| point.time.map(|time| (point, OffsetDateTime::from(time))))
+            .collect()
+    }
+
+    let first = track.segments.iter().find_map(|segment| times(segment).first().map(|(_, time)| *time))?;
+    let last = track.segments.iter().rev().find_map(|segment| times(segment).last().map(|(_, time)| *time))?;
+    if last <= first {
+        return None;
+    }
+
+    let mut moving = Duration::ZERO;
+    for segment in &track.segments {
+        for leg in times(segment).windows(2) {
+            let (a, time_a) = leg[0];
+            let (b, time_b) = leg[1];
+            let seconds = (time_b - time_a).as_seconds_f64();
+            if seconds <= 0. {
+                continue;
+            }
+
+            let kilometers = distance(a, b, algorithm);
+            if kilometers / (seconds / 3600.) >= stop_speed {
+                moving += Duration::from_secs_f64(seconds);
+            }
+        }
+    }
+
+    Some(RecordedTimes {
+        elapsed: Duration::from_secs_f64((last - first).as_seconds_f64()),
+        moving
+    })
+}
+
+pub fn grade_distribution(points: &[PointStats]) -> Vec<GradeBucket> {
+    let mut buckets: Vec<GradeBucket> = (0..=GRADE_BOUNDS.len())
+        .map(|i| GradeBucket {
+            from: i.checked_sub(1).map(|i| GRADE_BOUNDS[i]),
+            to: GRADE_BOUNDS.get(i).copied(),
+            distance: 0.
+        })
+        .collect();
+
+    for pair in points.windows(2) {
+        let grade = pair[1].grade * 100.;
+        let bucket = GRADE_BOUNDS.iter().take_while(|bound| grade >= **bound).count();
+        buckets[bucket].distance += pair[1].distance - pair[0].distance;
+    }
+
+    buckets
+}
+
+// `descending_below` and `climbing_above` are grades in percent.
+pub fn grade_zones(points: &[PointStats], descending_below: f64, climbing_above: f64) -> GradeZones {
+    let mut zones = GradeZones::default();
+    for point in points.iter().skip(1) {
+        let grade = point.grade * 100.;
+        if grade > climbing_above {
+            zones.climbing += point.duration;
+        }
+        else if grade < descending_below {
+            zones.descending += point.duration;
+        }
+        else {
+            zones.flat += point.duration;
+        }
+    }
+    zones
+}
+
+// Elevation gained per hour on legs steeper than `climbing_above` (%).
+pub fn ascent_rate(points: &[PointStats], climbing_above: f64) -> Option<f64> {
+    let (gain, duration) = points.windows(2)
+        .filter(|pair| pair[1].grade * 100. > climbing_above)
+        .filter_map(|pair| Some((pair[1].elevation? - pair[0].elevation?, pair[1].duration)))
+        .fold((0., Duration::ZERO), |(gain, total), (delta, duration)| (gain + delta.max(0.), total + duration));
+
+    (!duration.is_zero()).then(|| gain / duration.as_secs_f64() * 3600.)
+}
+
+// Best ascent rate over at least `window` of walking, from the D+ count.
+pub fn best_vam(points: &[PointStats], window: Duration) -> Option<Vam> {
+    let mut best: Option<Vam> = None;
+    let mut start = 0;
+
+    for (end, point) in points.iter().enumerate() {
+        // Shortest stretch ending here that still lasts the window.
+        while start + 1 < end && point.total_duration - points[start + 1].total_duration >= window {
+            start += 1;
+        }
+        let duration = point.total_duration.saturating_sub(points[start].total_duration);
+        if duration < window || duration.is_zero() {
+            continue;
+        }
+
+        let rate = (point.d_plus - points[start].d_plus) / duration.as_secs_f64() * 3600.;
+        if best.is_none_or(|best| rate > best.rate) {
+            best = Some(Vam { from_km: points[start].distance, to_km: point.distance, rate });
+        }
+    }
+
+    best.filter(|vam| vam.rate > 0.)
+}
+
+// Steepest climb and descent averaged over at least `window_km`, to ignore short GPS noise.
+pub fn steepest_sections(points: &[PointStats], window_km: f64) -> (Option<SteepSection>, Option<SteepSection>) {
+    let elevations: Vec<(f64, f64)> = points.iter()
+        .filter_map(|point| Some((point.distance, point.elevation?)))
+        .collect();
+    let (mut climb, mut descent): (Option<SteepSection>, Option<SteepSection>) = (None, None);
+
+    let mut end = 0;
+    for start in 0..elevations.len() {
+        let (from_km, from_elevation) = elevations[start];
+        end = end.max(start);
+        while end < elevations.len() && elevations[end].0 - from_km < window_km {
+            end += 1;
+        }
+        let Some(&(to_km, to_elevation)) = elevations.get(end) else {
+            break;
+        };
+
+        let section = SteepSection {
+            from_km,
+            to_km,
+            from_elevation,
+            to_elevation,
+            grade: (to_elevation - from_elevation) / ((to_km - from_km) * 1000.) * 100.
+        };
+        if section.grade > 0. && climb.is_none_or(|climb| section.grade > climb.grade) {
+            climb = Some(section);
+        }
+        if section.grade < 0. && descent.is_none_or(|descent| section.grade < descent.grade) {
+            descent = Some(section);
+        }
+    }
+
+    (climb, descent)
+}
+
+// A climb runs from a low point to the following high point, until the track drops more than
+// 20 m below that high point. Only climbs of 30 m and 3 % or more are kept.
+pub fn climbs(points: &[PointStats]) -> Vec<Climb> {
+    let elevations: Vec<(usize, f64)> = points.iter().enumerate()
+        .filter_map(|(i, point)| Some((i, point.elevation?)))
+        .collect();
+    let Some(&first) = elevations.first() else {
+        return vec![];
+    };
+
+    let mut climbs = vec![];
+    let (mut low, mut high) = (first, first);
+    for &(i, elevation) in &elevations[1..] {
+        if elevation > high.1 {
+            high = (i, elevation);
+        }
+        else if high.1 - elevation > 20. {
+            climbs.extend(climb(points, low, high));
+            (low, high) = ((i, elevation), (i, elevation));
+        }
+
+        if elevation < low.1 {
+            (low, high) = ((i, elevation), (i, elevation));
+        }
+    }
+    climbs.extend(climb(points, low, high));
+
+    climbs
+}
+
+fn climb(points: &[PointStats], low: (usize, f64), high: (usize, f64)) -> Option<Climb> {
+    let (from_km, to_km) = (points[low.0].distance, points[high.0].distance);
+    let gain = high.1 - low.1;
+    if to_km <= from_km {
+        return None;
+    }
+
+    let grade = gain / ((to_km - from_km) * 1000.) * 100.;
+    if gain < 30. || grade < 3. {
+        return None;
+    }
+
+    Some(Climb {
+        from_km,
+        to_km,
+        gain,
+        grade,
+        duration: points[low.0 + 1..=high.0].iter().map(|point| point.duration).sum(),
+        category: climb_category((to_km - from_km) * 1000. * grade)
+    })
+}
+
+// Cycling style categories, scored as length (m) times average grade (%).
+fn climb_category(score: f64) -> Option<&'static str> {
+    match score {
+        score if score >= 80_000. => Some("HC"),
+        score if score >= 64_000. => Some("cat 1"),
+        score if score >= 32_000. => Some("cat 2"),
+        score if score >= 16_000. => Some("cat 3"),
+        score if score >= 8_000. => Some("cat 4"),
+        _ => None
+    }
+}
+
+// Equirectangular approximation in meters, good enough for nearest-point searches.
+pub fn approximate_distance(lat_a: f64, lon_a: f64, lat_b: f64, lon_b: f64) -> f64 {
+    let x = (lon_b - lon_a).to_radians() * ((lat_a + lat_b) / 2.).to_radians().cos();
+    let y = (lat_b - lat_a).to_radians();
+    (x * x + y * y).sqrt() * 6_371_000.
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let minutes = (duration.as_secs() + 30) / 60;
+    format!("{}h{:02}", minutes / 60, minutes % 60)
+}
+
+// Rough gross cost: 0.75 kcal/kg/km on the flat, plus lifting the load at 25 % muscular efficiency
+// when climbing and a third of that when descending.
+pub fn energy_expenditure(stats: &PathStats, body_weight: f64, pack_weight: f64) -> f64 {
+    let mass = body_weight + pack_weight;
+    let lifting = |elevation: f64| mass * 9.81 * elevation / 0.25 / 4184.;
+
+    mass * 0.75 * stats.distance + lifting(stats.d_plus) + lifting(stats.d_minus) / 3.
+}
+
+pub struct Provisions {
+    pub water: f64,
+    pub snacks: f64
+}
+
+// Water (L) grows by 5 % per degree above 20 °C plus half a liter per 1000 m of D+, snacks (g) by the hour.
+pub fn provisions(stats: &PathStats, water_rate: f64, snack_rate: f64, temperature: f64) -> Provisions {
+    let hours = (stats.duration + stats.break_duration).as_secs_f64() / 3600.;
+    let heat_factor = 1. + 0.05 * (temperature - 20.).max(0.);
+
+    Provisions {
+        water: hours * water_rate * heat_factor + stats.d_plus / 1000. * 0.5,
+        snacks: hours * snack_rate
+    }
+}
+
+pub fn format_clock(start: OffsetDateTime, elapsed: Duration) -> String {
+    let time = start + elapsed;
+    let days = (time.date() - start.date()).whole_days();
+    let clock = format!("{:02}:{:02}", time.hour(), time.minute());
+
+    if days > 0 {
+        format!("{clock} (+{days}d)")
+    }
+    else {
+        clock
+    }
+}
+
+pub fn format_duration_difference(duration: Duration, reference: Duration) -> String {
+    if duration >= reference {
+        format!("+{}", format_duration(duration - reference))
+    }
+    else {
+        format!("-{}", format_duration(reference - duration))
+    }
+}
+
+pub fn reversed(track: &Track) -> Track {
+    let mut reversed = track.clone();
+    reversed.segments.reverse();
+    for segment in reversed.segments.iter_mut() {
+        segment.points.reverse();
+    }
+    reversed
+}
+
+pub fn calculate_travel_time(splits: &[Split], split_length: i32, plan: &PacePlan) -> Vec<Duration> {
     let mut time_table: Vec<Duration> = vec![];
+    let mut position = 0;
     
     for split in splits {
-        time_table.push(slope_speed((split.0 - split.1) as f64, split_length as f64, formula_adjustement));
+        let pace = plan.model_at(position as f64 / 1000.);
+        time_table.push(pace.time(split.length(split_length) as f64, (split.d_plus - split.d_minus) as f64));
+        position += split.length(split_length);
     }
 
     time_table
 }
 
 
-fn slope_speed(delta_elevation: f64, distance: f64, formula_adjustement: f64) -> Duration {
-    let segment_speed = 0.6_f64 * (3.5 * (delta_elevation / distance + formula_adjustement)).exp();
-    let seconds = (segment_speed * distance).round() as u64;
-    Duration::from_secs(seconds)
-}
-
-fn distance_3d(a: &Waypoint, b: &Waypoint) -> Result<f64> {
+// Distance in km between two points, ignoring elevation.
+pub fn distance(a: &Waypoint, b: &Waypoint, algorithm: DistanceAlgorithm) -> f64 {
     let a_p = &a.point();
     let b_p = &b.point();
 
-    distance_from_coords(&a_p.0, &b_p.0)
-    
-    /*
-    let result = distance_from_coords(&a_p.0, &b_p.0)?;
-    let a_elevation = a.elevation.unwrap_or(0.);
-    let b_elevation = b.elevation.unwrap_or(0.);
-    let delta_elevation = b_elevation - a_elevation;
-
-    Ok((result.powi(2) + delta_elevation.powi(2)).sqrt())*/
-}
\ No newline at end of file
+    match algorithm {
+        DistanceAlgorithm::Vincenty => match distance_from_coords(&a_p.0, &b_p.0) {
+            Ok(distance) => distance,
+            Err(e) => {
+                debug!("vincenty failed ({e}), using haversine for ({}, {}) -> ({}, {})", a_p.y(), a_p.x(), b_p.y(), b_p.x());
+                haversine(a_p.y(), a_p.x(), b_p.y(), b_p.x())
+            }
+        },
+        DistanceAlgorithm::Haversine => haversine(a_p.y(), a_p.x(), b_p.y(), b_p.x()),
+        DistanceAlgorithm::Geodesic => {
+            let meters: f64 = GEODESIC.inverse(a_p.y(), a_p.x(), b_p.y(), b_p.x());
+            meters / 1000.
+        }
+    }
+}
+
+fn haversine(lat_a: f64, lon_a: f64, lat_b: f64, lon_b: f64) -> f64 {
+    let d_lat = (lat_b - lat_a).to_radians();
+    let d_lon = (lon_b - lon_a).to_radians();
+    let h = (d_lat / 2.).sin().powi(2) + lat_a.to_radians().cos() * lat_b.to_radians().cos() * (d_lon / 2.).sin().powi(2);
+    2. * 6371.0088 * h.sqrt().asin()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Track points from (km, elevation, seconds since the previous point) steps.
+    fn profile(steps: &[(f64, f64, u64)]) -> Vec<PointStats> {
+        let mut points: Vec<PointStats> = vec![];
+        for &(distance, elevation, seconds) in steps {
+            let duration = Duration::from_secs(seconds);
+            let point = match points.last() {
+                Some(previous) => {
+                    let delta = elevation - previous.elevation.unwrap_or(elevation);
+                    let total_duration = previous.total_duration + duration;
+                    PointStats {
+                        d_plus: previous.d_plus + delta.max(0.),
+                        d_minus: previous.d_minus - delta.min(0.),
+                        grade: delta / ((distance - previous.distance) * 1000.),
+                        total_duration,
+                        elapsed: total_duration,
+                        ..previous.clone()
+                    }
+                },
+                None => PointStats {
+                    latitude: 0.,
+                    longitude: 0.,
+                    elevation: None,
+                    distance,
+                    d_plus: 0.,
+                    d_minus: 0.,
+                    grade: 0.,
+                    duration,
+                    total_duration: duration,
+                    elapsed: duration
+                }
+            };
+            points.push(PointStats { elevation: Some(elevation), distance, duration, ..point });
+        }
+        points
+    }
+
+    #[test]
+    fn distance_splits_cut_every_split_length() {
+        let points = profile(&[(0., 100., 0), (0.5, 100., 300), (1., 150., 400), (1.5, 150., 300), (2., 100., 250), (2.5, 100., 300)]);
+        let splits = distance_splits(&points, 1.);
+
+        assert_eq!(splits.len(), 3);
+        assert_eq!(splits.iter().map(|split| split.distance).collect::<Vec<_>>(), vec![1., 1., 0.5]);
+        assert_eq!(splits[0].duration, Duration::from_secs(700));
+        assert_eq!((splits[0].d_plus, splits[1].d_minus), (50., 50.));
+        assert_eq!(splits[2].total_duration, Duration::from_secs(1550));
+        assert!(distance_splits(&[], 1.).is_empty());
+    }
+
+    #[test]
+    fn format_duration_rounds_to_the_minute() {
+        assert_eq!(format_duration(Duration::from_secs(11_100)), "3h05");
+        assert_eq!(format_duration(Duration::from_secs(89)), "0h01");
+        assert_eq!(format_duration(Duration::from_secs(3599)), "1h00");
+    }
+
+    #[test]
+    fn duration_differences_are_signed() {
+        assert_eq!(format_duration_difference(Duration::from_secs(7200), Duration::from_secs(5400)), "+0h30");
+        assert_eq!(format_duration_difference(Duration::from_secs(5400), Duration::from_secs(7200)), "-0h30");
+    }
+
+    #[test]
+    fn format_clock_counts_the_days_after_the_start() {
+        let start = OffsetDateTime::from_unix_timestamp(8 * 3600).unwrap();
+        assert_eq!(format_clock(start, Duration::from_secs(5400)), "09:30");
+        assert_eq!(format_clock(start, Duration::from_secs(26 * 3600)), "10:00 (+1d)");
+    }
+
+    #[test]
+    fn recorded_times_leave_stops_and_pauses_out_of_the_moving_time() {
+        let timed = |longitude: f64, seconds: i64| {
+            let mut waypoint = Waypoint::new(geo_types::Point::new(longitude, 0.));
+            waypoint.time = Some(OffsetDateTime::from_unix_timestamp(seconds).unwrap().into());
+            waypoint
+        };
+        let mut track = Track::new();
+        for points in [vec![timed(0., 0), timed(0.001, 60), timed(0.001, 360)], vec![timed(0.001, 960), timed(0.002, 1020)]] {
+            let mut segment = gpx::TrackSegment::new();
+            segment.points = points;
+            track.segments.push(segment);
+        }
+
+        let times = recorded_times(&track, DistanceAlgorithm::Haversine, 1.).unwrap();
+        assert_eq!(times.elapsed, Duration::from_secs(1020));
+        assert_eq!(times.moving, Duration::from_secs(120));
+        assert!(recorded_times(&Track::new(), DistanceAlgorithm::Haversine, 1.).is_none());
+    }
+
+    #[test]
+    fn grade_distribution_sums_the_distance_per_bucket() {
+        let points = profile(&[(0., 100., 0), (1., 100., 600), (2., 250., 900), (3., 0., 600), (3.5, 10., 300)]);
+        let distances: Vec<f64> = grade_distribution(&points).iter().map(|bucket| bucket.distance).collect();
+        assert_eq!(distances, vec![1., 0., 0., 0., 1.5, 0., 1., 0.]);
+    }
+
+    #[test]
+    fn steepest_sections_average_over_the_window() {
+        // The 100 m steps are steeper but shorter than the window.
+        let points = profile(&[(0., 100., 0), (1., 100., 600), (2., 400., 1800), (3., 350., 500), (3.1, 250., 100), (4., 100., 600)]);
+        let (climb, descent) = steepest_sections(&points, 1.);
+
+        let climb = climb.unwrap();
+        assert_eq!((climb.from_km, climb.to_km), (1., 2.));
+        assert!((climb.grade - 30.).abs() < 1e-9);
+        let descent = descent.unwrap();
+        assert_eq!((descent.from_km, descent.to_km), (3., 4.));
+        assert!((descent.grade + 25.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn climbs_end_when_the_track_drops_and_small_ones_are_ignored() {
+        let points = profile(&[(0., 100., 0), (1., 400., 1800), (1.5, 370., 300), (2., 380., 300), (3., 100., 600)]);
+        let climbs = climbs(&points);
+
+        assert_eq!(climbs.len(), 1);
+        assert_eq!((climbs[0].from_km, climbs[0].to_km, climbs[0].gain), (0., 1., 300.));
+        assert_eq!(climbs[0].duration, Duration::from_secs(1800));
+        assert_eq!(climbs[0].category, Some("cat 3"));
+    }
+
+    #[test]
+    fn stages_end_at_each_boundary_then_at_the_finish() {
+        let points = profile(&[(0., 100., 0), (1., 100., 600), (2., 200., 900), (3., 200., 600), (4., 100., 500)]);
+        let stages = stages(&points, &[(String::from("Start"), 0.), (String::from("Hut"), 2.)]);
+
+        let names: Vec<&str> = stages.iter().map(|stage| stage.name.as_str()).collect();
+        assert_eq!(names, vec!["Hut", "Finish"]);
+        assert_eq!((stages[1].from_km, stages[1].stats.distance), (2., 2.));
+        assert_eq!(stages[0].stats.duration, Duration::from_secs(1500));
+        assert_eq!((stages[1].stats.d_plus, stages[1].stats.d_minus), (0., 100.));
+    }
+
+    #[test]
+    fn splits_default_to_the_chosen_length() {
+        let split = |d_plus: i32, d_minus: i32, length: Option<i32>| Split { d_plus, d_minus, length, terrain: None, adjustement: None };
+        let splits = Splits { splits: vec![split(100, 0, None), split(0, 50, Some(500)), split(20, 30, None)] };
+
+        let stats = stats(&splits, 1000);
+        assert_eq!((stats.distance, stats.d_plus, stats.d_minus), (2.5, 120., 80.));
+
+        let plan = PacePlan::new(std::rc::Rc::new(crate::pace::ExponentialPace::new(0.)));
+        let times = calculate_travel_time(&splits.splits, 1000, &plan);
+        assert_eq!(times[1], plan.default.time(500., -50.));
+    }
+
+    #[test]
+    fn grade_adjusted_pace_is_per_flat_equivalent_km() {
+        assert_eq!(flat_equivalent(2., 0.), 2.);
+        assert!(flat_equivalent(1., 0.2) > 2.);
+        assert_eq!(grade_adjusted_pace(Duration::from_secs(1200), 2.), Duration::from_secs(600));
+        assert_eq!(grade_adjusted_pace(Duration::from_secs(1200), 0.), Duration::ZERO);
+
+        let points = profile(&[(0., 100., 0), (1., 100., 600)]);
+        assert_eq!(distance_splits(&points, 1.)[0].grade_adjusted_pace, Duration::from_secs(600));
+    }
+
+    #[test]
+    fn best_vam_keeps_the_fastest_climb_lasting_the_window() {
+        let points = profile(&[(0., 100., 0), (1., 300., 1800), (2., 800., 1800), (3., 800., 600)]);
+
+        let vam = best_vam(&points, Duration::from_secs(1800)).unwrap();
+        assert_eq!((vam.from_km, vam.to_km), (1., 2.));
+        assert!((vam.rate - 1000.).abs() < 1e-9);
+        assert!(best_vam(&points, Duration::from_secs(7200)).is_none());
+    }
+
+    #[test]
+    fn effort_score_weighs_steep_legs_and_altitude() {
+        let mut stats = PathStats {
+            distance: 2.,
+            d_plus: 300.,
+            points: profile(&[(0., 100., 0), (1., 100., 600), (2., 400., 1800)]),
+            ..Default::default()
+        };
+        assert_eq!(km_effort(&stats), 5.);
+        assert!((effort_score(&stats) - 7.).abs() < 1e-9);
+
+        stats.average_altitude = 3000.;
+        assert!((effort_score(&stats) - 7.7).abs() < 1e-9);
+    }
+}
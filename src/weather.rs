@@ -0,0 +1,36 @@
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+use time::{OffsetDateTime, UtcOffset};
+
+const FORECAST_URL: &str = "https://api.open-meteo.com/v1/forecast";
+
+pub struct Forecast {
+    pub temperature: f64,
+    pub precipitation: f64,
+    pub wind_speed: f64
+}
+
+impl Forecast {
+    // Slower by 2 % per degree above 25 °C, and by 10 % in the rain.
+    pub fn speed_penalty(&self) -> f64 {
+        1. + 0.02 * (self.temperature - 25.).max(0.) + if self.precipitation >= 0.5 { 0.1 } else { 0. }
+    }
+}
+
+// Hourly forecast (open-meteo) for the given place, downscaled to `elevation`.
+pub fn forecast(latitude: f64, longitude: f64, elevation: f64, time: OffsetDateTime) -> Result<Forecast> {
+    let time = time.to_offset(UtcOffset::UTC);
+    let date = format!("{}-{:02}-{:02}", time.year(), time.month() as u8, time.day());
+    let url = format!("{FORECAST_URL}?latitude={latitude:.4}&longitude={longitude:.4}&elevation={elevation:.0}\
+        &hourly=temperature_2m,precipitation,wind_speed_10m&timezone=GMT&start_date={date}&end_date={date}");
+
+    let response: Value = ureq::get(&url).call()?.into_json()?;
+    let hour = time.hour() as usize;
+    let value = |key: &str| response["hourly"][key][hour].as_f64().ok_or(anyhow!("no {key} forecast for {date}"));
+
+    Ok(Forecast {
+        temperature: value("temperature_2m")?,
+        precipitation: value("precipitation")?,
+        wind_speed: value("wind_speed_10m")?
+    })
+}